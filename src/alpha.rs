@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+#[cfg(feature = "image")]
+use std::collections::HashMap;
+#[cfg(feature = "image")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "image")]
+use std::time::SystemTime;
+
+use crate::processing;
+#[cfg(feature = "image")]
+use crate::strips;
+
+
+/// `has_alpha` of the images checked so far, by path and modification time.
+#[cfg(feature = "image")]
+static HAS_ALPHA: OnceLock<Mutex<HashMap<(PathBuf, SystemTime), bool>>> = OnceLock::new();
+
+
+/// Whether the image format carries an alpha channel, read from the header
+/// alone so opaque images aren't decoded just to find out.
+#[cfg(feature = "image")]
+fn read_has_alpha(image: &Path) -> bool {
+    use image::ImageDecoder;
+
+    let Ok(reader) = image::io::Reader::open(image).and_then(|x| x.with_guessed_format()) else {
+        return false;
+    };
+
+    match reader.format() {
+        Some(image::ImageFormat::Png) => std::fs::File::open(image)
+            .ok()
+            .and_then(|x| image::codecs::png::PngDecoder::new(x).ok())
+            .is_some_and(|x| x.color_type().has_alpha()),
+        Some(image::ImageFormat::Jpeg) | None => false,
+        // Other formats are rarely transparent, decoding them tells for sure.
+        Some(_) => reader.decode().is_ok_and(|x| x.color().has_alpha()),
+    }
+}
+
+
+/// `read_has_alpha`, remembered until the image changes, as every poll asks
+/// again and some formats are decoded to tell.
+#[cfg(feature = "image")]
+fn has_alpha(image: &Path) -> bool {
+    let Ok(modified) = std::fs::metadata(image).and_then(|x| x.modified()) else {
+        return read_has_alpha(image);
+    };
+
+    let key = (image.to_path_buf(), modified);
+    let memo = HAS_ALPHA.get_or_init(Mutex::default);
+
+    if let Some(has_alpha) = memo.lock().ok().and_then(|x| x.get(&key).copied()) {
+        return has_alpha;
+    }
+
+    let has_alpha = read_has_alpha(image);
+
+    if let Ok(mut memo) = memo.lock() {
+        memo.insert(key, has_alpha);
+    }
+
+    has_alpha
+}
+
+
+#[cfg(feature = "image")]
+fn flatten(
+    image: &Path,
+    background: [u8; 3],
+    strip_rows: u32,
+    output: &Path
+) -> Result<(), String> {
+    let flatten_pixel = |pixel: &image::Rgba<u8>| {
+        let alpha = pixel[3] as u32;
+
+        [0, 1, 2].map(|channel| {
+            ((pixel[channel] as u32 * alpha + background[channel] as u32 * (255 - alpha) + 127) / 255) as u8
+        })
+    };
+
+    if strip_rows > 0 {
+        return strips::map(image, output, strip_rows, 0, true, |mut strip| {
+            for pixel in strip.pixels_mut() {
+                let [red, green, blue] = flatten_pixel(pixel);
+                *pixel = image::Rgba([red, green, blue, 255]);
+            }
+
+            strip
+        });
+    }
+
+    let source = image::open(image)
+        .map_err(|e| format!("Unable to open image {}: {e}", image.display()))?
+        .to_rgba8();
+
+    let flattened = image::RgbImage::from_fn(source.width(), source.height(), |x, y| {
+        image::Rgb(flatten_pixel(source.get_pixel(x, y)))
+    });
+
+    flattened
+        .save_with_format(output, image::ImageFormat::Png)
+        .map_err(|e| format!("Unable to save flattened image: {e}"))
+}
+
+
+#[cfg(not(feature = "image"))]
+fn has_alpha(_image: &Path) -> bool {
+    false
+}
+
+
+#[cfg(not(feature = "image"))]
+fn flatten(
+    _image: &Path,
+    _background: [u8; 3],
+    _strip_rows: u32,
+    _output: &Path
+) -> Result<(), String> {
+    Err("Flattening transparent images requires the `image` feature.".to_string())
+}
+
+
+/// `image` composited over `background`, for images with an alpha channel
+/// that backends would otherwise show over whatever they like. Opaque images
+/// are used as they are.
+///
+/// Flattened copies are cached like gamma corrected ones, under the image,
+/// its modification time and the background, and are made `strip_rows` at a
+/// time the same way.
+pub fn flattened_image(
+    image: &Path,
+    background: &str,
+    strip_rows: u32,
+    alpha_dir: &Path
+) -> PathBuf {
+    if cfg!(not(feature = "image")) || !image.is_file() || !has_alpha(image) {
+        return image.to_path_buf();
+    }
+
+    let background = match crate::solid_color::parse_hex_color(background) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("alpha_background: {e}");
+            return image.to_path_buf();
+        }
+    };
+
+    processing::cached_output(image, &format!("{background:?}"), alpha_dir, |output| {
+        flatten(image, background, strip_rows, output)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "image")]
+    #[test]
+    fn transparent_images_are_flattened_over_the_background() {
+        use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+        use super::*;
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_alpha_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let transparent = dir.join("dusk.png");
+        RgbaImage::from_pixel(4, 3, Rgba([200, 100, 0, 128])).save(&transparent).unwrap();
+        let opaque = dir.join("day.png");
+        RgbImage::from_pixel(4, 3, Rgb([200, 100, 0])).save(&opaque).unwrap();
+
+        // Opaque images are never flattened.
+        assert!(!has_alpha(&opaque));
+        assert_eq!(flattened_image(&opaque, "#000080", 0, &dir.join("alpha")), opaque);
+
+        assert!(has_alpha(&transparent));
+        let flattened = flattened_image(&transparent, "#000080", 0, &dir.join("alpha"));
+        assert_ne!(flattened, transparent);
+
+        // Half of each channel, half of the background's blue.
+        let flattened = image::open(flattened).unwrap();
+        assert!(!flattened.color().has_alpha());
+        assert_eq!(flattened.to_rgb8().get_pixel(0, 0), &Rgb([100, 50, 64]));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
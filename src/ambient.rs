@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+
+/// Biases the pick within the current phase towards brighter images in a
+/// bright room and darker ones in a dark room, as measured by an ambient
+/// light sensor.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AmbientLight {
+    /// File holding the illuminance, e.g. an IIO sensor's
+    /// `in_illuminance_input`. For `in_illuminance_raw` the sibling
+    /// `in_illuminance_scale` is applied when present.
+    pub sensor_path: String,
+    /// At or below this many lux the darkest nearby image is preferred.
+    pub dark_lux: f64,
+    /// At or above this many lux the brightest nearby image is preferred.
+    pub bright_lux: f64,
+    /// How many slots away from the scheduled image the pick may move,
+    /// staying within the phase.
+    pub max_shift: usize,
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        Self {
+            sensor_path: "/sys/bus/iio/devices/iio:device0/in_illuminance_input".to_string(),
+            dark_lux: 10.0,
+            bright_lux: 1000.0,
+            max_shift: 1,
+        }
+    }
+}
+
+
+/// How bright an image should be at `lux`, from 0.0 (darkest) to 1.0
+/// (brightest). Perceived brightness follows the logarithm of the light,
+/// so the range between `dark_lux` and `bright_lux` is mapped on a log scale.
+pub fn preference(
+    lux: f64,
+    dark_lux: f64,
+    bright_lux: f64
+) -> f64 {
+    let (dark, bright) = (dark_lux.max(0.01).ln(), bright_lux.max(0.01).ln());
+
+    if bright <= dark {
+        return if lux.max(0.01).ln() < bright { 0.0 } else { 1.0 };
+    }
+
+    ((lux.max(0.01).ln() - dark) / (bright - dark)).clamp(0.0, 1.0)
+}
+
+
+#[cfg(feature = "ambient-light")]
+fn read_lux(sensor_path: &Path) -> Result<f64, String> {
+    let read = |path: &Path| -> Result<f64, String> {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {e}", path.display()))?
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("Unable to parse {}: {e}", path.display()))
+    };
+
+    let value = read(sensor_path)?;
+
+    let scale_path = sensor_path.with_file_name("in_illuminance_scale");
+    let raw = sensor_path.file_name().is_some_and(|x| x.to_string_lossy().ends_with("_raw"));
+
+    match raw && scale_path.is_file() {
+        true => Ok(value * read(&scale_path)?),
+        false => Ok(value),
+    }
+}
+
+
+#[cfg(not(feature = "ambient-light"))]
+fn read_lux(_sensor_path: &Path) -> Result<f64, String> {
+    Err("Ambient light input requires the `ambient-light` feature.".to_string())
+}
+
+
+/// Mean luma of `image` from 0.0 to 1.0, measured on a thumbnail.
+#[cfg(feature = "ambient-light")]
+fn measure_brightness(image: &Path) -> Option<f64> {
+    let thumbnail = image::open(image).ok()?.thumbnail(64, 64).to_luma8();
+    let pixels = thumbnail.pixels().len().max(1) as f64;
+
+    Some(thumbnail.pixels().map(|x| x[0] as f64).sum::<f64>() / pixels / 255.0)
+}
+
+
+#[cfg(not(feature = "ambient-light"))]
+fn measure_brightness(_image: &Path) -> Option<f64> {
+    None
+}
+
+
+/// Picks images by the ambient light, remembering how bright each image is.
+#[derive(Debug, Default)]
+pub struct AmbientSelector {
+    brightness: HashMap<PathBuf, Option<f64>>,
+    /// The last sensor error, reported once until it changes.
+    last_error: Option<String>,
+}
+
+impl AmbientSelector {
+    /// The slot to show instead of `index`: of the slots of its phase within
+    /// `max_shift` of it, the one whose image is closest to the brightness the
+    /// room asks for. Without a sensor reading, or when the images can't be
+    /// measured, `index` is kept.
+    pub fn select(
+        &mut self,
+        ambient_light: &AmbientLight,
+        index: usize,
+        phase_slots: Range<usize>,
+        image_at: impl Fn(usize) -> Option<PathBuf>
+    ) -> usize {
+        let lux = match read_lux(Path::new(&ambient_light.sensor_path)) {
+            Ok(x) => {
+                self.last_error = None;
+                x
+            }
+            Err(e) => {
+                if self.last_error.as_ref() != Some(&e) {
+                    eprintln!("{e}\nFalling back to the astronomical schedule.");
+                    self.last_error = Some(e);
+                }
+                return index;
+            }
+        };
+
+        let first = index.saturating_sub(ambient_light.max_shift).max(phase_slots.start);
+        let last = (index + ambient_light.max_shift + 1).min(phase_slots.end);
+
+        let candidates = (first..last)
+            .filter_map(|x| {
+                let image = image_at(x)?;
+                let brightness = *self
+                    .brightness
+                    .entry(image.clone())
+                    .or_insert_with(|| measure_brightness(&image));
+
+                brightness.map(|brightness| (x, brightness))
+            })
+            .collect::<Vec<(usize, f64)>>();
+
+        let (darkest, brightest) = candidates.iter().fold((f64::MAX, f64::MIN), |(low, high), (_, x)| {
+            (low.min(*x), high.max(*x))
+        });
+
+        if candidates.len() < 2 || brightest <= darkest {
+            return index;
+        }
+
+        let target = darkest + (brightest - darkest) * preference(lux, ambient_light.dark_lux, ambient_light.bright_lux);
+
+        // Ties go to the scheduled image, then to the earlier one.
+        candidates
+            .into_iter()
+            .min_by(|(a, a_brightness), (b, b_brightness)| {
+                ((a_brightness - target).abs(), *a != index)
+                    .partial_cmp(&((b_brightness - target).abs(), *b != index))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(x, _)| x)
+            .unwrap_or(index)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn lux_maps_to_a_preference_on_a_log_scale() {
+        assert_eq!(preference(0.0, 10.0, 1000.0), 0.0);
+        assert_eq!(preference(10.0, 10.0, 1000.0), 0.0);
+        assert!((preference(100.0, 10.0, 1000.0) - 0.5).abs() < 1e-9);
+        assert_eq!(preference(1000.0, 10.0, 1000.0), 1.0);
+        assert_eq!(preference(100_000.0, 10.0, 1000.0), 1.0);
+
+        let preferences = [1.0, 20.0, 50.0, 300.0, 800.0].map(|x| preference(x, 10.0, 1000.0));
+        assert!(preferences.windows(2).all(|x| x[0] <= x[1]), "{preferences:?}");
+
+        // Without a range in between it's one or the other, split at `bright_lux`.
+        assert_eq!(preference(99.0, 100.0, 100.0), 0.0);
+        assert_eq!(preference(100.0, 100.0, 100.0), 1.0);
+        assert_eq!(preference(5.0, 1000.0, 10.0), 0.0);
+        assert_eq!(preference(500.0, 1000.0, 10.0), 1.0);
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+use crate::SunAndMoonKeys;
+
+
+const KEY_NAMES: [(&str, SunAndMoonKeys); 7] = [
+    ("midnight", SunAndMoonKeys::Midnight),
+    ("moonset", SunAndMoonKeys::Moonset),
+    ("sunrise", SunAndMoonKeys::Sunrise),
+    ("noon", SunAndMoonKeys::Noon),
+    ("sunset", SunAndMoonKeys::Sunset),
+    ("moonrise", SunAndMoonKeys::Moonrise),
+    ("next_day_midnight", SunAndMoonKeys::NextDayMidnight),
+];
+
+
+/// A point in the day relative to a sun or moon event, written as
+/// `sunset - 60m`, `sunrise + 1h`, `noon + 90s` or just `noon`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct AnchorTime {
+    pub(crate) key: SunAndMoonKeys,
+    pub(crate) offset_seconds: i64,
+}
+
+
+fn parse_offset(offset: &str) -> Option<i64> {
+    let (sign, amount) = match offset.split_at(1) {
+        ("+", amount) => (1, amount),
+        ("-", amount) => (-1, amount),
+        _ => return None,
+    };
+    let unit = amount.chars().last()?;
+    let amount = &amount[..amount.len() - unit.len_utf8()];
+
+    let unit_seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        _ => return None,
+    };
+
+    // Digits only, `parse` would take a second sign as in `+-5m`.
+    if !amount.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+
+    i64::try_from(amount.parse::<u64>().ok()?)
+        .ok()?
+        .checked_mul(unit_seconds)
+        .map(|x| sign * x)
+}
+
+
+impl TryFrom<String> for AnchorTime {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid anchor '{value}', expected e.g. 'sunset - 60m'.");
+
+        let compact = value.split_whitespace().collect::<String>().to_lowercase();
+        let (name, offset) = compact.split_at(compact.find(['+', '-']).unwrap_or(compact.len()));
+
+        let key = KEY_NAMES
+            .iter()
+            .find(|(x, _)| *x == name)
+            .map(|(_, x)| *x)
+            .ok_or_else(invalid)?;
+
+        let offset_seconds = match offset {
+            "" => 0,
+            _ => parse_offset(offset).ok_or_else(invalid)?,
+        };
+
+        Ok(Self { key, offset_seconds })
+    }
+}
+
+
+impl From<AnchorTime> for String {
+    fn from(value: AnchorTime) -> Self {
+        let name = KEY_NAMES
+            .iter()
+            .find(|(_, x)| *x == value.key)
+            .map(|(x, _)| *x)
+            .unwrap_or_default();
+
+        let sign = if value.offset_seconds < 0 { "-" } else { "+" };
+        let seconds = value.offset_seconds.abs();
+
+        match seconds {
+            0 => name.to_string(),
+            _ if seconds % 3600 == 0 => format!("{name} {sign} {}h", seconds / 3600),
+            _ if seconds % 60 == 0 => format!("{name} {sign} {}m", seconds / 60),
+            _ => format!("{name} {sign} {seconds}s"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn offsets_are_parsed_with_their_sign_and_unit() {
+        assert_eq!(parse_offset("+90s"), Some(90));
+        assert_eq!(parse_offset("-60m"), Some(-3600));
+        assert_eq!(parse_offset("+1h"), Some(3600));
+        assert_eq!(parse_offset("+1d"), None);
+        assert_eq!(parse_offset("+m"), None);
+
+        let anchor = AnchorTime::try_from("Sunset - 60m".to_string()).unwrap();
+        assert_eq!((anchor.key, anchor.offset_seconds), (SunAndMoonKeys::Sunset, -3600));
+        assert_eq!(String::from(anchor), "sunset - 1h");
+    }
+
+
+    #[test]
+    fn a_second_sign_is_rejected() {
+        assert_eq!(parse_offset("+-5m"), None);
+        assert_eq!(parse_offset("-+5m"), None);
+        assert!(AnchorTime::try_from("sunset +-5m".to_string()).is_err());
+    }
+
+
+    #[test]
+    fn offsets_too_large_to_represent_are_rejected() {
+        assert_eq!(parse_offset("+9999999999999999h"), None);
+        assert_eq!(parse_offset("-99999999999999999999s"), None);
+        assert!(AnchorTime::try_from("sunset +9999999999999999h".to_string()).is_err());
+    }
+}
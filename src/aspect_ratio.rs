@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+
+/// How far apart two ratios may be and still count as the same, wide enough
+/// for 2560x1080 to match `21:9`.
+const RATIO_TOLERANCE: f64 = 0.05;
+
+
+/// Parses `21:9` style ratios as well as plain numbers like `2.35`.
+fn parse_ratio(ratio: &str) -> Option<f64> {
+    let value = match ratio.split_once(':') {
+        Some((width, height)) => width.trim().parse::<f64>().ok()? / height.trim().parse::<f64>().ok()?,
+        None => ratio.trim().parse::<f64>().ok()?,
+    };
+
+    (value.is_finite() && value > 0.0).then_some(value)
+}
+
+
+/// Picks the variant whose aspect ratio is closest to the one of `size`,
+/// or `None` when no variant is close enough.
+pub fn select_variant(
+    variants: &BTreeMap<String, String>,
+    size: (u32, u32)
+) -> Option<&String> {
+    if size.1 == 0 {
+        return None;
+    }
+
+    let target = size.0 as f64 / size.1 as f64;
+
+    variants
+        .iter()
+        .filter_map(|(ratio, variant)| match parse_ratio(ratio) {
+            Some(x) => Some(((x - target).abs(), variant)),
+            None => {
+                eprintln!("Invalid aspect ratio '{ratio}', expected e.g. '21:9'.");
+                None
+            }
+        })
+        .filter(|(difference, _)| *difference <= RATIO_TOLERANCE)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, variant)| variant)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn variants() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("16:10".to_string(), "wide".to_string()),
+            ("21:9".to_string(), "ultrawide".to_string()),
+            ("2.35".to_string(), "cinema".to_string()),
+        ])
+    }
+
+
+    #[test]
+    fn parses_ratios_and_plain_numbers() {
+        assert_eq!(parse_ratio("16:10"), Some(1.6));
+        assert_eq!(parse_ratio(" 4 : 2 "), Some(2.0));
+        assert_eq!(parse_ratio("2.35"), Some(2.35));
+        assert_eq!(parse_ratio("16:0"), None);
+        assert_eq!(parse_ratio("-1"), None);
+        assert_eq!(parse_ratio("wide"), None);
+    }
+
+
+    #[test]
+    fn picks_the_closest_variant_within_the_tolerance() {
+        let variants = variants();
+
+        assert_eq!(select_variant(&variants, (1920, 1200)).map(String::as_str), Some("wide"));
+        assert_eq!(select_variant(&variants, (2520, 1080)).map(String::as_str), Some("ultrawide"));
+        // 2560x1080 is within the tolerance of both, the closer one wins.
+        assert_eq!(select_variant(&variants, (2560, 1080)).map(String::as_str), Some("cinema"));
+        // 16:9 is too far from every variant, the default images are used.
+        assert_eq!(select_variant(&variants, (1920, 1080)), None);
+        assert_eq!(select_variant(&variants, (1920, 0)), None);
+    }
+}
@@ -0,0 +1,124 @@
+use geodate::{moon_transit, sun_transit};
+
+
+/// Degrees of latitude around a pole where the sun circles the horizon and
+/// rises and sets at most once a year, so schedules follow the clock instead.
+pub const POLE_EPSILON: f64 = 0.01;
+
+
+pub fn near_pole(latitude: f64) -> bool {
+    latitude.abs() >= 90.0 - POLE_EPSILON
+}
+
+
+/// Source of the sun and moon event times a day's schedule is built from.
+/// Every method takes the POSIX timestamp of the start of the day.
+pub trait AstronomyProvider {
+    fn sunrise(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64>;
+    fn sunset(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64>;
+    fn noon(&self, day_posix: i64, longitude: f64) -> i64;
+    /// Solar midnight, when the sun is lowest.
+    fn midnight(&self, day_posix: i64, longitude: f64) -> i64;
+    fn moonrise(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64>;
+    fn moonset(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64>;
+}
+
+
+/// The actual sky, computed by `geodate`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Geodate;
+
+impl AstronomyProvider for Geodate {
+    fn sunrise(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64> {
+        sun_transit::get_sunrise(day_posix, longitude, latitude)
+    }
+
+    fn sunset(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64> {
+        sun_transit::get_sunset(day_posix, longitude, latitude)
+    }
+
+    fn noon(&self, day_posix: i64, longitude: f64) -> i64 {
+        sun_transit::get_noon(day_posix, longitude)
+    }
+
+    fn midnight(&self, day_posix: i64, longitude: f64) -> i64 {
+        sun_transit::get_midnight(day_posix, longitude)
+    }
+
+    fn moonrise(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64> {
+        moon_transit::get_moonrise(day_posix, longitude, latitude)
+    }
+
+    fn moonset(&self, day_posix: i64, longitude: f64, latitude: f64) -> Option<i64> {
+        moon_transit::get_moonset(day_posix, longitude, latitude)
+    }
+}
+
+
+/// Events at fixed offsets in seconds from the start of every day, regardless
+/// of the location, so a schedule can be checked without depending on the
+/// exact values `geodate` computes. `None` stands for an event that doesn't
+/// happen, like the sunrise during a polar night.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedAstronomy {
+    pub sunrise: Option<i64>,
+    pub sunset: Option<i64>,
+    pub noon: i64,
+    pub midnight: i64,
+    pub moonrise: Option<i64>,
+    pub moonset: Option<i64>,
+}
+
+impl Default for FixedAstronomy {
+    fn default() -> Self {
+        Self {
+            sunrise: Some(6 * 3600),
+            sunset: Some(18 * 3600),
+            noon: 12 * 3600,
+            midnight: 0,
+            moonrise: Some(20 * 3600),
+            moonset: Some(4 * 3600),
+        }
+    }
+}
+
+impl FixedAstronomy {
+    /// The same events `seconds` later, e.g. counted from local instead of
+    /// UTC midnight.
+    pub fn shifted(self, seconds: i64) -> Self {
+        Self {
+            sunrise: self.sunrise.map(|x| x + seconds),
+            sunset: self.sunset.map(|x| x + seconds),
+            noon: self.noon + seconds,
+            midnight: self.midnight + seconds,
+            moonrise: self.moonrise.map(|x| x + seconds),
+            moonset: self.moonset.map(|x| x + seconds),
+        }
+    }
+}
+
+impl AstronomyProvider for FixedAstronomy {
+    fn sunrise(&self, day_posix: i64, _longitude: f64, _latitude: f64) -> Option<i64> {
+        self.sunrise.map(|x| day_posix + x)
+    }
+
+    fn sunset(&self, day_posix: i64, _longitude: f64, _latitude: f64) -> Option<i64> {
+        self.sunset.map(|x| day_posix + x)
+    }
+
+    fn noon(&self, day_posix: i64, _longitude: f64) -> i64 {
+        day_posix + self.noon
+    }
+
+    fn midnight(&self, day_posix: i64, _longitude: f64) -> i64 {
+        day_posix + self.midnight
+    }
+
+    fn moonrise(&self, day_posix: i64, _longitude: f64, _latitude: f64) -> Option<i64> {
+        self.moonrise.map(|x| day_posix + x)
+    }
+
+    fn moonset(&self, day_posix: i64, _longitude: f64, _latitude: f64) -> Option<i64> {
+        self.moonset.map(|x| day_posix + x)
+    }
+}
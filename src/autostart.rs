@@ -0,0 +1,104 @@
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+#[cfg(target_os = "linux")]
+use directories::BaseDirs;
+
+
+#[cfg(not(target_os = "linux"))]
+const GUIDANCE: &str = "Autostart entries are only written on Linux. On Windows put a shortcut to the \
+    executable into the Startup folder (Win+R, `shell:startup`), on macOS add it under System Settings, \
+    General, Login Items.";
+
+
+/// `~/.config/autostart/<app_name>.desktop`, following `XDG_CONFIG_HOME`.
+#[cfg(target_os = "linux")]
+fn entry_path(app_name: &str) -> Result<PathBuf, String> {
+    let base_dirs = BaseDirs::new().ok_or("Unable to find the home directory.")?;
+
+    Ok(base_dirs.config_dir().join("autostart").join(format!("{app_name}.desktop")))
+}
+
+
+/// Quotes `argument` for a desktop entry's `Exec` key when it needs it.
+#[cfg(target_os = "linux")]
+fn quote_exec_argument(argument: &str) -> String {
+    if !argument.contains(|x: char| x.is_whitespace() || "\"'\\`$<>|&;*?#()".contains(x)) {
+        return argument.to_string();
+    }
+
+    let escaped = argument
+        .chars()
+        .map(|x| match x {
+            '"' | '`' | '$' | '\\' => format!("\\\\{x}"),
+            x => x.to_string(),
+        })
+        .collect::<String>();
+
+    format!("\"{escaped}\"")
+}
+
+
+/// Writes a desktop entry that starts this executable on graphical login,
+/// for desktops where a user service is awkward.
+#[cfg(target_os = "linux")]
+pub fn install(app_name: &str) -> Result<(), String> {
+    let executable = std::env::current_exe()
+        .map_err(|e| format!("Unable to find the running executable: {e}"))?;
+    let executable = executable
+        .to_str()
+        .ok_or("Unable to convert PathBuf to &str.")?;
+
+    let entry = format!(
+        "[Desktop Entry]\n\
+        Type=Application\n\
+        Name={app_name}\n\
+        Comment=Changes the wallpaper with the sun and the moon\n\
+        Exec={}\n\
+        Terminal=false\n\
+        NoDisplay=true\n\
+        X-GNOME-Autostart-enabled=true\n",
+        quote_exec_argument(executable)
+    );
+
+    let path = entry_path(app_name)?;
+
+    fs::create_dir_all(path.parent().unwrap_or(&path))
+        .and_then(|_| fs::write(&path, entry))
+        .map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+
+    println!("Installed {}, the changer starts on the next login.", path.display());
+
+    Ok(())
+}
+
+
+#[cfg(not(target_os = "linux"))]
+pub fn install(_app_name: &str) -> Result<(), String> {
+    Err(GUIDANCE.to_string())
+}
+
+
+/// Removes the desktop entry written by `install`.
+#[cfg(target_os = "linux")]
+pub fn uninstall(app_name: &str) -> Result<(), String> {
+    let path = entry_path(app_name)?;
+
+    if !path.exists() {
+        println!("No autostart entry at {}.", path.display());
+        return Ok(());
+    }
+
+    fs::remove_file(&path).map_err(|e| format!("Unable to remove {}: {e}", path.display()))?;
+    println!("Removed {}.", path.display());
+
+    Ok(())
+}
+
+
+#[cfg(not(target_os = "linux"))]
+pub fn uninstall(_app_name: &str) -> Result<(), String> {
+    Err(GUIDANCE.to_string())
+}
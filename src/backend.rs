@@ -0,0 +1,106 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{display, power, workspace};
+
+
+fn print_capability(name: &str, status: String) {
+    println!("  {name:<30} {status}");
+}
+
+
+fn feature_status(enabled: bool, feature: &str) -> String {
+    match enabled {
+        true => "yes".to_string(),
+        false => format!("no (build with the `{feature}` feature)"),
+    }
+}
+
+
+/// Probes what the wallpaper backend and this build can do on the current
+/// system, without changing the wallpaper.
+pub fn print_capabilities() {
+    println!("Wallpaper backend on {}:", std::env::consts::OS);
+
+    if let Ok(desktop) = std::env::var("XDG_CURRENT_DESKTOP") {
+        print_capability("Desktop environment", desktop);
+    }
+
+    print_capability("Read current wallpaper", match wallpaper::get() {
+        Ok(current) => format!("yes ({current})"),
+        Err(e) => format!("no ({e})"),
+    });
+
+    print_capability("Detect displays", match display::displays_available() {
+        true => "yes".to_string(),
+        false => "no".to_string(),
+    });
+
+    print_capability("Display change detection", feature_status(cfg!(feature = "display-info"), "display-info"));
+    // One image is set on every monitor, so the variant is picked for the
+    // primary one.
+    print_capability("Aspect ratio variants", match cfg!(feature = "display-info") {
+        true => "yes (matching the primary monitor only)".to_string(),
+        false => feature_status(false, "display-info"),
+    });
+    print_capability("Per-monitor wallpapers", "no (one image is set on every monitor)".to_string());
+    print_capability("Per-workspace wallpapers", match (cfg!(feature = "xfce-workspaces"), workspace::supported()) {
+        (false, _) => feature_status(false, "xfce-workspaces"),
+        (true, true) => "yes".to_string(),
+        (true, false) => "no (only on XFCE)".to_string(),
+    });
+    print_capability("Lock screen", "no".to_string());
+    print_capability("Crossfade transitions", feature_status(cfg!(feature = "image"), "image"));
+    print_capability("Solid colors and compositing", feature_status(cfg!(feature = "image"), "image"));
+    print_capability("Remote images", feature_status(cfg!(feature = "network"), "network"));
+
+    print_capability("Power source detection", match (cfg!(feature = "battery"), power::detect()) {
+        (false, _) => feature_status(false, "battery"),
+        (true, Some(source)) => format!("yes (currently {source:?})"),
+        (true, None) => "no (unable to read the power source)".to_string(),
+    });
+}
+
+
+/// Sets `image` `times` times and reports the latency of the backend, which
+/// bounds how smooth crossfades can be. The wallpaper set before is restored
+/// afterwards, also when setting the image fails.
+pub fn bench_set(image: &Path, times: u32) -> Result<(), String> {
+    if !image.is_file() {
+        return Err(format!("Image doesn't exist: {}", image.display()));
+    }
+
+    let image = image
+        .to_str()
+        .ok_or("Unable to convert PathBuf to &str.")?;
+
+    let previous = wallpaper::get()
+        .map_err(|e| format!("Unable to read the current wallpaper, it couldn't be restored: {e}"))?;
+
+    let mut timings = vec![];
+
+    for _ in 0..times.max(1) {
+        let start = Instant::now();
+
+        if let Err(e) = wallpaper::set_from_path(image) {
+            let _ = wallpaper::set_from_path(&previous);
+            return Err(format!("Unable to set wallpaper {image}: {e}"));
+        }
+
+        timings.push(start.elapsed());
+    }
+
+    wallpaper::set_from_path(&previous)
+        .map_err(|e| format!("Unable to restore wallpaper {previous}: {e}"))?;
+
+    let average = timings.iter().sum::<Duration>() / timings.len() as u32;
+
+    println!(
+        "Set {image} {} times: min {:.2?}, avg {average:.2?}, max {:.2?}",
+        timings.len(),
+        timings.iter().min().copied().unwrap_or_default(),
+        timings.iter().max().copied().unwrap_or_default()
+    );
+
+    Ok(())
+}
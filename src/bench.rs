@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use chrono::Duration as ChronoDuration;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::astronomy::Geodate;
+use crate::{
+    get_day_sun_and_moon_position_times,
+    map_timestamps,
+    schedule_boundaries,
+    transition,
+    BoundaryOptions,
+    PlaybackSettings,
+    SunAndMoonKeys,
+    WallpaperChangerConfig,
+    WallpaperPackConfig,
+};
+
+
+const ITERATIONS: i64 = 1000;
+const SOAK_DAYS: i64 = 366;
+
+
+fn percentile(sorted: &[Duration], percentile: usize) -> Duration {
+    sorted[(sorted.len() - 1) * percentile / 100]
+}
+
+
+fn print_timings(label: &str, mut timings: Vec<Duration>) {
+    timings.sort();
+
+    let average = timings.iter().sum::<Duration>() / timings.len() as u32;
+
+    println!(
+        "{label}: avg {average:?}, p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        percentile(&timings, 50),
+        percentile(&timings, 90),
+        percentile(&timings, 99),
+        timings[timings.len() - 1],
+    );
+}
+
+
+/// Computes the schedule for consecutive days starting at `today` and reports
+/// how long the astronomy and the image mapping take.
+pub fn run(
+    today: NaiveDateTime,
+    config: &WallpaperChangerConfig,
+    wallpaper_pack_config: &WallpaperPackConfig
+) -> Result<(), String> {
+    let mut astronomy_timings = vec![];
+    let mut mapping_timings = vec![];
+
+    for day in 0..ITERATIONS {
+        let start = Instant::now();
+        let sun_and_moon = get_day_sun_and_moon_position_times(
+            &Geodate,
+            (today + ChronoDuration::days(day)).timestamp(),
+            config.longitude,
+            config.latitude,
+            BoundaryOptions::from_config(config),
+        )?;
+        astronomy_timings.push(start.elapsed());
+
+        let start = Instant::now();
+        let _ = map_timestamps(&sun_and_moon, wallpaper_pack_config);
+        mapping_timings.push(start.elapsed());
+    }
+
+    println!("Schedule computation over {ITERATIONS} days:");
+    print_timings("  get_day_sun_and_moon_position_times", astronomy_timings);
+    print_timings("  map_timestamps", mapping_timings);
+
+    Ok(())
+}
+
+
+/// Checks the invariants of one day's schedule, returning what is broken.
+fn schedule_violations(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    wallpaper_pack_config: &WallpaperPackConfig
+) -> Vec<String> {
+    let timestamp_seq = map_timestamps(sun_and_moon, wallpaper_pack_config);
+    let image_count: usize = schedule_boundaries(sun_and_moon, wallpaper_pack_config, false)
+        .windows(2)
+        .map(|x| x[0].images.len())
+        .sum();
+
+    let mut violations = vec![];
+
+    if timestamp_seq.len() != image_count {
+        violations.push(format!("{} timestamps for {image_count} images", timestamp_seq.len()));
+    }
+
+    if let Some(x) = timestamp_seq.windows(2).find(|x| x[1] < x[0]) {
+        violations.push(format!("timestamps out of order, {} after {}", x[1], x[0]));
+    }
+
+    // Even splits round down, so the last slot may end a few seconds early.
+    let day_end = sun_and_moon[&SunAndMoonKeys::NextDayMidnight];
+    if let Some(last) = timestamp_seq.last() {
+        if day_end - last > image_count as i64 || *last > day_end {
+            violations.push(format!("last slot ends at {last}, the day at {day_end}"));
+        }
+    }
+
+    violations
+}
+
+
+pub(crate) fn format_duration(seconds: i64) -> String {
+    format!("{}h {:02}m {:02}s", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+
+/// Shortest and longest time something was shown, with the days it happened on.
+#[derive(Debug, Clone)]
+struct DisplayTime {
+    label: String,
+    shortest: (i64, NaiveDate),
+    longest: (i64, NaiveDate),
+}
+
+impl DisplayTime {
+    fn new(label: String, seconds: i64, day: NaiveDate) -> Self {
+        Self {
+            label,
+            shortest: (seconds, day),
+            longest: (seconds, day),
+        }
+    }
+
+    fn record(&mut self, seconds: i64, day: NaiveDate) {
+        if seconds < self.shortest.0 {
+            self.shortest = (seconds, day);
+        }
+        if seconds > self.longest.0 {
+            self.longest = (seconds, day);
+        }
+    }
+}
+
+
+/// Builds the schedule for every day of the coming year at the configured
+/// location and reports, for pack authors, how long each image is shown at
+/// least and at most, the shortest phase and the days on which anchors had to
+/// be dropped or the schedule's invariants broke.
+pub fn analyze(
+    today: NaiveDateTime,
+    config: &WallpaperChangerConfig,
+    wallpaper_pack: &str,
+    wallpaper_pack_config: &WallpaperPackConfig
+) -> Result<(), String> {
+    let mut skipped = 0;
+    let mut warning_days = vec![];
+    let mut shortest_phase: Option<(i64, String, NaiveDate)> = None;
+    let mut images: Vec<DisplayTime> = vec![];
+    let mut image_indices: HashMap<String, usize> = HashMap::new();
+
+    for day in 0..SOAK_DAYS {
+        let day_start = today + ChronoDuration::days(day);
+
+        // Polar days and nights have no sunrise or sunset to build on.
+        let Ok(sun_and_moon) = get_day_sun_and_moon_position_times(
+            &Geodate,
+            day_start.timestamp(),
+            config.longitude,
+            config.latitude,
+            BoundaryOptions::from_config(config),
+        ) else {
+            skipped += 1;
+            continue;
+        };
+
+        let boundaries = schedule_boundaries(&sun_and_moon, wallpaper_pack_config, false);
+        let skipped_anchors = wallpaper_pack_config.anchors.len()
+            - boundaries.iter().filter(|x| x.key.is_none()).count();
+
+        if skipped_anchors > 0 || !schedule_violations(&sun_and_moon, wallpaper_pack_config).is_empty() {
+            warning_days.push(day_start.date());
+        }
+
+        for phase in boundaries.windows(2).filter(|x| !x[0].images.is_empty()) {
+            let seconds = phase[1].timestamp - phase[0].timestamp;
+
+            match &shortest_phase {
+                Some((shortest, _, _)) if *shortest <= seconds => (),
+                _ => shortest_phase = Some((seconds, phase[0].name.clone(), day_start.date())),
+            }
+        }
+
+        let labels = boundaries
+            .windows(2)
+            .flat_map(|x| x[0].images.iter().map(|image| format!("{} {}", x[0].name, image.path())));
+        let timestamp_seq = map_timestamps(&sun_and_moon, wallpaper_pack_config);
+
+        let mut slot_start = boundaries.first().map(|x| x.timestamp).unwrap_or_default();
+
+        for (label, slot_end) in labels.zip(timestamp_seq) {
+            let seconds = slot_end - slot_start;
+            slot_start = slot_end;
+
+            // Empty phases leave slots of no length, nothing is shown in them.
+            if seconds == 0 {
+                continue;
+            }
+
+            match image_indices.get(&label) {
+                Some(index) => images[*index].record(seconds, day_start.date()),
+                None => {
+                    image_indices.insert(label.clone(), images.len());
+                    images.push(DisplayTime::new(label, seconds, day_start.date()));
+                }
+            }
+        }
+    }
+
+    println!(
+        "Analysis of '{wallpaper_pack}' at latitude {}, longitude {} over {SOAK_DAYS} days from {}:",
+        config.latitude,
+        config.longitude,
+        today.date()
+    );
+    println!("  Days without sun or moon events: {skipped}");
+    println!("  Days with ordering warnings: {}", warning_days.len());
+
+    if let (Some(first), Some(last)) = (warning_days.first(), warning_days.last()) {
+        println!("    first on {first}, last on {last}");
+    }
+
+    if let Some((seconds, name, day)) = shortest_phase {
+        println!("  Shortest phase: {name}, {} on {day}", format_duration(seconds));
+    }
+
+    if let Some(x) = images.iter().min_by_key(|x| x.shortest.0) {
+        println!("  Shortest image display: {}, {} on {}", x.label, format_duration(x.shortest.0), x.shortest.1);
+
+        let playback = PlaybackSettings::resolve(config, wallpaper_pack_config, None);
+        let transition = playback
+            .transitions()
+            .max_by_key(|x| x.duration_seconds())
+            .unwrap_or(&playback.transition);
+
+        if let Some(fitted) = transition::fit_to_slot(transition, x.shortest.0) {
+            println!(
+                "    shorter than the {} transition, which is shortened to {} then",
+                format_duration(transition.duration_seconds() as i64),
+                format_duration(fitted.duration_seconds() as i64)
+            );
+        }
+    }
+
+    if let Some(x) = images.iter().max_by_key(|x| x.longest.0) {
+        println!("  Longest image display: {}, {} on {}", x.label, format_duration(x.longest.0), x.longest.1);
+    }
+
+    println!("  Display time per image, shortest to longest:");
+
+    let label_width = images.iter().map(|x| x.label.len()).max().unwrap_or_default();
+
+    for x in &images {
+        println!(
+            "    {:<label_width$}  {} - {}",
+            x.label,
+            format_duration(x.shortest.0),
+            format_duration(x.longest.0)
+        );
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+    use crate::astronomy::{AstronomyProvider, FixedAstronomy};
+    use crate::{PackImage, PhaseOrder};
+
+
+    const SOAK_LATITUDES: [f64; 13] = [-89.99, -80.0, -67.0, -66.0, -45.0, -23.5, 0.0, 23.5, 45.0, 66.0, 67.0, 80.0, 89.99];
+    const SOAK_LONGITUDES: [f64; 5] = [-150.0, -60.0, 0.0, 60.0, 150.0];
+    /// Around and beyond the polar circles, where the sun and moon events are
+    /// most likely to come in an odd order or not at all.
+    const POLAR_LATITUDES: [f64; 6] = [-89.99, -80.0, -67.0, 67.0, 80.0, 89.99];
+    const SOAK_REPORTED_VIOLATIONS: usize = 10;
+
+
+    /// Skies the soak locations rarely or never have, checked on top of them.
+    /// Skies whose moon events fall between the sun events only keep their
+    /// order with the `sorted` phase order.
+    fn soak_skies(phase_order: PhaseOrder) -> Vec<(&'static str, FixedAstronomy)> {
+        let hour = 3600;
+
+        let mut skies = vec![
+            ("a typical day", FixedAstronomy::default()),
+            ("a short summer night", FixedAstronomy {
+                sunrise: Some(3 * hour + 30 * 60),
+                sunset: Some(22 * hour + 30 * 60),
+                moonrise: Some(23 * hour),
+                moonset: Some(3 * hour),
+                ..FixedAstronomy::default()
+            }),
+        ];
+
+        if phase_order == PhaseOrder::Sorted {
+            skies.push(("the moon up in daylight", FixedAstronomy {
+                moonrise: Some(15 * hour),
+                moonset: Some(9 * hour),
+                ..FixedAstronomy::default()
+            }));
+            skies.push(("the moon rising before midnight and setting after noon", FixedAstronomy {
+                moonrise: Some(21 * hour),
+                moonset: Some(13 * hour),
+                ..FixedAstronomy::default()
+            }));
+        }
+
+        skies
+    }
+
+
+    fn soak_pack(phase_order: PhaseOrder) -> WallpaperPackConfig {
+        let images = |phase: &str, count: usize| {
+            (0..count).map(|x| PackImage::Path(format!("{phase}_{x}.png"))).collect()
+        };
+
+        WallpaperPackConfig {
+            midnight: images("midnight", 3),
+            moonset: images("moonset", 1),
+            sunrise: images("sunrise", 2),
+            noon: images("noon", 4),
+            sunset: images("sunset", 2),
+            moonrise: images("moonrise", 1),
+            phase_order: Some(phase_order),
+            ..WallpaperPackConfig::default()
+        }
+    }
+
+
+    fn soak_start() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    }
+
+
+    /// Builds one day's schedule, turning a panic into an error.
+    fn check_schedule(
+        astronomy: &impl AstronomyProvider,
+        day_start: i64,
+        longitude: f64,
+        latitude: f64,
+        config: &WallpaperChangerConfig,
+        wallpaper_pack_config: &WallpaperPackConfig
+    ) -> Result<Result<Vec<String>, String>, String> {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            let sun_and_moon = get_day_sun_and_moon_position_times(
+                astronomy,
+                day_start,
+                longitude,
+                latitude,
+                BoundaryOptions::from_config(config),
+            )?;
+
+            Ok(schedule_violations(&sun_and_moon, wallpaper_pack_config))
+        })).map_err(|_| "panicked".to_string())
+    }
+
+
+    #[test]
+    fn fixed_skies_keep_the_schedule_invariants() {
+        let config = WallpaperChangerConfig::default();
+
+        for phase_order in [PhaseOrder::Fixed, PhaseOrder::Sorted] {
+            let wallpaper_pack_config = soak_pack(phase_order);
+
+            for (sky, astronomy) in soak_skies(phase_order) {
+                for day in 0..SOAK_DAYS {
+                    let day_start = (soak_start() + ChronoDuration::days(day)).timestamp();
+
+                    let violations = check_schedule(
+                        &astronomy,
+                        day_start,
+                        config.longitude,
+                        config.latitude,
+                        &config,
+                        &wallpaper_pack_config
+                    ).and_then(|x| x);
+
+                    assert_eq!(violations, Ok(vec![]), "{sky} on day {day} with {phase_order:?} phase order");
+                }
+            }
+        }
+    }
+
+
+    /// Builds the schedule for each of `days` at every location and checks
+    /// for panics, unsorted timestamps, a timestamp count that doesn't match
+    /// the images and days that aren't fully covered.
+    fn soak(
+        phase_order: PhaseOrder,
+        latitudes: &[f64],
+        longitudes: &[f64],
+        days: impl Iterator<Item = i64> + Clone
+    ) {
+        let config = WallpaperChangerConfig::default();
+        let wallpaper_pack_config = soak_pack(phase_order);
+        let mut schedules = 0;
+        let mut skipped = 0;
+        let mut violations = vec![];
+
+        for latitude in latitudes {
+            for longitude in longitudes {
+                for day in days.clone() {
+                    let day_start = (soak_start() + ChronoDuration::days(day)).timestamp();
+                    let location = format!("day {day_start} at latitude {latitude}, longitude {longitude}");
+
+                    match check_schedule(&Geodate, day_start, *longitude, *latitude, &config, &wallpaper_pack_config) {
+                        Ok(Ok(day_violations)) => {
+                            schedules += 1;
+                            violations.extend(day_violations.into_iter().map(|x| format!("{location}: {x}")));
+                        }
+                        // Polar days and nights have no sunrise or sunset to build on.
+                        Ok(Err(_)) => skipped += 1,
+                        Err(e) => violations.push(format!("{location}: {e}")),
+                    }
+                }
+            }
+        }
+
+        assert!(schedules > 0, "every schedule was skipped");
+        assert!(
+            violations.is_empty(),
+            "{} of {schedules} schedules broke their invariants with {phase_order:?} phase order, {skipped} skipped, e.g.:\n{}",
+            violations.len(),
+            violations[..violations.len().min(SOAK_REPORTED_VIOLATIONS)].join("\n")
+        );
+    }
+
+
+    #[test]
+    fn polar_schedules_keep_their_invariants_in_the_fixed_phase_order() {
+        soak(PhaseOrder::Fixed, &POLAR_LATITUDES, &[-150.0, 0.0, 150.0], (0..SOAK_DAYS).step_by(15));
+    }
+
+
+    #[test]
+    fn polar_schedules_keep_their_invariants_in_the_sorted_phase_order() {
+        soak(PhaseOrder::Sorted, &POLAR_LATITUDES, &[-150.0, 0.0, 150.0], (0..SOAK_DAYS).step_by(15));
+    }
+
+
+    #[test]
+    #[ignore = "builds a year of schedules at 65 locations"]
+    fn schedules_around_the_world_keep_their_invariants() {
+        for phase_order in [PhaseOrder::Fixed, PhaseOrder::Sorted] {
+            soak(phase_order, &SOAK_LATITUDES, &SOAK_LONGITUDES, 0..SOAK_DAYS);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+
+/// How often `follow` looks for new entries.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    /// One JSON object per line.
+    Json,
+}
+
+
+/// Where wallpaper changes are logged, e.g. `[change_log]` with
+/// `path = "/home/me/.local/state/wallpaper_changes.log"` and `format = "json"`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ChangeLogConfig {
+    /// File every wallpaper change is appended to, disabled when unset.
+    pub path: Option<String>,
+    pub format: LogFormat,
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct ChangeRecord<'a> {
+    pub timestamp: String,
+    pub phase: String,
+    pub image: &'a Path,
+    pub monitor: Option<&'a str>,
+    pub success: bool,
+    pub error: Option<&'a str>,
+}
+
+impl<'a> ChangeRecord<'a> {
+    pub fn new(phase: String, image: &'a Path, error: Option<&'a str>) -> Self {
+        Self {
+            timestamp: Local::now().to_rfc3339(),
+            phase,
+            image,
+            monitor: None,
+            success: error.is_none(),
+            error,
+        }
+    }
+
+    fn to_plain(&self) -> String {
+        let mut line = format!("{} {} {}", self.timestamp, self.phase, self.image.display());
+
+        if let Some(monitor) = self.monitor {
+            line.push_str(&format!(" monitor={monitor}"));
+        }
+
+        match self.error {
+            None => line.push_str(" ok"),
+            Some(error) => line.push_str(&format!(" failed: {error}")),
+        }
+
+        line
+    }
+}
+
+
+pub struct ChangeLog {
+    writer: BufWriter<File>,
+    format: LogFormat,
+}
+
+impl ChangeLog {
+    pub fn open(path: &Path, format: LogFormat) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .ok()
+                .ok_or_else(|| format!("Unable to create change log directory: {}", parent.display()))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Unable to open change log {}: {e}", path.display()))?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            format,
+        })
+    }
+
+    pub fn record(&mut self, record: &ChangeRecord) -> Result<(), String> {
+        let line = match self.format {
+            LogFormat::Plain => record.to_plain(),
+            LogFormat::Json => serde_json::to_string(record)
+                .map_err(|e| format!("Unable to serialize change log record: {e}"))?,
+        };
+
+        writeln!(self.writer, "{line}")
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("Unable to write to change log: {e}"))
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.writer
+            .flush()
+            .map_err(|e| format!("Unable to flush change log: {e}"))
+    }
+}
+
+
+/// A change as read back from the log, in either format.
+#[derive(Deserialize, Debug)]
+struct LoggedChange {
+    timestamp: String,
+    phase: String,
+    image: PathBuf,
+    monitor: Option<String>,
+    error: Option<String>,
+}
+
+impl LoggedChange {
+    /// Parses a line written by `ChangeLog::record`, `None` for anything else.
+    fn parse(line: &str) -> Option<Self> {
+        if line.starts_with('{') {
+            return serde_json::from_str(line).ok();
+        }
+
+        let (timestamp, rest) = line.split_once(' ')?;
+        let (phase, rest) = rest.split_once(' ')?;
+
+        let (rest, error) = match rest.rsplit_once(" failed: ") {
+            Some((rest, error)) => (rest, Some(error.to_string())),
+            None => (rest.strip_suffix(" ok")?, None),
+        };
+
+        let (image, monitor) = match rest.rsplit_once(" monitor=") {
+            Some((image, monitor)) => (image, Some(monitor.to_string())),
+            None => (rest, None),
+        };
+
+        Some(Self {
+            timestamp: timestamp.to_string(),
+            phase: phase.to_string(),
+            image: PathBuf::from(image),
+            monitor,
+            error,
+        })
+    }
+
+    /// One aligned line: local time, phase, image and what became of it.
+    fn format(&self) -> String {
+        let time = DateTime::parse_from_rfc3339(&self.timestamp)
+            .map(|x| x.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|_| self.timestamp.clone());
+
+        let mut line = format!("{time}  {:<10} {}", self.phase, self.image.display());
+
+        if let Some(monitor) = &self.monitor {
+            line.push_str(&format!("  on {monitor}"));
+        }
+
+        if let Some(error) = &self.error {
+            line.push_str(&format!("  FAILED: {error}"));
+        }
+
+        line
+    }
+}
+
+
+fn print_entry(line: &str) {
+    match LoggedChange::parse(line) {
+        Some(change) => println!("{}", change.format()),
+        None if !line.trim().is_empty() => println!("{line}"),
+        None => (),
+    }
+}
+
+
+/// Tells a file replaced by rotation from the one being read.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+
+/// Prints the last `lines` entries of the change log at `path`, then every
+/// entry appended to it until `terminate` is set, like `tail -f`. A log that
+/// is truncated, or rotated away and recreated, is read again from the start.
+pub fn follow(
+    path: &Path,
+    lines: usize,
+    terminate: Arc<AtomicBool>
+) -> Result<(), String> {
+    let mut file = File::open(path)
+        .map_err(|e| format!("Unable to open change log {}: {e}", path.display()))?;
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|e| format!("Unable to read change log {}: {e}", path.display()))?;
+
+    let existing = content.lines().collect::<Vec<&str>>();
+    existing[existing.len().saturating_sub(lines)..].iter().for_each(|x| print_entry(x));
+
+    let mut position = content.len() as u64;
+    let mut id = file.metadata().ok().as_ref().and_then(file_id);
+    // The end of a line still being written.
+    let mut partial = String::new();
+
+    println!("Watching {}, press Ctrl+C to stop.", path.display());
+
+    while !terminate.load(Ordering::SeqCst) {
+        thread::sleep(FOLLOW_INTERVAL);
+
+        let Ok(metadata) = fs::metadata(path) else {
+            // Rotated away and not recreated yet.
+            continue;
+        };
+
+        let rotated = file_id(&metadata).is_some_and(|x| Some(x) != id);
+
+        if rotated || metadata.len() < position {
+            file = match File::open(path) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            id = file_id(&metadata);
+            position = 0;
+            partial.clear();
+            println!("The change log was {}, reading it from the start.", if rotated { "rotated" } else { "truncated" });
+        }
+
+        if metadata.len() == position {
+            continue;
+        }
+
+        let mut appended = String::new();
+
+        file.seek(SeekFrom::Start(position))
+            .and_then(|_| file.read_to_string(&mut appended))
+            .map_err(|e| format!("Unable to read change log {}: {e}", path.display()))?;
+
+        position += appended.len() as u64;
+        partial.push_str(&appended);
+
+        if let Some(end) = partial.rfind('\n') {
+            partial[..end].lines().for_each(print_entry);
+            partial.drain(..=end);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use clap::{Parser, Subcommand};
+use clap_complete::Shell;
+
+
+/// Parses `HH:MM[:SS]` as a time today or `YYYY-MM-DD HH:MM[:SS]`.
+fn parse_local_time(value: &str) -> Result<NaiveDateTime, String> {
+    let value = value.trim();
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(time);
+        }
+    }
+
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(value, format) {
+            return Ok(Local::now().date_naive().and_time(time));
+        }
+    }
+
+    Err(format!("Invalid time '{value}', expected HH:MM or YYYY-MM-DD HH:MM."))
+}
+
+
+/// Parses degrees within `-limit..=limit`, the range the config enforces.
+fn parse_degrees(value: &str, name: &str, limit: f64) -> Result<f64, String> {
+    let degrees = value
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid {} '{value}': {e}", name.to_lowercase()))?;
+
+    if !(-limit..=limit).contains(&degrees) {
+        return Err(format!("{name} {degrees} is out of range [-{limit}, {limit}]."));
+    }
+
+    Ok(degrees)
+}
+
+
+fn parse_latitude(value: &str) -> Result<f64, String> {
+    parse_degrees(value, "Latitude", 90.0)
+}
+
+
+fn parse_longitude(value: &str) -> Result<f64, String> {
+    parse_degrees(value, "Longitude", 180.0)
+}
+
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print a phase's images with their indices, read a new order as comma
+    /// separated indices from stdin and save it to the pack config.
+    ReorderPack {
+        pack: String,
+        /// One of the six phases or the name of an anchor.
+        phase: String,
+    },
+    /// Bring an older pack config up to the current format, adding missing
+    /// phases and defaults while keeping its comments. The old config is kept
+    /// as a `.bak` file.
+    UpgradePack {
+        pack: String,
+    },
+    /// Compare the pack's images against the hashes in its `update_manifest`
+    /// and list the ones that are missing or changed.
+    CheckUpdates {
+        pack: String,
+        /// Download the listed images, replacing the local copies.
+        #[arg(long)]
+        download: bool,
+    },
+    /// Create the pack `output` with the images of both packs, each phase
+    /// listing those of `first` before those of `second`. Identical images are
+    /// copied once.
+    MergePacks {
+        first: String,
+        second: String,
+        output: String,
+    },
+    /// Delete a pack from the packs directory and report the space freed. A
+    /// pack the config still names is kept unless `--force` is given.
+    RemovePack {
+        pack: String,
+        /// Don't ask before deleting.
+        #[arg(long)]
+        yes: bool,
+        /// Delete it even if the config still names it.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Build the pack's schedule for every day of the coming year at the
+    /// configured location and report display times, the shortest phase and
+    /// days with ordering problems.
+    AnalyzePack {
+        pack: String,
+    },
+    /// Play the configured transition, or the one of `--pack`, from one image
+    /// to another right away.
+    PreviewTransition {
+        from: String,
+        to: String,
+        /// Set the previous wallpaper again once Enter is pressed.
+        #[arg(long)]
+        restore: bool,
+    },
+    /// Set an image several times and report how long the wallpaper backend
+    /// takes, then set the previous wallpaper again.
+    BenchSet {
+        image: String,
+        /// How many times to set the image.
+        #[arg(long, default_value_t = 10)]
+        times: u32,
+    },
+    /// Cycle through every image of the active pack in schedule order, each
+    /// for a fixed time and regardless of the clock, until Ctrl+C. Unlike the
+    /// schedule, the sun and the moon play no part. The previous wallpaper is
+    /// set again on exit.
+    Demo {
+        /// How long each image is shown.
+        #[arg(long, value_name = "SECONDS", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        dwell_seconds: u64,
+    },
+    /// Print the entries of the change log as they are written, formatted,
+    /// like `tail -f`. Follows the log through rotation and truncation.
+    Watch {
+        /// How many of the latest entries to print first.
+        #[arg(long, default_value_t = 10)]
+        lines: usize,
+    },
+    /// Print the effective config, the active pack, today's phase boundaries
+    /// and the schedule as one JSON document, e.g. to attach to a bug report.
+    Report,
+    /// Stop changing the wallpaper, also after restarts, until `enable`. A
+    /// running instance holds its wallpaper meanwhile.
+    Disable,
+    /// Undo `disable`.
+    Enable,
+    /// Start the changer on graphical login by writing a desktop entry to
+    /// `~/.config/autostart`. Linux only.
+    InstallAutostart,
+    /// Remove the desktop entry written by `install-autostart`.
+    UninstallAutostart,
+    /// Print a completion script for the given shell, e.g.
+    /// `wallpaper_changer_rust completions bash > /etc/bash_completion.d/wallpaper_changer_rust`.
+    Completions {
+        shell: Shell,
+    },
+}
+
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    after_help = "Exit codes: 1 other error, 2 config error, 3 pack error, 4 astronomy error, \
+        5 backend error, 6 no displays detected."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Use this pack for the current run, overriding `WALLPAPER_CHANGER_PACK`
+    /// and the configured pack.
+    #[arg(long)]
+    pub pack: Option<String>,
+
+    /// Use the pack in this directory for the current run, bypassing the packs
+    /// directory and any configured or selected pack name.
+    #[arg(long, value_name = "DIR", conflicts_with = "pack")]
+    pub pack_path: Option<String>,
+
+    /// Print today's phase boundary times and exit.
+    #[arg(long)]
+    pub list_phases: bool,
+
+    /// Print today's schedule of the active pack as a 24 hour timeline and exit.
+    #[arg(long)]
+    pub timeline: bool,
+
+    /// With `--timeline`, also print a histogram of how long each image is
+    /// shown, flagging images shown for less than the poll interval.
+    #[arg(long, requires = "timeline")]
+    pub histogram: bool,
+
+    /// Print which image the active pack shows at a local time (HH:MM today or
+    /// YYYY-MM-DD HH:MM), with its phase and slot, and exit.
+    #[arg(long, value_name = "TIME", value_parser = parse_local_time)]
+    pub dump_image_for: Option<NaiveDateTime>,
+
+    /// Print all sun and moon event times for a date (YYYY-MM-DD) and exit.
+    #[arg(long, value_name = "DATE")]
+    pub dump_astronomy: Option<NaiveDate>,
+
+    /// Override a config field for this run, e.g. `--set poll_interval_seconds=60`.
+    /// Nested fields are joined with dots. May be given several times.
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+
+    /// Poll every this many seconds for this run, over the config, `--set`
+    /// and the pack's own interval. At least 1.
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval_override: Option<u64>,
+
+    /// Override the configured latitude for this run.
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_latitude)]
+    pub latitude: Option<f64>,
+
+    /// Override the configured longitude for this run.
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_longitude)]
+    pub longitude: Option<f64>,
+
+    /// Print which wallpaper backend capabilities work on this system and exit.
+    #[arg(long)]
+    pub check_backend: bool,
+
+    /// Set a solid color wallpaper from a hex value (e.g. `#1e90ff`) and exit.
+    /// Useful to check that setting wallpapers works at all.
+    #[arg(long, value_name = "HEX")]
+    pub color: Option<String>,
+
+    /// Time the schedule computation for the configured pack and exit.
+    #[arg(long, hide = true)]
+    pub bench: bool,
+}
@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::processing;
+#[cfg(feature = "image")]
+use crate::strips;
+
+
+/// Color temperature of daylight, left untouched by the grade.
+const NEUTRAL_KELVIN: f64 = 6500.0;
+/// Temperatures are rounded to this, so the sun creeping along doesn't
+/// render a new image on every poll.
+const KELVIN_BUCKET: f64 = 250.0;
+/// Sun altitude in degrees from which `noon_kelvin` applies.
+const HIGH_SUN_ALTITUDE: f64 = 45.0;
+/// Below this sun altitude in degrees it's night and images stay neutral.
+const NIGHT_SUN_ALTITUDE: f64 = -6.0;
+
+
+/// White balance that follows the sun: warm while it is low, around sunrise
+/// and sunset, cool while it is high. At night images are left as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ColorGrade {
+    /// Temperature with the sun on the horizon, e.g. 3500 for a golden tint.
+    pub horizon_kelvin: f64,
+    /// Temperature with the sun high in the sky, above 6500 cools the image.
+    pub noon_kelvin: f64,
+    /// From 0.0 (no grading) to 1.0 (the full white balance shift).
+    pub strength: f64,
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        Self {
+            horizon_kelvin: 3500.0,
+            noon_kelvin: 7500.0,
+            strength: 0.5,
+        }
+    }
+}
+
+
+/// A white balance shift towards `kelvin`, as applied to one image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grade {
+    pub kelvin: f64,
+    pub strength: f64,
+}
+
+
+impl ColorGrade {
+    /// The grade for the sun at `sun_altitude` degrees, `None` when it leaves
+    /// images untouched.
+    pub fn at(&self, sun_altitude: f64) -> Option<Grade> {
+        let kelvin = match sun_altitude {
+            x if x < NIGHT_SUN_ALTITUDE => NEUTRAL_KELVIN,
+            // Twilight eases from neutral into the horizon's warmth.
+            x if x < 0.0 => {
+                let progress = (x - NIGHT_SUN_ALTITUDE) / -NIGHT_SUN_ALTITUDE;
+                NEUTRAL_KELVIN + (self.horizon_kelvin - NEUTRAL_KELVIN) * progress
+            }
+            x => {
+                let progress = (x / HIGH_SUN_ALTITUDE).min(1.0);
+                self.horizon_kelvin + (self.noon_kelvin - self.horizon_kelvin) * progress
+            }
+        };
+
+        let kelvin = (kelvin / KELVIN_BUCKET).round() * KELVIN_BUCKET;
+        let strength = self.strength.clamp(0.0, 1.0);
+
+        match kelvin == NEUTRAL_KELVIN || strength == 0.0 {
+            true => None,
+            false => Some(Grade { kelvin, strength }),
+        }
+    }
+}
+
+
+/// Approximate RGB of a black body at `kelvin`, each channel from 0.0 to 1.0.
+/// Tanner Helland's fit, close enough between 1000 K and 40000 K.
+#[cfg(feature = "image")]
+fn black_body(kelvin: f64) -> [f64; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let red = match t <= 66.0 {
+        true => 255.0,
+        false => 329.698727446 * (t - 60.0).powf(-0.1332047592),
+    };
+    let green = match t <= 66.0 {
+        true => 99.4708025861 * t.ln() - 161.1195681661,
+        false => 288.1221695283 * (t - 60.0).powf(-0.0755148492),
+    };
+    let blue = match t {
+        t if t >= 66.0 => 255.0,
+        t if t <= 19.0 => 0.0,
+        t => 138.5177312231 * (t - 10.0).ln() - 305.0447927307,
+    };
+
+    [red, green, blue].map(|x| x.clamp(0.0, 255.0) / 255.0)
+}
+
+
+/// Per channel multipliers moving white from daylight to `grade.kelvin`.
+#[cfg(feature = "image")]
+fn channel_gains(grade: Grade) -> [f64; 3] {
+    let target = black_body(grade.kelvin);
+    let neutral = black_body(NEUTRAL_KELVIN);
+
+    [0, 1, 2].map(|x| 1.0 + (target[x] / neutral[x].max(f64::EPSILON) - 1.0) * grade.strength)
+}
+
+
+#[cfg(feature = "image")]
+fn apply(
+    image: &Path,
+    grade: Grade,
+    strip_rows: u32,
+    output: &Path
+) -> Result<(), String> {
+    let gains = channel_gains(grade);
+
+    let grade_pixels = |mut graded: image::RgbaImage| {
+        for pixel in graded.pixels_mut() {
+            for channel in 0..3 {
+                pixel[channel] = (pixel[channel] as f64 * gains[channel]).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+
+        graded
+    };
+
+    if strip_rows > 0 {
+        return strips::map(image, output, strip_rows, 0, false, grade_pixels);
+    }
+
+    let graded = image::open(image)
+        .map_err(|e| format!("Unable to open image {}: {e}", image.display()))?
+        .to_rgba8();
+
+    grade_pixels(graded)
+        .save_with_format(output, image::ImageFormat::Png)
+        .map_err(|e| format!("Unable to save color graded image: {e}"))
+}
+
+
+#[cfg(not(feature = "image"))]
+fn apply(
+    _image: &Path,
+    _grade: Grade,
+    _strip_rows: u32,
+    _output: &Path
+) -> Result<(), String> {
+    Err("Color grading requires the `image` feature.".to_string())
+}
+
+
+/// `image` with `grade` applied, cached like gamma corrected images under the
+/// image, its modification time and the grade. Without a grade, or when it
+/// can't be applied, the image itself is used. `strip_rows` works like for
+/// gamma correction.
+pub fn graded_image(
+    image: &Path,
+    grade: Option<Grade>,
+    strip_rows: u32,
+    color_grade_dir: &Path
+) -> PathBuf {
+    let Some(grade) = grade else {
+        return image.to_path_buf();
+    };
+
+    if cfg!(not(feature = "image")) || !image.is_file() {
+        return image.to_path_buf();
+    }
+
+    processing::cached_output(image, &format!("{grade:?}"), color_grade_dir, |output| {
+        apply(image, grade, strip_rows, output)
+    })
+}
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::display;
+use crate::transition::{self, Transition};
+use crate::WallpaperChangerConfig;
+
+
+/// A vertical slice of the screen filled from its own pack's schedule.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompositeRegion {
+    pub pack: String,
+    /// Left edge as a fraction of the screen width.
+    pub start: f64,
+    /// Right edge as a fraction of the screen width.
+    pub end: f64,
+}
+
+
+#[cfg(feature = "image")]
+fn render(
+    regions: &[CompositeRegion],
+    images: &[PathBuf],
+    (canvas_size, threads): (Option<(u32, u32)>, usize),
+    output: &Path
+) -> Result<(), String> {
+    use image::imageops::{self, FilterType};
+    use image::RgbaImage;
+
+    use crate::{orientation, processing};
+
+    let (width, height) = match canvas_size {
+        Some(size) => size,
+        None => image::image_dimensions(&images[0])
+            .map_err(|e| format!("Unable to read image {}: {e}", images[0].display()))?,
+    };
+
+    let mut canvas = RgbaImage::new(width, height);
+
+    let slices = regions
+        .iter()
+        .zip(images)
+        .filter_map(|(region, image)| {
+            let left = (region.start.clamp(0.0, 1.0) * width as f64).round() as u32;
+            let right = (region.end.clamp(0.0, 1.0) * width as f64).round() as u32;
+
+            (right > left).then_some((left, right, image))
+        })
+        .collect::<Vec<(u32, u32, &PathBuf)>>();
+
+    // Decoding and scaling is the slow part, the slices are pasted in order.
+    let scaled = processing::parallel_map(&slices, threads, |(left, right, image)| {
+        orientation::open(image)
+            .map(|x| (*left, x.resize_to_fill(right - left, height, FilterType::Triangle).to_rgba8()))
+    });
+
+    for slice in scaled {
+        let (left, slice) = slice?;
+        imageops::replace(&mut canvas, &slice, left as i64, 0);
+    }
+
+    canvas
+        .save(output)
+        .map_err(|e| format!("Unable to save composite wallpaper: {e}"))
+}
+
+
+#[cfg(not(feature = "image"))]
+fn render(
+    _regions: &[CompositeRegion],
+    _images: &[PathBuf],
+    _canvas: (Option<(u32, u32)>, usize),
+    _output: &Path
+) -> Result<(), String> {
+    Err("Compositing packs requires the `image` feature.".to_string())
+}
+
+
+/// The canvas the regions are combined into, sized to the primary monitor and
+/// set as a single wallpaper.
+pub struct Composite {
+    regions: Vec<CompositeRegion>,
+    canvas_size: Option<(u32, u32)>,
+    threads: usize,
+    output_dir: PathBuf,
+    frame: u64,
+    /// The canvas set last, the next one transitions from it.
+    pub canvas: Option<PathBuf>,
+}
+
+impl Composite {
+    pub fn new(config: &WallpaperChangerConfig, output_dir: &Path) -> Result<Self, String> {
+        std::fs::create_dir_all(output_dir)
+            .ok()
+            .ok_or("Unable to create composite output directory.")?;
+
+        Ok(Self {
+            regions: config.composite_regions.clone(),
+            canvas_size: display::primary_monitor_size(),
+            threads: config.processing_threads,
+            output_dir: output_dir.to_path_buf(),
+            frame: 0,
+            canvas: None,
+        })
+    }
+
+    /// Renders `images`, one per region, into a new canvas and sets it with
+    /// `transition` from the previous one.
+    pub fn set(
+        &mut self,
+        images: &[PathBuf],
+        transition: &Transition,
+        frames_dir: &Path
+    ) -> Result<PathBuf, String> {
+        // Alternate between two file names, some desktops ignore a set to the same path.
+        let output = self.output_dir.join(format!("composite_{}.png", self.frame % 2));
+
+        render(&self.regions, images, (self.canvas_size, self.threads), &output)?;
+        transition::apply(self.canvas.as_deref(), &output, transition, frames_dir)?;
+
+        self.frame += 1;
+        self.canvas = Some(output.clone());
+
+        Ok(output)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "image")]
+    #[test]
+    fn canvases_are_the_same_on_any_number_of_threads() {
+        use image::{Rgba, RgbaImage};
+
+        use super::*;
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_composite_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let images = [(40, 30), (25, 60), (90, 20)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, (width, height))| {
+                let image = dir.join(format!("{index}.png"));
+                RgbaImage::from_fn(width, height, |x, y| Rgba([(x * 7) as u8, (y * 5) as u8, index as u8 * 80, 255]))
+                    .save(&image)
+                    .unwrap();
+                image
+            })
+            .collect::<Vec<PathBuf>>();
+        let regions = [(0.0, 0.25), (0.25, 0.7), (0.7, 1.0)]
+            .map(|(start, end)| CompositeRegion { pack: String::new(), start, end });
+
+        let canvas = |threads: usize| {
+            let output = dir.join(format!("canvas_{threads}.png"));
+            render(&regions, &images, (Some((64, 36)), threads), &output).unwrap();
+            image::open(output).unwrap().to_rgba8()
+        };
+
+        let serial = canvas(1);
+
+        assert_eq!(serial.dimensions(), (64, 36));
+        assert_eq!(canvas(3), serial);
+        assert_eq!(canvas(16), serial);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::load_wallpaper_pack;
+use crate::remove::check_pack_name;
+
+
+/// Requests from the control endpoint, picked up by the main loop on its
+/// next poll, and the status the loop publishes in return.
+#[derive(Debug, Default)]
+pub struct Control {
+    pub paused: bool,
+    pub reload_requested: bool,
+    pub requested_pack: Option<String>,
+    pub wallpaper_pack: String,
+    pub current_image: Option<PathBuf>,
+    /// How far through the day's schedule the loop is, from 0.0 to 1.0.
+    pub day_progress: f64,
+}
+
+pub type SharedControl = Arc<Mutex<Control>>;
+
+
+/// Whether a `Host` header names this machine, with or without a port.
+#[cfg_attr(not(feature = "control-server"), allow(dead_code))]
+fn is_loopback_host(host: &str) -> bool {
+    let host = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(host, _)| host),
+    };
+
+    host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|x| x.is_loopback())
+}
+
+
+/// Whether a request may act on the daemon. Web pages can send requests to
+/// localhost too, ones from other origins or through a rebound host name are
+/// refused.
+#[cfg_attr(not(feature = "control-server"), allow(dead_code))]
+fn request_allowed(
+    host: Option<&str>,
+    origin: Option<&str>
+) -> bool {
+    let origin_allowed = origin.is_none_or(|x| {
+        x.strip_prefix("http://")
+            .or_else(|| x.strip_prefix("https://"))
+            .is_some_and(is_loopback_host)
+    });
+
+    host.is_some_and(is_loopback_host) && origin_allowed
+}
+
+
+/// Checks that `pack` is a pack in the packs directory that loads, returning
+/// the HTTP status and error to respond with otherwise.
+#[cfg_attr(not(feature = "control-server"), allow(dead_code))]
+fn check_requested_pack(
+    pack: &str,
+    wallpaper_packs_dir: &String,
+    wallpaper_pack_config_name: &String
+) -> Result<(), (&'static str, String)> {
+    check_pack_name(pack).map_err(|e| ("400 Bad Request", e))?;
+
+    load_wallpaper_pack(wallpaper_packs_dir, &pack.to_string(), wallpaper_pack_config_name)
+        .map(|_| ())
+        .map_err(|e| ("404 Not Found", e))
+}
+
+
+#[cfg(feature = "control-server")]
+mod server {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    use serde::Serialize;
+
+    use super::{check_requested_pack, request_allowed, SharedControl};
+
+
+    /// How long a client may take to send its request or read the response,
+    /// connections are served one at a time so a stalled one holds up the rest.
+    const STREAM_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Requests are read up to this size, the rest is ignored.
+    const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+
+    #[derive(Serialize)]
+    struct StatusResponse<'a> {
+        status: &'a str,
+        pack: &'a str,
+        image: Option<String>,
+        day_progress: f64,
+    }
+
+
+    fn respond(stream: &mut TcpStream, status: &str, body: &str) {
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+
+        let _ = stream.write_all(response.as_bytes());
+    }
+
+
+    fn respond_error(stream: &mut TcpStream, status: &str, error: &str) {
+        let body = serde_json::json!({ "error": error }).to_string();
+        respond(stream, status, &body);
+    }
+
+
+    fn handle(
+        mut stream: TcpStream,
+        control: &SharedControl,
+        wallpaper_packs_dir: &String,
+        wallpaper_pack_config_name: &String
+    ) {
+        let timeouts_set = stream
+            .set_read_timeout(Some(STREAM_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(STREAM_TIMEOUT)));
+
+        if timeouts_set.is_err() {
+            return;
+        }
+
+        let mut reader = BufReader::new((&stream).take(MAX_REQUEST_BYTES));
+        let mut request_line = String::new();
+
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+
+        // Only the headers telling where the request comes from are needed,
+        // bodies carry nothing the endpoints use.
+        let (mut host, mut origin) = (None, None);
+        let mut header = String::new();
+        while reader.read_line(&mut header).is_ok_and(|x| x > 2) {
+            if let Some((name, value)) = header.split_once(':') {
+                let value = Some(value.trim().to_string());
+
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "host" => host = value,
+                    "origin" => origin = value,
+                    _ => {}
+                }
+            }
+
+            header.clear();
+        }
+
+        if !request_allowed(host.as_deref(), origin.as_deref()) {
+            respond_error(&mut stream, "403 Forbidden", "requests are only accepted from localhost");
+            return;
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+        let Ok(mut control) = control.lock() else {
+            respond(&mut stream, "500 Internal Server Error", r#"{"error":"control state is poisoned"}"#);
+            return;
+        };
+
+        match (method, path) {
+            ("GET", "/status") => {
+                let body = serde_json::to_string(&StatusResponse {
+                    status: if control.paused { "paused" } else { "running" },
+                    pack: &control.wallpaper_pack,
+                    image: control.current_image.as_ref().map(|x| x.to_string_lossy().to_string()),
+                    day_progress: control.day_progress,
+                }).unwrap_or_default();
+
+                respond(&mut stream, "200 OK", &body);
+            }
+            ("POST", "/reload") => {
+                control.reload_requested = true;
+                respond(&mut stream, "200 OK", r#"{"ok":true}"#);
+            }
+            ("POST", "/pause") => {
+                control.paused = true;
+                respond(&mut stream, "200 OK", r#"{"ok":true}"#);
+            }
+            ("POST", "/resume") => {
+                control.paused = false;
+                respond(&mut stream, "200 OK", r#"{"ok":true}"#);
+            }
+            ("POST", path) if path.starts_with("/pack/") => {
+                let pack = &path["/pack/".len()..];
+
+                match check_requested_pack(pack, wallpaper_packs_dir, wallpaper_pack_config_name) {
+                    Ok(()) => {
+                        control.requested_pack = Some(pack.to_string());
+                        respond(&mut stream, "200 OK", r#"{"ok":true}"#);
+                    }
+                    Err((status, error)) => respond_error(&mut stream, status, &error),
+                }
+            }
+            _ => respond(&mut stream, "404 Not Found", r#"{"error":"unknown endpoint"}"#),
+        }
+    }
+
+
+    pub fn serve(
+        address: &str,
+        control: SharedControl,
+        wallpaper_packs_dir: String,
+        wallpaper_pack_config_name: String
+    ) -> Result<(), String> {
+        let address: SocketAddr = address
+            .parse()
+            .map_err(|e| format!("Invalid control address '{address}': {e}"))?;
+
+        if !address.ip().is_loopback() {
+            return Err(format!("The control endpoint only binds to localhost, not {address}."));
+        }
+
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("Unable to bind the control endpoint to {address}: {e}"))?;
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                handle(stream, &control, &wallpaper_packs_dir, &wallpaper_pack_config_name);
+            }
+        });
+
+        Ok(())
+    }
+}
+
+
+/// Starts the HTTP control endpoint on a background thread. It only listens
+/// on loopback addresses and refuses requests from web pages of other
+/// origins, anyone else able to reach it can change the wallpaper.
+///
+/// `GET /status` reports the pack, image, how far through the day it is and
+/// whether playback is paused, `POST /reload`, `/pause`, `/resume` and
+/// `/pack/<name>` act on the daemon.
+#[cfg(feature = "control-server")]
+pub fn serve(
+    address: &str,
+    control: SharedControl,
+    wallpaper_packs_dir: String,
+    wallpaper_pack_config_name: String
+) -> Result<(), String> {
+    server::serve(address, control, wallpaper_packs_dir, wallpaper_pack_config_name)
+}
+
+
+#[cfg(not(feature = "control-server"))]
+pub fn serve(
+    _address: &str,
+    _control: SharedControl,
+    _wallpaper_packs_dir: String,
+    _wallpaper_pack_config_name: String
+) -> Result<(), String> {
+    Err("The control endpoint requires the `control-server` feature.".to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn only_requests_from_localhost_are_allowed() {
+        assert!(request_allowed(Some("127.0.0.1:7878"), None));
+        assert!(request_allowed(Some("localhost:7878"), Some("http://localhost:7878")));
+        assert!(request_allowed(Some("[::1]:7878"), Some("http://[::1]:7878")));
+
+        assert!(!request_allowed(None, None));
+        assert!(!request_allowed(Some("evil.example:7878"), None));
+        assert!(!request_allowed(Some("127.0.0.1:7878"), Some("https://evil.example")));
+        assert!(!request_allowed(Some("127.0.0.1:7878"), Some("null")));
+    }
+
+
+    #[test]
+    fn requested_packs_have_to_load() {
+        let wallpaper_packs_dir = std::env::temp_dir()
+            .join(format!("wallpaper_changer_control_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&wallpaper_packs_dir);
+        std::fs::create_dir_all(wallpaper_packs_dir.join("photos")).unwrap();
+        std::fs::create_dir_all(wallpaper_packs_dir.join("empty")).unwrap();
+        std::fs::write(wallpaper_packs_dir.join("photos").join("wallpaper_pack_config.toml"), "noon = [\"day.png\"]").unwrap();
+
+        let wallpaper_packs_dir = wallpaper_packs_dir.to_string_lossy().to_string();
+        let config_name = "wallpaper_pack_config.toml".to_string();
+        let status = |pack: &str| {
+            check_requested_pack(pack, &wallpaper_packs_dir, &config_name).map_err(|(status, _)| status)
+        };
+
+        assert_eq!(status("photos"), Ok(()));
+        assert_eq!(status("empty"), Err("404 Not Found"));
+        assert_eq!(status("missing"), Err("404 Not Found"));
+        assert_eq!(status("."), Err("400 Bad Request"));
+        assert_eq!(status(".."), Err("400 Bad Request"));
+        assert_eq!(status("photos/.."), Err("400 Bad Request"));
+        assert_eq!(status(""), Err("400 Bad Request"));
+
+        std::fs::remove_dir_all(&wallpaper_packs_dir).unwrap();
+    }
+}
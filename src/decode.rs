@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::WallpaperPackConfig;
+
+
+/// What to do with pack images that exist but can't be decoded.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodeCheck {
+    /// Images aren't decoded up front, the backend gets them as they are.
+    #[default]
+    Off,
+    /// Undecodable images are reported and left out of the schedule, the
+    /// images around them share their time.
+    Skip,
+    /// An undecodable image is an error.
+    Fatal,
+}
+
+
+#[cfg(feature = "image")]
+fn decode(image: &Path) -> Result<(), String> {
+    image::open(image)
+        .map(|_| ())
+        .map_err(|e| format!("Unable to decode image {}: {e}", image.display()))
+}
+
+
+#[cfg(not(feature = "image"))]
+fn decode(_image: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+
+/// Decodes pack images, remembering the outcome until the file is modified
+/// so a day rollover doesn't decode the whole pack again.
+#[derive(Debug, Default)]
+pub struct DecodeChecker {
+    checked: HashMap<PathBuf, (Option<SystemTime>, Result<(), String>)>,
+}
+
+impl DecodeChecker {
+    fn check(&mut self, image: &Path) -> Result<(), String> {
+        let modified = std::fs::metadata(image).and_then(|x| x.modified()).ok();
+
+        if let Some((checked_modified, result)) = self.checked.get(image) {
+            if *checked_modified == modified {
+                return result.clone();
+            }
+        }
+
+        let result = decode(image);
+        self.checked.insert(image.to_path_buf(), (modified, result.clone()));
+
+        result
+    }
+
+    /// Applies `decode_check` to every image of a resolved pack, removing the
+    /// ones that fail to decode when skipping.
+    pub fn filter_pack(
+        &mut self,
+        decode_check: DecodeCheck,
+        wallpaper_pack_config: &mut WallpaperPackConfig,
+        wallpaper_pack_dir: &String
+    ) -> Result<(), String> {
+        if decode_check == DecodeCheck::Off {
+            return Ok(());
+        }
+
+        for phase_list in wallpaper_pack_config.phase_lists_mut() {
+            let mut kept = Vec::with_capacity(phase_list.len());
+
+            for entry in phase_list.drain(..) {
+                match self.check(&Path::new(wallpaper_pack_dir).join(entry.path())) {
+                    Ok(_) => kept.push(entry),
+                    Err(e) if decode_check == DecodeCheck::Fatal => return Err(e),
+                    Err(e) => eprintln!("{e}. Skipping it."),
+                }
+            }
+
+            *phase_list = kept;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "image")]
+    #[test]
+    fn truncated_images_are_skipped_or_fatal() {
+        use image::{Rgba, RgbaImage};
+
+        use super::*;
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_decode_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        RgbaImage::from_pixel(32, 32, Rgba([10, 20, 30, 255])).save(dir.join("good.png")).unwrap();
+        let bytes = std::fs::read(dir.join("good.png")).unwrap();
+        std::fs::write(dir.join("truncated.png"), &bytes[..bytes.len() / 2]).unwrap();
+
+        let wallpaper_pack_dir = dir.to_string_lossy().to_string();
+        let pack_config = || -> WallpaperPackConfig {
+            toml::from_str("noon = [\"good.png\", \"truncated.png\"]").unwrap()
+        };
+        let mut checker = DecodeChecker::default();
+
+        let mut off = pack_config();
+        checker.filter_pack(DecodeCheck::Off, &mut off, &wallpaper_pack_dir).unwrap();
+        assert_eq!(off.noon.len(), 2);
+
+        let mut skipped = pack_config();
+        checker.filter_pack(DecodeCheck::Skip, &mut skipped, &wallpaper_pack_dir).unwrap();
+        assert_eq!(skipped.noon.iter().map(|x| x.path()).collect::<Vec<_>>(), ["good.png"]);
+
+        let error = checker.filter_pack(DecodeCheck::Fatal, &mut pack_config(), &wallpaper_pack_dir).unwrap_err();
+        assert!(error.contains("truncated.png"), "{error}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// How often the dwell checks for Ctrl+C.
+const TERMINATE_CHECK: Duration = Duration::from_millis(100);
+
+
+/// Sleeps for `dwell`, returning early with `false` once `terminate` is set.
+pub fn dwell(
+    dwell: Duration,
+    terminate: &AtomicBool
+) -> bool {
+    let start = Instant::now();
+
+    while start.elapsed() < dwell {
+        if terminate.load(Ordering::SeqCst) {
+            return false;
+        }
+        thread::sleep(TERMINATE_CHECK.min(dwell - start.elapsed()));
+    }
+
+    !terminate.load(Ordering::SeqCst)
+}
+
+
+/// Sets `images` one after the other, each for `dwell_seconds`, starting over
+/// after the last one until `terminate` is set. The wallpaper from before is
+/// set again on the way out.
+pub fn run(
+    images: &[PathBuf],
+    dwell_seconds: u64,
+    terminate: Arc<AtomicBool>
+) -> Result<(), String> {
+    if images.is_empty() {
+        return Err("The pack has no images to show.".to_string());
+    }
+
+    let previous = wallpaper::get()
+        .map_err(|e| format!("Unable to read the current wallpaper, it couldn't be restored: {e}"))?;
+
+    println!(
+        "Showing {} image(s) for {dwell_seconds}s each, press Ctrl+C to stop.",
+        images.len()
+    );
+
+    let mut result = Ok(());
+
+    'demo: loop {
+        for (index, image) in images.iter().enumerate() {
+            let Some(image_str) = image.to_str() else {
+                eprintln!("Skipping {}, the path isn't valid UTF-8.", image.display());
+                continue;
+            };
+
+            println!("  {}/{} {}", index + 1, images.len(), image.display());
+
+            if let Err(e) = wallpaper::set_from_path(image_str) {
+                result = Err(format!("Unable to set wallpaper {image_str}: {e}"));
+                break 'demo;
+            }
+
+            if !dwell(Duration::from_secs(dwell_seconds), &terminate) {
+                break 'demo;
+            }
+        }
+    }
+
+    wallpaper::set_from_path(&previous)
+        .map_err(|e| format!("Unable to restore wallpaper {previous}: {e}"))?;
+
+    result
+}
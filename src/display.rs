@@ -0,0 +1,49 @@
+/// Whether at least one display is attached, used to bail out early on
+/// headless machines instead of failing on the first wallpaper change.
+#[cfg(feature = "display-info")]
+pub fn displays_available() -> bool {
+    display_info::DisplayInfo::all().is_ok_and(|x| !x.is_empty())
+}
+
+
+#[cfg(not(feature = "display-info"))]
+pub fn displays_available() -> bool {
+    wallpaper::get().is_ok()
+}
+
+
+/// Width and height of the primary monitor, when it can be determined.
+#[cfg(feature = "display-info")]
+pub fn primary_monitor_size() -> Option<(u32, u32)> {
+    display_info::DisplayInfo::all()
+        .ok()?
+        .into_iter()
+        .find(|x| x.is_primary)
+        .map(|x| (x.width, x.height))
+}
+
+
+#[cfg(not(feature = "display-info"))]
+pub fn primary_monitor_size() -> Option<(u32, u32)> {
+    None
+}
+
+
+/// Id, position and size of a display.
+pub type DisplayGeometry = (u32, i32, i32, u32, u32);
+
+
+/// Position and size of every attached display, compared between polls to
+/// notice monitors being plugged in, removed or rearranged.
+#[cfg(feature = "display-info")]
+pub fn topology() -> Option<Vec<DisplayGeometry>> {
+    display_info::DisplayInfo::all()
+        .ok()
+        .map(|x| x.iter().map(|d| (d.id, d.x, d.y, d.width, d.height)).collect())
+}
+
+
+#[cfg(not(feature = "display-info"))]
+pub fn topology() -> Option<Vec<DisplayGeometry>> {
+    None
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+
+/// How an interpolation progresses over its duration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    /// The same change every step.
+    #[default]
+    Linear,
+    /// Starts and ends gently, smoothstep.
+    #[serde(alias = "ease-in-out")]
+    EaseInOut,
+    /// Like `ease_in_out` but lingers longer on both images, with the change
+    /// packed into the middle.
+    Cubic,
+}
+
+impl Easing {
+    /// The eased progress at `t`, both from 0.0 to 1.0. Every curve starts
+    /// at 0.0, is halfway at 0.5 and ends at 1.0.
+    #[cfg_attr(not(feature = "image"), allow(dead_code))]
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Easing::Cubic => match t < 0.5 {
+                true => 4.0 * t * t * t,
+                false => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    const CURVES: [Easing; 3] = [Easing::Linear, Easing::EaseInOut, Easing::Cubic];
+
+
+    #[test]
+    fn every_curve_starts_at_zero_is_halfway_in_the_middle_and_ends_at_one() {
+        for easing in CURVES {
+            assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(0.5), 0.5, "{easing:?}");
+            assert_eq!(easing.apply(1.0), 1.0, "{easing:?}");
+            // Progress outside the transition is clamped.
+            assert_eq!(easing.apply(-1.0), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(2.0), 1.0, "{easing:?}");
+        }
+    }
+
+
+    #[test]
+    fn every_curve_is_monotonic() {
+        for easing in CURVES {
+            let steps = (0..=1000).map(|x| easing.apply(x as f64 / 1000.0)).collect::<Vec<f64>>();
+
+            assert!(steps.windows(2).all(|x| x[0] <= x[1]), "{easing:?}");
+        }
+    }
+
+
+    #[test]
+    fn eased_curves_start_and_end_gently() {
+        assert!(Easing::EaseInOut.apply(0.1) < Easing::Linear.apply(0.1));
+        assert!(Easing::Cubic.apply(0.1) < Easing::EaseInOut.apply(0.1));
+        assert!(Easing::EaseInOut.apply(0.9) > Easing::Linear.apply(0.9));
+        assert!(Easing::Cubic.apply(0.9) > Easing::EaseInOut.apply(0.9));
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::processing;
+#[cfg(feature = "image")]
+use crate::strips;
+
+
+/// Effects applied to a phase's images before they are set, e.g.
+/// `[effects.midnight]` with `blur = 8.0`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct Effects {
+    /// Gaussian blur with this standard deviation in pixels.
+    pub blur: Option<f32>,
+}
+
+impl Effects {
+    fn is_noop(&self) -> bool {
+        !matches!(self.blur, Some(blur) if blur > 0.0)
+    }
+}
+
+
+/// The effects configured for `phase`, matching phase and anchor names
+/// regardless of case.
+pub fn for_phase(
+    effects: &BTreeMap<String, Effects>,
+    phase: Option<&str>
+) -> Option<Effects> {
+    let phase = phase?;
+
+    effects
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(phase))
+        .map(|(_, x)| *x)
+}
+
+
+#[cfg(feature = "image")]
+fn apply(
+    image: &Path,
+    effects: Effects,
+    strip_rows: u32,
+    output: &Path
+) -> Result<(), String> {
+    let blur = effects.blur.filter(|x| *x > 0.0);
+
+    if strip_rows > 0 {
+        // The blur reads up to twice the deviation away, strips see that
+        // much of their neighbours so they come out as if blurred whole.
+        let margin = blur.map(|x| (2.0 * x).ceil() as u32 + 2).unwrap_or_default();
+
+        return strips::map(image, output, strip_rows, margin, false, |strip| match blur {
+            Some(blur) => image::imageops::blur(&strip, blur),
+            None => strip,
+        });
+    }
+
+    let mut processed = image::open(image)
+        .map_err(|e| format!("Unable to open image {}: {e}", image.display()))?;
+
+    if let Some(blur) = blur {
+        processed = processed.blur(blur);
+    }
+
+    processed
+        .save_with_format(output, image::ImageFormat::Png)
+        .map_err(|e| format!("Unable to save image with effects: {e}"))
+}
+
+
+#[cfg(not(feature = "image"))]
+fn apply(
+    _image: &Path,
+    _effects: Effects,
+    _strip_rows: u32,
+    _output: &Path
+) -> Result<(), String> {
+    Err("Image effects require the `image` feature.".to_string())
+}
+
+
+/// `image` with `effects` applied, cached like gamma corrected images under
+/// the image, its modification time and the effects. Without effects, or
+/// when they can't be applied, the image itself is used. `strip_rows` works
+/// like for gamma correction.
+pub fn applied_image(
+    image: &Path,
+    effects: Option<Effects>,
+    strip_rows: u32,
+    effects_dir: &Path
+) -> PathBuf {
+    let Some(effects) = effects.filter(|x| !x.is_noop()) else {
+        return image.to_path_buf();
+    };
+
+    if cfg!(not(feature = "image")) || !image.is_file() {
+        return image.to_path_buf();
+    }
+
+    processing::cached_output(image, &format!("{effects:?}"), effects_dir, |output| {
+        apply(image, effects, strip_rows, output)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn effects_apply_only_to_their_phase() {
+        let effects = BTreeMap::from([("midnight".to_string(), Effects { blur: Some(4.0) })]);
+
+        assert_eq!(for_phase(&effects, Some("Midnight")), Some(Effects { blur: Some(4.0) }));
+        assert_eq!(for_phase(&effects, Some("noon")), None);
+        assert_eq!(for_phase(&effects, None), None);
+
+        let image = Path::new("day.png");
+        assert_eq!(applied_image(image, for_phase(&effects, Some("noon")), 0, Path::new("effects")), image);
+    }
+
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn the_configured_phase_gets_a_blurred_copy() {
+        use image::{Rgba, RgbaImage};
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_effects_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let image = dir.join("day.png");
+        RgbaImage::from_fn(16, 16, |x, _| Rgba([if x < 8 { 0 } else { 255 }, 0, 0, 255])).save(&image).unwrap();
+
+        let effects = BTreeMap::from([("midnight".to_string(), Effects { blur: Some(2.0) })]);
+        let applied = |phase| applied_image(&image, for_phase(&effects, Some(phase)), 0, &dir.join("effects"));
+
+        assert_eq!(applied("noon"), image);
+
+        let blurred = image::open(applied("midnight")).unwrap().to_rgba8();
+        let edge = blurred.get_pixel(7, 8)[0];
+        assert!(edge > 0 && edge < 255, "{edge}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
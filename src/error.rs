@@ -0,0 +1,87 @@
+use std::fmt;
+
+
+/// Failure classes of the program. Each one exits with its own code so
+/// scripts can tell a broken config apart from a missing pack:
+///
+/// | Code | Class                                      |
+/// |------|--------------------------------------------|
+/// | 1    | anything else                              |
+/// | 2    | config error                               |
+/// | 3    | pack error                                 |
+/// | 4    | astronomy error                            |
+/// | 5    | backend error, the wallpaper can't be set  |
+/// | 6    | no displays detected                       |
+/// | 130  | Ctrl+C pressed again while shutting down   |
+#[derive(Debug, Clone, PartialEq)]
+pub enum WallpaperChangerError {
+    Config(String),
+    Pack(String),
+    Astronomy(String),
+    Backend(String),
+    NoDisplays,
+    Other(String),
+}
+
+
+impl WallpaperChangerError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WallpaperChangerError::Other(_) => 1,
+            WallpaperChangerError::Config(_) => 2,
+            WallpaperChangerError::Pack(_) => 3,
+            WallpaperChangerError::Astronomy(_) => 4,
+            WallpaperChangerError::Backend(_) => 5,
+            WallpaperChangerError::NoDisplays => 6,
+        }
+    }
+
+    /// Stable name of the failure class, printed in front of the message.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WallpaperChangerError::Config(_) => "config",
+            WallpaperChangerError::Pack(_) => "pack",
+            WallpaperChangerError::Astronomy(_) => "astronomy",
+            WallpaperChangerError::Backend(_) => "backend",
+            WallpaperChangerError::NoDisplays => "display",
+            WallpaperChangerError::Other(_) => "other",
+        }
+    }
+
+    /// Prints the error to stderr as `error[<kind>]: <message>` and exits with
+    /// the class's exit code.
+    pub fn exit(&self) -> ! {
+        eprintln!("error[{}]: {self}", self.kind());
+        std::process::exit(self.exit_code());
+    }
+}
+
+
+impl fmt::Display for WallpaperChangerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WallpaperChangerError::Config(x)
+            | WallpaperChangerError::Pack(x)
+            | WallpaperChangerError::Astronomy(x)
+            | WallpaperChangerError::Backend(x)
+            | WallpaperChangerError::Other(x) => write!(f, "{x}"),
+            WallpaperChangerError::NoDisplays => {
+                write!(f, "No displays detected, there is nothing to set the wallpaper on.")
+            }
+        }
+    }
+}
+
+
+impl From<String> for WallpaperChangerError {
+    fn from(value: String) -> Self {
+        WallpaperChangerError::Other(value)
+    }
+}
+
+
+impl From<&str> for WallpaperChangerError {
+    fn from(value: &str) -> Self {
+        WallpaperChangerError::Other(value.to_string())
+    }
+}
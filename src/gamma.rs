@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+
+use crate::processing;
+#[cfg(feature = "image")]
+use crate::strips;
+
+
+#[cfg(feature = "image")]
+fn correct(
+    image: &Path,
+    gamma: f64,
+    strip_rows: u32,
+    output: &Path
+) -> Result<(), String> {
+    // Every channel value maps to the same output, so the curve is only
+    // evaluated 256 times instead of once per pixel.
+    let lookup = (0..=255)
+        .map(|x| ((x as f64 / 255.0).powf(1.0 / gamma) * 255.0).round() as u8)
+        .collect::<Vec<u8>>();
+
+    let correct_pixels = |mut corrected: image::RgbaImage| {
+        for pixel in corrected.pixels_mut() {
+            for channel in 0..3 {
+                pixel[channel] = lookup[pixel[channel] as usize];
+            }
+        }
+
+        corrected
+    };
+
+    if strip_rows > 0 {
+        return strips::map(image, output, strip_rows, 0, false, correct_pixels);
+    }
+
+    let corrected = image::open(image)
+        .map_err(|e| format!("Unable to open image {}: {e}", image.display()))?
+        .to_rgba8();
+
+    correct_pixels(corrected)
+        .save_with_format(output, image::ImageFormat::Png)
+        .map_err(|e| format!("Unable to save gamma corrected image: {e}"))
+}
+
+
+#[cfg(not(feature = "image"))]
+fn correct(
+    _image: &Path,
+    _gamma: f64,
+    _strip_rows: u32,
+    _output: &Path
+) -> Result<(), String> {
+    Err("Gamma correction requires the `image` feature.".to_string())
+}
+
+
+/// `image` with its channels raised to `1 / gamma`, so values above 1.0
+/// brighten it and values below darken it. 1.0 leaves the image untouched.
+///
+/// Corrected copies are cached by `processing::cached_output` under the gamma.
+/// With `strip_rows` above 0 the image is corrected that many rows at a time.
+pub fn corrected_image(
+    image: &Path,
+    gamma: f64,
+    strip_rows: u32,
+    gamma_dir: &Path
+) -> PathBuf {
+    if gamma == 1.0 || gamma <= 0.0 || cfg!(not(feature = "image")) || !image.is_file() {
+        return image.to_path_buf();
+    }
+
+    processing::cached_output(image, &gamma.to_string(), gamma_dir, |output| {
+        correct(image, gamma, strip_rows, output)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "image")]
+    #[test]
+    fn a_known_pixel_is_brightened_by_the_gamma() {
+        use image::{Rgba, RgbaImage};
+
+        use super::*;
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_gamma_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let image = dir.join("day.png");
+        RgbaImage::from_pixel(4, 3, Rgba([64, 128, 255, 100])).save(&image).unwrap();
+
+        assert_eq!(corrected_image(&image, 1.0, 0, &dir.join("gamma")), image);
+
+        let corrected = corrected_image(&image, 2.2, 0, &dir.join("gamma"));
+        assert_ne!(corrected, image);
+
+        // (64 / 255) ^ (1 / 2.2) * 255 is 136, alpha is left alone.
+        let corrected = image::open(corrected).unwrap().to_rgba8();
+        assert_eq!(corrected.get_pixel(0, 0), &Rgba([136, 186, 255, 100]));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+
+
+
+fn validate_coordinates(latitude: f64, longitude: f64) -> Result<(f64, f64), String> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(format!("Latitude {latitude} is out of range [-90, 90]."));
+    }
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err(format!("Longitude {longitude} is out of range [-180, 180]."));
+    }
+
+    Ok((latitude, longitude))
+}
+
+
+fn parse_geo_uri(uri: &str) -> Result<(f64, f64), String> {
+    let coordinates = uri["geo:".len()..]
+        .split(';')
+        .next()
+        .unwrap_or_default();
+
+    let mut parts = coordinates.split(',');
+
+    let latitude = parts
+        .next()
+        .and_then(|x| x.trim().parse::<f64>().ok())
+        .ok_or_else(|| format!("Unable to parse latitude from geo URI: {uri}"))?;
+    let longitude = parts
+        .next()
+        .and_then(|x| x.trim().parse::<f64>().ok())
+        .ok_or_else(|| format!("Unable to parse longitude from geo URI: {uri}"))?;
+
+    validate_coordinates(latitude, longitude)
+}
+
+
+fn parse_gpx_file(path: &Path) -> Result<(f64, f64), String> {
+    let gpx = fs::read_to_string(path)
+        .ok()
+        .ok_or_else(|| format!("Unable to read GPX file: {}", path.display()))?;
+
+    let document = roxmltree::Document::parse(&gpx)
+        .ok()
+        .ok_or_else(|| format!("Unable to parse GPX file: {}", path.display()))?;
+
+    let waypoint = document
+        .descendants()
+        .find(|x| x.tag_name().name() == "wpt")
+        .ok_or_else(|| format!("GPX file contains no waypoints: {}", path.display()))?;
+
+    let latitude = waypoint
+        .attribute("lat")
+        .and_then(|x| x.trim().parse::<f64>().ok())
+        .ok_or_else(|| format!("Unable to parse waypoint latitude in: {}", path.display()))?;
+    let longitude = waypoint
+        .attribute("lon")
+        .and_then(|x| x.trim().parse::<f64>().ok())
+        .ok_or_else(|| format!("Unable to parse waypoint longitude in: {}", path.display()))?;
+
+    validate_coordinates(latitude, longitude)
+}
+
+
+/// Parses a `geo:` URI (`geo:<lat>,<lon>`) or a path to a GPX file, whose
+/// first waypoint is used, into a `(latitude, longitude)` pair.
+pub fn parse_location(location: &str) -> Result<(f64, f64), String> {
+    let location = location.trim();
+
+    if location.to_lowercase().starts_with("geo:") {
+        return parse_geo_uri(location);
+    }
+
+    parse_gpx_file(Path::new(location))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn parses_geo_uris() {
+        assert_eq!(parse_location("geo:45.815,15.982"), Ok((45.815, 15.982)));
+        assert_eq!(parse_location(" GEO:-33.9, 18.4;u=35 "), Ok((-33.9, 18.4)));
+        assert!(parse_location("geo:91,0").is_err());
+        assert!(parse_location("geo:0,-181").is_err());
+        assert!(parse_location("geo:45.815").is_err());
+    }
+
+
+    #[test]
+    fn uses_the_first_waypoint_of_a_gpx_file() {
+        let gpx_path = std::env::temp_dir().join(format!("wallpaper_changer_location_{}.gpx", std::process::id()));
+        fs::write(&gpx_path, r#"<?xml version="1.0"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+                <wpt lat="45.815" lon="15.982"><name>Zagreb</name></wpt>
+                <wpt lat="43.508" lon="16.440"><name>Split</name></wpt>
+            </gpx>
+        "#).unwrap();
+
+        assert_eq!(parse_location(&gpx_path.to_string_lossy()), Ok((45.815, 15.982)));
+
+        fs::write(&gpx_path, r#"<gpx version="1.1"></gpx>"#).unwrap();
+        assert!(parse_location(&gpx_path.to_string_lossy()).is_err());
+
+        fs::remove_file(&gpx_path).unwrap();
+        assert!(parse_location(&gpx_path.to_string_lossy()).is_err());
+    }
+}
@@ -1,389 +1,4113 @@
-use std::{thread, time};
-use std::collections::HashMap;
+use std::time;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
 
-use chrono::{Duration, Local, TimeZone};
-use confy;
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use clap::{CommandFactory, Parser};
 use directories::ProjectDirs;
-use geodate::{moon_transit, sun_transit};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use toml;
-use ctrlc;
+
+mod alpha;
+mod ambient;
+mod anchor;
+mod astronomy;
+mod aspect_ratio;
+mod autostart;
+mod backend;
+mod bench;
+mod change_log;
+mod cli;
+mod color_grade;
+mod composite;
+mod control;
+mod decode;
+mod demo;
+mod display;
+mod easing;
+mod effects;
+mod error;
+mod gamma;
+mod location;
+mod merge;
+mod migration;
+mod monitor;
+mod natural_sort;
+mod orientation;
+mod overlay;
+mod overrides;
+mod power;
+mod processing;
+mod quiet_hours;
+mod random;
+mod remote;
+mod remove;
+mod reorder;
+mod report;
+mod route;
+mod selection;
+mod solar;
+mod solid_color;
+mod state;
+mod strips;
+mod timeline;
+mod transition;
+mod updates;
+mod upgrade;
+mod watch;
+mod weather;
+mod weekly_blend;
+mod workspace;
+
+use ambient::{AmbientLight, AmbientSelector};
+use anchor::AnchorTime;
+use astronomy::{AstronomyProvider, FixedAstronomy, Geodate};
+use change_log::{ChangeLog, ChangeLogConfig, ChangeRecord};
+use cli::{Cli, Command};
+use color_grade::ColorGrade;
+use composite::{Composite, CompositeRegion};
+use control::SharedControl;
+use decode::{DecodeCheck, DecodeChecker};
+use effects::Effects;
+use overlay::Overlay;
+use error::WallpaperChangerError;
+use monitor::{Change, MonitorSchedule, Output, PackDirChange};
+use power::PowerSource;
+use processing::{ImageProcessor, Job, Processed, ProcessingDirs};
+use quiet_hours::QuietHours;
+use random::Rng;
+use route::Waypoint;
+use state::{DaemonStatus, WallpaperChangerState};
+use transition::Transition;
+use watch::FileWatcher;
+use weather::Weather;
+use weekly_blend::{BlendPack, WeeklyBlend};
+
+const CATCH_UP_STEP_MILLIS: u64 = 500;
+const MAX_TIME_OFFSET_MINUTES: i64 = 12 * 60;
+/// How long before a day ends the next one is computed ahead, late enough
+/// for its directory listings to still be current at the rollover.
+const PRECOMPUTE_LEAD_SECONDS: i64 = 5 * 60;
+/// Exit code used when Ctrl+C is pressed again while shutting down.
+const FORCE_EXIT_CODE: i32 = 130;
+
+static TERMINATE_LOOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum ScheduleMode {
+    /// Each phase's images evenly split the time between its boundaries.
+    #[default]
+    Phases,
+    /// All images, in schedule order, evenly split the 24 hours of apparent
+    /// solar time, so the image follows the sun's hour angle continuously.
+    SolarTime,
+}
+
+
+/// How the day's sun and moon events are put in order to form its phases.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum PhaseOrder {
+    /// Midnight, moonset, sunrise, noon, sunset, moonrise, as the pack
+    /// phases are listed. On days the moon rises or sets out of this order
+    /// its phase is left empty.
+    #[default]
+    Fixed,
+    /// By the events' times, each phase lasting until the next event. Where
+    /// the moon rises or sets on the other side of a sun event, e.g. moonrise
+    /// before sunset, its phase moves along instead of ending up with a
+    /// negative span.
+    Sorted,
+}
+
+
+/// Where one day's schedule ends and the next one begins.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum DayBoundary {
+    /// Local calendar midnight, the `midnight` phase starts at 00:00 on the
+    /// clock and the schedule rolls over when the date changes.
+    #[default]
+    Local,
+    /// Solar midnight, when the sun is lowest. Depending on the longitude
+    /// within the time zone and daylight saving time it can be well before
+    /// or after 00:00, the rollover happens then instead.
+    Solar,
+}
+
+
+/// What to do when several schedule slots were missed at once, e.g. after
+/// the machine wakes from sleep.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum CatchUp {
+    /// Go straight to the image for the current time.
+    #[default]
+    Jump,
+    /// Briefly set every missed image in order before the current one, so
+    /// the change log and transitions see the whole sequence.
+    Step,
+}
+
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 struct WallpaperChangerConfig {
+    /// Config format version, older configs are migrated and rewritten on load.
+    version: i64,
     longitude: f64,
     latitude: f64,
+    /// Alternative to `latitude`/`longitude`: a `geo:<lat>,<lon>` URI or a
+    /// path to a GPX file. Takes precedence over the numeric fields.
+    location: Option<String>,
+    /// Where you'll be and when, as `[[route]]` entries with `at`, `latitude`
+    /// and `longitude`. The position is interpolated between them and the
+    /// schedule follows it, outside the route the nearest end is used. Takes
+    /// precedence over `location` and the numeric fields.
+    route: Vec<Waypoint>,
+    /// When empty, the pack a `current` symlink in the packs directory points
+    /// to is used, so packs can be switched with `ln -sfn <pack> current`.
     wallpaper_pack: String,
+    /// A pack defined right here under `[pack]`, with the same fields as a
+    /// `wallpaper_pack_config.toml` and image paths relative to this file.
+    /// It's used instead of the pack named by `wallpaper_pack`, which can
+    /// then be left empty. Packs picked by name elsewhere, like `--pack`, the
+    /// rotation or the power packs, still load from the packs directory.
+    pack: Option<WallpaperPackConfig>,
+    /// Packs to cycle through, advancing by one on every day rollover.
+    /// Takes precedence over `wallpaper_pack` when not empty.
+    pack_rotation: Vec<String>,
+    /// Picks a random pack on every launch instead of `wallpaper_pack`, unless
+    /// a pack is given on the command line or in the environment.
+    random_pack_on_launch: bool,
+    /// Packs `random_pack_on_launch` picks from, every installed pack when empty.
+    random_pack_candidates: Vec<String>,
+    /// Seeds the randomness of every randomized feature, currently only
+    /// `random_pack_on_launch`, so it picks the same every time. Seeded from
+    /// the clock when unset.
+    random_seed: Option<u64>,
+    /// Pack used while running on battery. Needs the `battery` feature.
+    battery_pack: Option<String>,
+    /// Pack used while running on AC power. Needs the `battery` feature.
+    ac_pack: Option<String>,
+    /// When disabled, moonrise/moonset are not computed and the night is
+    /// covered by the `midnight` and `sunset` images alone.
+    use_moon: bool,
+    schedule_mode: ScheduleMode,
+    day_boundary: DayBoundary,
+    catch_up: CatchUp,
+    /// Shifts every sun and moon event by this many minutes, e.g. -60 to show
+    /// the sunrise images an hour before the actual sunrise. At most ±12h.
+    time_offset_minutes: i64,
+    /// Rounds every sun and moon event to the nearest minute. Events that
+    /// would end up in the same minute as a neighbour keep their exact time.
+    round_boundaries_to_minute: bool,
+    phase_order: PhaseOrder,
+    /// Phases and anchors with more images, e.g. from a large directory, show
+    /// only this many, picked evenly from first to last so each gets a
+    /// reasonable time. At least 1.
+    max_images_per_phase: Option<usize>,
+    /// Opt-in: while the sun is below this altitude in degrees (e.g. -6.0 for
+    /// civil twilight) midnight images are shown regardless of the phase.
+    night_sun_altitude_threshold: Option<f64>,
+    /// Leans the pick within the current phase towards brighter or darker
+    /// images by the light an ambient light sensor measures. Needs the
+    /// `ambient-light` feature, without it or a reading the schedule is
+    /// followed as is.
+    ambient_light: Option<AmbientLight>,
+    poll_interval_seconds: u64,
+    /// The wallpaper never changes sooner than this after the last change, a
+    /// change that comes too early waits for the first tick after it.
+    min_change_interval_seconds: u64,
+    transition: Transition,
+    quiet_hours: Option<QuietHours>,
+    change_log: ChangeLogConfig,
+    /// Image set immediately at launch, before the schedule is computed.
+    startup_image: Option<String>,
+    /// File holding the path of the wallpaper currently set, rewritten after
+    /// every change for scripts like pywal to read. Defaults to `current` in
+    /// the cache directory, e.g. `~/.cache/wallpaper_changer_rust/current`.
+    current_wallpaper_file: Option<String>,
+    /// When not empty, each region of the screen shows its own pack and the
+    /// slices are composited into one wallpaper. Needs the `image` feature.
+    composite_regions: Vec<CompositeRegion>,
+    /// Pack per virtual desktop, keyed by the workspace's number counting from
+    /// 1 or by its name. Only XFCE supports this, elsewhere the regular pack is
+    /// set on every workspace. Needs the `xfce-workspaces` feature.
+    workspace_packs: BTreeMap<String, String>,
+    /// Directory for transient files such as transition frames and composite
+    /// canvases. Defaults to a folder in the system temp dir, so it never has
+    /// to share a filesystem with the config or the packs.
+    temp_dir: Option<String>,
+    /// Sets the current image again whenever a display is connected, removed
+    /// or rearranged. Needs the `display-info` feature.
+    reapply_on_display_change: bool,
+    /// Sets the current image again this many seconds after it was last set,
+    /// for desktops that drop the wallpaper without a trace. Disabled at 0.
+    reapply_interval_seconds: u64,
+    /// Address of the HTTP control endpoint, e.g. `127.0.0.1:7878`. Only
+    /// loopback addresses are accepted and it is off when unset. Needs the
+    /// `control-server` feature.
+    control_address: Option<String>,
+    /// Blends every image with its overcast variant, see the pack's
+    /// `overcast_dir`, by the current cloud cover. Needs the `network`
+    /// feature, and the `image` feature to blend instead of picking one.
+    weather: bool,
+    /// Reloads the config and the active pack's config as soon as they are
+    /// saved. The temp dir, change log, control address and composite regions
+    /// still need a restart. Needs the `watch` feature.
+    watch_config_files: bool,
+    /// Decodes every pack image when the schedule is built, to leave out or
+    /// fail on corrupt files. Needs the `image` feature.
+    decode_check: DecodeCheck,
+    /// Gamma correction applied to every image before it is set, above 1.0
+    /// brightens and below darkens. Needs the `image` feature.
+    gamma: f64,
+    /// Images with an alpha channel are composited over this color, e.g.
+    /// `#101018`, before they are set. Needs the `image` feature, without it
+    /// they are set as they are.
+    alpha_background: String,
+    /// Warms images while the sun is low and cools them while it is high,
+    /// following the sun's altitude at the configured location. Needs the
+    /// `image` feature.
+    color_grade: Option<ColorGrade>,
+    /// A second pack the active one turns into over the week, blending each
+    /// image with the one in the same place of the other pack. Needs the
+    /// `image` feature.
+    weekly_blend: Option<WeeklyBlend>,
+    /// Threads decoding and scaling the images of `composite_regions` at once.
+    /// 1 handles them one after the other, more are capped at the number of
+    /// CPUs.
+    processing_threads: usize,
+    /// Flattening, effects, color grading and gamma correction work on this
+    /// many rows at a time and stream their output, so memory stays bounded
+    /// for 8K images and larger. 0 processes whole images. PNGs, as made by
+    /// each step, are read a row at a time, other formats are still decoded
+    /// whole by the first step. Needs the `image` feature.
+    processing_strip_rows: u32,
+    /// Text such as the time or the phase drawn onto every wallpaper. Needs
+    /// the `overlay` feature.
+    overlay: Option<Overlay>,
 }
 
 impl Default for WallpaperChangerConfig {
     fn default() -> Self {
         Self {
+            version: migration::CURRENT_CONFIG_VERSION,
             longitude: 45.71,
             latitude: 15.81,
+            location: None,
+            route: vec![],
             wallpaper_pack: "".to_string(),
+            pack: None,
+            pack_rotation: vec![],
+            random_pack_on_launch: false,
+            random_pack_candidates: vec![],
+            random_seed: None,
+            battery_pack: None,
+            ac_pack: None,
+            use_moon: true,
+            schedule_mode: ScheduleMode::Phases,
+            day_boundary: DayBoundary::Local,
+            catch_up: CatchUp::Jump,
+            time_offset_minutes: 0,
+            round_boundaries_to_minute: false,
+            phase_order: PhaseOrder::Fixed,
+            max_images_per_phase: None,
+            night_sun_altitude_threshold: None,
+            ambient_light: None,
+            poll_interval_seconds: 1,
+            min_change_interval_seconds: 0,
+            transition: Transition::Instant,
+            quiet_hours: None,
+            change_log: ChangeLogConfig::default(),
+            startup_image: None,
+            current_wallpaper_file: None,
+            composite_regions: vec![],
+            workspace_packs: BTreeMap::new(),
+            temp_dir: None,
+            reapply_on_display_change: false,
+            reapply_interval_seconds: 0,
+            control_address: None,
+            weather: false,
+            watch_config_files: false,
+            decode_check: DecodeCheck::Off,
+            gamma: 1.0,
+            alpha_background: "#000000".to_string(),
+            color_grade: None,
+            weekly_blend: None,
+            processing_threads: 1,
+            processing_strip_rows: 0,
+            overlay: None,
         }
     }
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
-struct WallpaperPackConfig {
-    midnight: Vec<String>,
-    sunrise: Vec<String>,
-    noon: Vec<String>,
-    sunset: Vec<String>,
-    moonrise: Vec<String>,
-    moonset: Vec<String>
+/// A phase entry, either a bare image or an image with a fixed display time.
+/// Untimed images of a phase evenly share whatever time the timed ones leave.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum PackImage {
+    Path(String),
+    Timed {
+        image: String,
+        duration_seconds: i64,
+    },
 }
 
+impl PackImage {
+    fn path(&self) -> &str {
+        match self {
+            Self::Path(image) => image,
+            Self::Timed { image, .. } => image,
+        }
+    }
 
-#[derive(Hash, PartialEq, Eq, Debug)]
-enum SunAndMoonKeys {
-    Midnight,
-    Sunrise,
-    Noon,
-    Sunset,
-    Moonrise,
-    Moonset,
-    NextDayMidnight,
-}
+    fn duration_seconds(&self) -> Option<i64> {
+        match self {
+            Self::Path(_) => None,
+            Self::Timed { duration_seconds, .. } => Some(*duration_seconds),
+        }
+    }
 
+    /// The same entry, with its duration if any, pointing at another image.
+    fn with_path(&self, path: String) -> Self {
+        match self {
+            Self::Path(_) => Self::Path(path),
+            Self::Timed { duration_seconds, .. } => Self::Timed {
+                image: path,
+                duration_seconds: *duration_seconds,
+            },
+        }
+    }
+}
 
-fn get_day_sun_and_moon_position_times(
-    today_posix: i64,
-    longitude: f64,
-    latitude: f64,
-) -> Result<HashMap<SunAndMoonKeys, i64>, String> {
-    let mut sun_and_moon = HashMap::new();
 
-    sun_and_moon.insert(
-        SunAndMoonKeys::Sunrise,
-        sun_transit::get_sunrise(
-            today_posix,
-            longitude,
-            latitude,
-        ).ok_or_else(|| "Can't get sunrise.")?,
-    );
-    sun_and_moon.insert(
-        SunAndMoonKeys::Sunset,
-        sun_transit::get_sunset(
-            today_posix,
-            longitude,
-            latitude,
-        ).ok_or_else(|| "Can't get sunset.")?,
-    );
+/// Extra images shown from a point relative to a sun or moon event until the
+/// next boundary, e.g. a golden hour starting an hour before sunset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TimeAnchor {
+    at: AnchorTime,
+    images: Vec<PackImage>,
+}
 
-    sun_and_moon.insert(
-        SunAndMoonKeys::Noon,
-        sun_transit::get_noon(
-            today_posix,
-            longitude,
-        ),
-    );
-    sun_and_moon.insert(
-        SunAndMoonKeys::Midnight,
-        sun_transit::get_midnight(
-            today_posix,
-            longitude,
-        ),
-    );
 
-    sun_and_moon.insert(
-        SunAndMoonKeys::Moonrise,
-        moon_transit::get_moonrise(
-            today_posix,
-            longitude,
-            latitude,
-        ).ok_or_else(|| "Can't get moonrise.")?,
-    );
-    sun_and_moon.insert(
-        SunAndMoonKeys::Moonset,
-        moon_transit::get_moonset(
-            today_posix,
-            longitude,
-            latitude,
-        ).ok_or_else(|| "Can't get moonset.")?,
-    );
+/// What a pack shows at night, between sunset and sunrise, while the current
+/// phase or anchor has no images.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum NightBehavior {
+    /// Like any other empty phase: the pack's `default_image` if it has one,
+    /// otherwise the images of the neighbouring phases.
+    #[default]
+    Loop,
+    /// The wallpaper that was set when the program started.
+    DesktopDefault,
+    /// A solid color, e.g. `night_behavior = { solid = "#101018" }`. Needs the
+    /// `image` feature.
+    Solid(String),
+}
 
-    sun_and_moon.insert(
-        SunAndMoonKeys::NextDayMidnight,
-        (Local.timestamp_opt(today_posix, 0).unwrap() + Duration::days(1)).timestamp()
-    );
 
-    Ok(sun_and_moon)
+/// Phase entries may name a directory inside the pack, in which case every
+/// image in it is used, in natural filename order. Entries may also be
+/// `file://` or `http(s)://` URLs, the latter are downloaded to a cache.
+///
+/// Directories and URLs are resolved again every time the schedule is
+/// recomputed, at the latest in the last minutes before each rollover, so
+/// images added to a directory show up from the next day on. Explicit image
+/// lists only change when the pack config is reloaded.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+struct WallpaperPackConfig {
+    midnight: Vec<PackImage>,
+    sunrise: Vec<PackImage>,
+    noon: Vec<PackImage>,
+    sunset: Vec<PackImage>,
+    moonrise: Vec<PackImage>,
+    moonset: Vec<PackImage>,
+    /// Named anchors woven into the timeline, e.g.
+    /// `[anchors.golden_hour]` with `at = "sunset - 60m"` and its own `images`.
+    anchors: BTreeMap<String, TimeAnchor>,
+    /// Subdirectories holding the pack's images cropped for other aspect
+    /// ratios, keyed by ratio, e.g. `"21:9" = "ultrawide"`. The variant matching
+    /// the primary monitor is used, images it lacks fall back to the default
+    /// ones. As the same image is set on every monitor, other monitors with a
+    /// different ratio get the primary one's variant. Needs the `display-info`
+    /// feature.
+    aspect_variants: BTreeMap<String, String>,
+    /// Shown while the current phase or anchor has no images, instead of
+    /// stretching the neighbouring phases over the gap.
+    default_image: Option<String>,
+    /// For daytime-only packs, what to show at night instead of their images.
+    night_behavior: NightBehavior,
+    /// Subdirectory with overcast versions of the pack's images under the
+    /// same names, used when `weather` is enabled.
+    overcast_dir: Option<String>,
+    /// Overrides the global `poll_interval_seconds` while this pack is active.
+    poll_interval_seconds: Option<u64>,
+    /// Overrides the global `transition` while this pack is active.
+    transition: Option<Transition>,
+    /// Transitions into the images of a phase or anchor, keyed by its name,
+    /// e.g. `[transitions.midnight]` with `style = "instant"`. They take
+    /// precedence over `transition`.
+    transitions: BTreeMap<String, Transition>,
+    /// Overrides the global `phase_order` while this pack is active.
+    phase_order: Option<PhaseOrder>,
+    /// URL of a manifest with the hashes of the pack's images, compared
+    /// against by `check-updates`. Needs the `network` feature.
+    update_manifest: Option<String>,
+    /// Effects for the images of a phase or anchor, keyed by its name, e.g.
+    /// `[effects.midnight]` with `blur = 8.0`. Needs the `image` feature.
+    effects: BTreeMap<String, Effects>,
+    /// Format version of the pack config, packs without one are version 1.
+    /// `upgrade-pack` brings older packs up to date.
+    version: Option<i64>,
 }
 
 
-fn timestamp_splitter(
-    start: i64,
-    end: i64,
-    chunks: i64
-) -> Vec<i64> {
-    let step = (end - start) / chunks;
-
-    (1..chunks + 1).map(|x| start + x * step).collect()
-}
+const IMAGE_EXTENSIONS: [&str; 9] = ["bmp", "gif", "heic", "jpeg", "jpg", "png", "tif", "tiff", "webp"];
 
 
-fn map_images_and_timestamps(
-    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
-    wallpaper_pack_config: &WallpaperPackConfig,
-    wallpaper_pack_dir: &String
-) -> (Vec<PathBuf>, Vec<i64>) {
-    let mut to_return_images: Vec<PathBuf> = vec![];
-    let mut to_return_timestamps: Vec<i64> = vec![];
+impl WallpaperPackConfig {
+    fn phase_lists_mut(&mut self) -> Vec<&mut Vec<PackImage>> {
+        let mut phase_lists = vec![
+            &mut self.midnight,
+            &mut self.sunrise,
+            &mut self.noon,
+            &mut self.sunset,
+            &mut self.moonrise,
+            &mut self.moonset,
+        ];
+        phase_lists.extend(self.anchors.values_mut().map(|x| &mut x.images));
 
-    to_return_images.extend(
-        wallpaper_pack_config.midnight
-            .clone()
-            .iter()
-            .map(|x| {
-                PathBuf::new()
-                    .join(wallpaper_pack_dir)
-                    .join(x)
-            })
-            .collect::<Vec<PathBuf>>()
-    );
-    to_return_timestamps.extend(
-        timestamp_splitter(
-            sun_and_moon[&SunAndMoonKeys::Midnight],
-            sun_and_moon[&SunAndMoonKeys::Moonset],
-            wallpaper_pack_config.midnight.len() as i64
-        )
-    );
+        phase_lists
+    }
 
-    to_return_images.extend(
-        wallpaper_pack_config.moonset
-            .clone()
-            .iter()
-            .map(|x| {
-                PathBuf::new()
-                    .join(wallpaper_pack_dir)
-                    .join(x)
-            })
-            .collect::<Vec<PathBuf>>()
-    );
-    to_return_timestamps.extend(
-        timestamp_splitter(
-            sun_and_moon[&SunAndMoonKeys::Moonset],
-            sun_and_moon[&SunAndMoonKeys::Sunrise],
-            wallpaper_pack_config.moonset.len() as i64
-        )
-    );
+    /// Rejects entries `timed_splitter` can't place, a fixed display time of
+    /// zero or less would put the slots out of order.
+    fn validate(&self) -> Result<(), String> {
+        let phases = [
+            ("midnight", &self.midnight),
+            ("sunrise", &self.sunrise),
+            ("noon", &self.noon),
+            ("sunset", &self.sunset),
+            ("moonrise", &self.moonrise),
+            ("moonset", &self.moonset),
+        ];
+        let anchors = self.anchors.iter().map(|(name, x)| (name.as_str(), &x.images));
 
-    to_return_images.extend(
-        wallpaper_pack_config.sunrise
-            .clone()
-            .iter()
-            .map(|x| {
-                PathBuf::new()
-                    .join(wallpaper_pack_dir)
-                    .join(x)
-            })
-            .collect::<Vec<PathBuf>>()
-    );
-    to_return_timestamps.extend(
-        timestamp_splitter(
-            sun_and_moon[&SunAndMoonKeys::Sunrise],
-            sun_and_moon[&SunAndMoonKeys::Noon],
-            wallpaper_pack_config.sunrise.len() as i64
-        )
-    );
+        for (name, images) in phases.into_iter().chain(anchors) {
+            if let Some(image) = images.iter().find(|x| x.duration_seconds().is_some_and(|x| x <= 0)) {
+                return Err(format!("duration_seconds of '{}' in {name} must be positive.", image.path()));
+            }
+        }
 
-    to_return_images.extend(
-        wallpaper_pack_config.noon
-            .clone()
-            .iter()
-            .map(|x| {
-                PathBuf::new()
-                    .join(wallpaper_pack_dir)
-                    .join(x)
-            })
-            .collect::<Vec<PathBuf>>()
-    );
-    to_return_timestamps.extend(
-        timestamp_splitter(
-            sun_and_moon[&SunAndMoonKeys::Noon],
-            sun_and_moon[&SunAndMoonKeys::Sunset],
-            wallpaper_pack_config.noon.len() as i64
-        )
-    );
+        Ok(())
+    }
 
-    to_return_images.extend(
-        wallpaper_pack_config.sunset
-            .clone()
+    /// Whether not a single phase or anchor has an image.
+    fn is_empty(&self) -> bool {
+        [&self.midnight, &self.sunrise, &self.noon, &self.sunset, &self.moonrise, &self.moonset]
             .iter()
-            .map(|x| {
-                PathBuf::new()
-                    .join(wallpaper_pack_dir)
-                    .join(x)
-            })
-            .collect::<Vec<PathBuf>>()
-    );
-    to_return_timestamps.extend(
-        timestamp_splitter(
-            sun_and_moon[&SunAndMoonKeys::Sunset],
-            sun_and_moon[&SunAndMoonKeys::Moonrise],
-            wallpaper_pack_config.sunset.len() as i64
-        )
-    );
+            .all(|x| x.is_empty())
+            && self.anchors.values().all(|x| x.images.is_empty())
+    }
+}
 
-    to_return_images.extend(
-        wallpaper_pack_config.moonrise
-            .clone()
-            .iter()
-            .map(|x| {
-                PathBuf::new()
-                    .join(wallpaper_pack_dir)
-                    .join(x)
-            })
-            .collect::<Vec<PathBuf>>()
-    );
-    to_return_timestamps.extend(
-        timestamp_splitter(
-            sun_and_moon[&SunAndMoonKeys::Moonrise],
-            sun_and_moon[&SunAndMoonKeys::NextDayMidnight],
-            wallpaper_pack_config.moonrise.len() as i64
-        )
-    );
 
-    (to_return_images, to_return_timestamps)
+/// `count` of `items` spread evenly over them, always keeping the first and
+/// the last one.
+fn sample_evenly<T: Clone>(items: &[T], count: usize) -> Vec<T> {
+    match (items.len(), count) {
+        (0, _) | (_, 0) => vec![],
+        (_, 1) => items[..1].to_vec(),
+        (length, count) if count >= length => items.to_vec(),
+        (length, count) => (0..count)
+            .map(|x| items[(x * (length - 1) + (count - 1) / 2) / (count - 1)].clone())
+            .collect(),
+    }
 }
 
 
-fn main() -> Result<(), String>{
-    let app_name= "wallpaper_changer_rust".to_string();
-    let config_name = "wallpaper_changer_config.toml".to_string();
-    let wallpaper_pack_config_name = "wallpaper_pack_config.toml".to_string();
+fn list_directory_images(directory: &Path) -> Result<Vec<String>, String> {
+    let mut images = fs::read_dir(directory)
+        .ok()
+        .ok_or_else(|| format!("Unable to read pack directory: {}", directory.display()))?
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| x.is_file())
+        .filter(|x| {
+            x.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        })
+        .filter_map(|x| x.file_name().and_then(|n| n.to_str()).map(|n| n.to_string()))
+        .collect::<Vec<String>>();
 
-    let project_dirs: ProjectDirs = ProjectDirs::from(
-        "hr",
-        "IDerdic",
-        &app_name
-    ).ok_or_else(|| "Unable to create ProjectDirs struct.")?;
+    images.sort_by(|a, b| natural_sort::natural_cmp(a, b));
 
-    let wallpaper_packs_dir = project_dirs
-        .data_local_dir()
-        .to_path_buf()
-        .join("wallpaper_packs")
-        .to_str()
-        .ok_or_else(|| "Unable to convert PathBuf to &str.")?
-        .to_string();
+    Ok(images)
+}
 
-    if !Path::new(&wallpaper_packs_dir).exists() {
-        fs::create_dir_all(&wallpaper_packs_dir)
-            .ok()
-            .ok_or_else(|| "Unable to create wallpaper pack directory tree.")?;
+
+fn resolve_pack_entries(
+    wallpaper_pack_config: &WallpaperPackConfig,
+    wallpaper_pack_dir: &String,
+    remote_cache_dir: &Path,
+    config: &WallpaperChangerConfig
+) -> Result<WallpaperPackConfig, String> {
+    let mut resolved_pack_config = wallpaper_pack_config.clone();
+    resolved_pack_config.phase_order.get_or_insert(config.phase_order);
+
+    // Without the moon's events the moon phases have nowhere to go.
+    if !config.use_moon {
+        let moon_phases = [
+            ("moonrise", &mut resolved_pack_config.moonrise),
+            ("moonset", &mut resolved_pack_config.moonset),
+        ];
+        let ignored_phases = moon_phases
+            .into_iter()
+            .filter(|(_, images)| !images.is_empty())
+            .map(|(phase, images)| {
+                images.clear();
+                phase
+            })
+            .collect::<Vec<&str>>();
+
+        if !ignored_phases.is_empty() {
+            eprintln!(
+                "use_moon is disabled, ignoring the {} images of the pack in {wallpaper_pack_dir}.",
+                ignored_phases.join(" and ")
+            );
+        }
     }
 
-    let mut today = Local::now()
-        .date_naive()
-        .and_hms_opt(0, 0, 0)
-        .ok_or_else(|| "Unable to get current day timestamp.")?;
+    for phase in resolved_pack_config.phase_lists_mut() {
+        let mut expanded = vec![];
 
-    let config_path = project_dirs
-        .config_local_dir()
-        .to_path_buf()
-        .join(&config_name)
-        .to_str()
-        .ok_or_else(|| "Unable to convert PathBuf to &str.")?
-        .to_string();
+        for pack_image in phase.iter() {
+            let entry = pack_image.path();
 
-    let config: WallpaperChangerConfig = confy::load_path(&config_path)
-        .ok()
-        .ok_or_else(|| "Unable to load the config file.")?;
+            if remote::is_remote(entry) {
+                match remote::fetch(entry, remote_cache_dir) {
+                    Ok(image) => expanded.push(pack_image.with_path(image.to_string_lossy().to_string())),
+                    Err(e) => eprintln!("{e}\nSkipping the image."),
+                }
+                continue;
+            }
 
-    if config.wallpaper_pack.eq("") {
-        println!("Wallpaper pack is not selected.\nCheck the config folder at path: {config_path}");
-        return Ok(());
+            let entry = remote::strip_file_scheme(entry);
+            let entry_path = Path::new(wallpaper_pack_dir).join(entry);
+
+            if entry_path.is_dir() {
+                expanded.extend(
+                    list_directory_images(&entry_path)?
+                        .into_iter()
+                        .map(|x| pack_image.with_path(Path::new(entry).join(x).to_string_lossy().to_string()))
+                );
+            } else {
+                expanded.push(pack_image.with_path(entry.to_string()));
+            }
+        }
+
+        *phase = match config.max_images_per_phase.map(|x| x.max(1)) {
+            Some(max_images) if expanded.len() > max_images => sample_evenly(&expanded, max_images),
+            _ => expanded,
+        };
+    }
+
+    let variant_dir = display::primary_monitor_size()
+        .and_then(|x| aspect_ratio::select_variant(&wallpaper_pack_config.aspect_variants, x));
+
+    if let Some(variant_dir) = variant_dir {
+        for phase in resolved_pack_config.phase_lists_mut() {
+            for pack_image in phase.iter_mut() {
+                let variant = Path::new(variant_dir).join(pack_image.path());
+
+                if Path::new(wallpaper_pack_dir).join(&variant).is_file() {
+                    *pack_image = pack_image.with_path(variant.to_string_lossy().to_string());
+                }
+            }
+        }
     }
 
+    Ok(resolved_pack_config)
+}
+
+
+fn load_wallpaper_pack(
+    wallpaper_packs_dir: &String,
+    wallpaper_pack: &String,
+    wallpaper_pack_config_name: &String
+) -> Result<(String, WallpaperPackConfig), String> {
     let wallpaper_pack_dir = PathBuf::new()
-        .join(&wallpaper_packs_dir)
-        .join(&config.wallpaper_pack)
+        .join(wallpaper_packs_dir)
+        .join(wallpaper_pack)
         .to_str()
-        .ok_or_else(|| "Unable to convert PathBuf to &str.")?
+        .ok_or("Unable to convert PathBuf to &str.")?
         .to_string();
 
+    if !Path::new(&wallpaper_pack_dir).is_dir() {
+        return Err(format!("Wallpaper pack '{wallpaper_pack}' does not exist in: {wallpaper_packs_dir}"));
+    }
+
+    load_wallpaper_pack_from_dir(wallpaper_pack_dir, wallpaper_pack_config_name)
+}
+
+
+/// Loads the pack in `wallpaper_pack_dir` directly, wherever it is.
+fn load_wallpaper_pack_from_dir(
+    wallpaper_pack_dir: String,
+    wallpaper_pack_config_name: &String
+) -> Result<(String, WallpaperPackConfig), String> {
     let wallpaper_pack_config_path = PathBuf::new()
         .join(&wallpaper_pack_dir)
-        .join(&wallpaper_pack_config_name)
+        .join(wallpaper_pack_config_name)
         .to_str()
-        .ok_or_else(|| "Unable to convert PathBuf to &str.")?
+        .ok_or("Unable to convert PathBuf to &str.")?
         .to_string();
 
+    if !Path::new(&wallpaper_pack_config_path).is_file() {
+        return Err(format!("No {wallpaper_pack_config_name} in wallpaper pack: {wallpaper_pack_dir}"));
+    }
+
     let wallpaper_pack_config: WallpaperPackConfig = toml::from_str(
             &fs::read_to_string(&wallpaper_pack_config_path)
                 .ok()
-                .ok_or_else(|| "unable to read wallpaper_pack_config.toml to String.")?
-        ).ok().ok_or_else(|| "Unable to parse wallpaper_pack_config.toml file.")?;
+                .ok_or("unable to read wallpaper_pack_config.toml to String.")?
+        ).map_err(|e| format!("Unable to parse wallpaper_pack_config.toml file: {e}"))?;
+    wallpaper_pack_config
+        .validate()
+        .map_err(|e| format!("Invalid wallpaper_pack_config.toml file: {e}"))?;
 
-    let mut sun_and_moon = get_day_sun_and_moon_position_times(
-        today.timestamp(),
-        config.longitude,
-        config.latitude,
-    )?;
+    if let Some(version) = wallpaper_pack_config.version {
+        if version > migration::CURRENT_PACK_VERSION {
+            eprintln!(
+                "Pack {wallpaper_pack_dir} has version {version}, newer than the supported version {}. Fields it relies on may be ignored.",
+                migration::CURRENT_PACK_VERSION
+            );
+        }
+    }
 
-    let (mut images_seq, mut timestamp_seq) = map_images_and_timestamps(
-        &sun_and_moon,
-        &wallpaper_pack_config,
-        &wallpaper_pack_dir
-    );
+    Ok((wallpaper_pack_dir, wallpaper_pack_config))
+}
 
-    let mut current_timestamp = Local::now().timestamp();
 
-    let terminate_loop = Arc::new(AtomicBool::new(false));
-    let tl = terminate_loop.clone();
+/// Loads `wallpaper_pack` by name, unless it's the config's own pack and that
+/// is defined inline.
+fn load_configured_pack(
+    config: &WallpaperChangerConfig,
+    config_path: &String,
+    wallpaper_packs_dir: &String,
+    wallpaper_pack: &String,
+    wallpaper_pack_config_name: &String
+) -> Result<(String, WallpaperPackConfig), String> {
+    match &config.pack {
+        Some(pack) if *wallpaper_pack == config.wallpaper_pack => {
+            let config_dir = Path::new(config_path)
+                .parent()
+                .and_then(|x| x.to_str())
+                .ok_or("Unable to get the config directory.")?
+                .to_string();
 
-    ctrlc::set_handler(move || {
-        tl.store(true, Ordering::SeqCst);
-    }).ok().ok_or_else(|| "Unable to set Ctrl+C handler.")?;
+            pack.validate().map_err(|e| format!("Invalid inline pack: {e}"))?;
 
-    while !terminate_loop.load(Ordering::SeqCst) {
-        if current_timestamp > sun_and_moon[&SunAndMoonKeys::NextDayMidnight] {
-            today = Local::now()
-                .date_naive()
-                .and_hms_opt(0, 0, 0)
-                .ok_or_else(|| "Unable to get current day timestamp.")?;
+            Ok((config_dir, pack.clone()))
+        }
+        _ => load_wallpaper_pack(wallpaper_packs_dir, wallpaper_pack, wallpaper_pack_config_name),
+    }
+}
 
-            sun_and_moon = get_day_sun_and_moon_position_times(
-                today.timestamp(),
-                config.longitude,
-                config.latitude,
-            )?;
 
-            let (images_seq_tmp, timestamp_seq_tmp) = map_images_and_timestamps(
-                &sun_and_moon,
-                &wallpaper_pack_config,
-                &wallpaper_pack_dir
-            );
+/// Returns the rotation pack for `day`, advancing the rotation index when
+/// the day differs from the one the index was last advanced on.
+fn rotate_pack(
+    config: &WallpaperChangerConfig,
+    state: &mut WallpaperChangerState,
+    day: NaiveDate
+) -> Option<String> {
+    if config.pack_rotation.is_empty() {
+        return None;
+    }
 
-            images_seq = images_seq_tmp;
-            timestamp_seq = timestamp_seq_tmp;
-        }
+    if state.rotation_date.is_some_and(|x| x != day) {
+        state.rotation_index += 1;
+    }
+    state.rotation_index %= config.pack_rotation.len();
+    state.rotation_date = Some(day);
 
-        for (index, timestamp) in timestamp_seq.iter().enumerate() {
-            if current_timestamp < *timestamp {
-                wallpaper::set_from_path(
-                    images_seq[index]
-                        .to_str()
-                        .ok_or_else(|| "Unable to convert PathBuf to &str.")?
-                ).ok().ok_or_else(|| "Unable to set wallpaper.")?;
-                break;
+    Some(config.pack_rotation[state.rotation_index].clone())
+}
+
+
+/// Name of the symlink in the packs directory that selects the pack when the
+/// config leaves `wallpaper_pack` empty, e.g. `ln -sfn mountains current`.
+const CURRENT_PACK_LINK: &str = "current";
+
+
+/// Fills in an empty `wallpaper_pack` from the `current` link in the packs
+/// directory. A link to a pack in the packs directory selects that pack by
+/// name, a link to anywhere else is loaded through the link itself.
+fn select_linked_pack(
+    config: &mut WallpaperChangerConfig,
+    wallpaper_packs_dir: &String
+) -> Result<(), String> {
+    if !config.wallpaper_pack.is_empty() || config.pack.is_some() {
+        return Ok(());
+    }
+
+    let link = Path::new(wallpaper_packs_dir).join(CURRENT_PACK_LINK);
+
+    if !fs::symlink_metadata(&link).is_ok_and(|x| x.file_type().is_symlink()) {
+        return Ok(());
+    }
+
+    let target = fs::canonicalize(&link).map_err(|_| {
+        let target = fs::read_link(&link).unwrap_or_default();
+        format!(
+            "The `{CURRENT_PACK_LINK}` link in {wallpaper_packs_dir} points to {}, which doesn't exist.",
+            target.display()
+        )
+    })?;
+
+    let in_packs_dir = fs::canonicalize(wallpaper_packs_dir).is_ok_and(|x| target.parent() == Some(x.as_path()));
+
+    config.wallpaper_pack = match (in_packs_dir, target.file_name()) {
+        (true, Some(name)) => name.to_string_lossy().to_string(),
+        _ => CURRENT_PACK_LINK.to_string(),
+    };
+
+    Ok(())
+}
+
+
+/// A random pack out of `random_pack_candidates`, or out of every installed
+/// pack when there are none. Candidates that fail to load are passed over.
+fn random_pack(
+    config: &WallpaperChangerConfig,
+    wallpaper_packs_dir: &String,
+    wallpaper_pack_config_name: &String,
+    rng: &mut Rng
+) -> Option<String> {
+    let mut candidates = config.random_pack_candidates.clone();
+
+    if candidates.is_empty() {
+        candidates = fs::read_dir(wallpaper_packs_dir)
+            .ok()?
+            .filter_map(|x| x.ok())
+            .filter(|x| x.path().is_dir() && x.file_name() != CURRENT_PACK_LINK)
+            .map(|x| x.file_name().to_string_lossy().to_string())
+            .collect();
+        candidates.sort();
+    }
+
+    if candidates.is_empty() {
+        eprintln!("No packs to pick a random one from in: {wallpaper_packs_dir}");
+        return None;
+    }
+
+    let start = rng.below(candidates.len());
+
+    let wallpaper_pack = (0..candidates.len())
+        .map(|x| &candidates[(start + x) % candidates.len()])
+        .find(|x| match load_wallpaper_pack(wallpaper_packs_dir, x, wallpaper_pack_config_name) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("Skipping random pack candidate: {e}");
+                false
             }
+        })?;
+
+    println!("Randomly picked pack '{wallpaper_pack}'.");
+
+    Some(wallpaper_pack.clone())
+}
+
+
+/// The power source specific pack when one is configured for the detected
+/// source, otherwise the rotation/configured pack.
+fn active_wallpaper_pack(
+    config: &WallpaperChangerConfig,
+    base_wallpaper_pack: &String,
+    power_source: Option<PowerSource>
+) -> String {
+    let power_pack = match power_source {
+        Some(PowerSource::Battery) => config.battery_pack.as_ref(),
+        Some(PowerSource::Ac) => config.ac_pack.as_ref(),
+        None => None,
+    };
+
+    power_pack.unwrap_or(base_wallpaper_pack).clone()
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+struct PlaybackSettings {
+    poll_interval_seconds: u64,
+    transition: Transition,
+    phase_transitions: BTreeMap<String, Transition>,
+}
+
+impl PlaybackSettings {
+    /// Pack settings take precedence over the global config, an interval
+    /// given on the command line over both. Phase transitions are resolved
+    /// per change by `transition_for`.
+    fn resolve(
+        config: &WallpaperChangerConfig,
+        wallpaper_pack_config: &WallpaperPackConfig,
+        interval_override: Option<u64>
+    ) -> Self {
+        Self {
+            poll_interval_seconds: interval_override
+                .or(wallpaper_pack_config.poll_interval_seconds)
+                .unwrap_or(config.poll_interval_seconds)
+                .max(1),
+            transition: wallpaper_pack_config
+                .transition
+                .clone()
+                .unwrap_or_else(|| config.transition.clone()),
+            phase_transitions: wallpaper_pack_config.transitions.clone(),
         }
+    }
 
-        thread::sleep(time::Duration::from_secs(1));
+    /// The transition into the images of `phase`: its own one from the pack,
+    /// matching phase and anchor names regardless of case, otherwise the
+    /// pack's, otherwise the global one.
+    fn transition_for(&self, phase: Option<&str>) -> &Transition {
+        phase
+            .and_then(|phase| {
+                self.phase_transitions
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(phase))
+            })
+            .map(|(_, x)| x)
+            .unwrap_or(&self.transition)
+    }
 
-        current_timestamp = Local::now().timestamp();
+    /// Every transition changes may use.
+    fn transitions(&self) -> impl Iterator<Item = &Transition> {
+        std::iter::once(&self.transition).chain(self.phase_transitions.values())
     }
+}
 
-    println!("The program was terminated using ctrl+c.");
 
-    Ok(())
+/// How the sun and moon events of a day are turned into its boundaries.
+#[derive(Debug, Clone, Copy)]
+struct BoundaryOptions {
+    use_moon: bool,
+    time_offset_minutes: i64,
+    day_boundary: DayBoundary,
+    round_to_minute: bool,
+}
+
+impl BoundaryOptions {
+    fn from_config(config: &WallpaperChangerConfig) -> Self {
+        Self {
+            use_moon: config.use_moon,
+            time_offset_minutes: config.time_offset_minutes,
+            day_boundary: config.day_boundary,
+            round_to_minute: config.round_boundaries_to_minute,
+        }
+    }
+}
+
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+enum SunAndMoonKeys {
+    Midnight,
+    Sunrise,
+    Noon,
+    Sunset,
+    Moonrise,
+    Moonset,
+    NextDayMidnight,
+}
+
+
+/// The local midnight `days` after the day `day_posix` stands for. Like all
+/// day timestamps here, `day_posix` is the local date's midnight read as UTC.
+fn local_midnight(
+    day_posix: i64,
+    days: i64
+) -> Result<i64, String> {
+    NaiveDateTime::from_timestamp_opt(day_posix, 0)
+        .map(|x| x + Duration::days(days))
+        .and_then(|x| Local.from_local_datetime(&x).earliest())
+        .map(|x| x.timestamp())
+        .ok_or_else(|| "Unable to get local midnight.".to_string())
+}
+
+
+fn get_day_sun_and_moon_position_times(
+    astronomy: &impl AstronomyProvider,
+    today_posix: i64,
+    longitude: f64,
+    latitude: f64,
+    options: BoundaryOptions,
+) -> Result<HashMap<SunAndMoonKeys, i64>, String> {
+    let BoundaryOptions {
+        use_moon,
+        time_offset_minutes,
+        day_boundary,
+        round_to_minute: round_boundaries_to_minute,
+    } = options;
+
+    // The fixed times count from the start of the local day.
+    let clock = FixedAstronomy::default().shifted(local_midnight(today_posix, 0)? - today_posix);
+
+    let astronomy: &dyn AstronomyProvider = match astronomy::near_pole(latitude) {
+        true => &clock,
+        false => astronomy,
+    };
+
+    if time_offset_minutes.abs() > MAX_TIME_OFFSET_MINUTES {
+        return Err(format!(
+            "time_offset_minutes must be between -{MAX_TIME_OFFSET_MINUTES} and {MAX_TIME_OFFSET_MINUTES}."
+        ));
+    }
+
+    let mut sun_and_moon = HashMap::new();
+
+    sun_and_moon.insert(
+        SunAndMoonKeys::Sunrise,
+        astronomy
+            .sunrise(today_posix, longitude, latitude)
+            .ok_or("Can't get sunrise.")?,
+    );
+    sun_and_moon.insert(
+        SunAndMoonKeys::Sunset,
+        astronomy
+            .sunset(today_posix, longitude, latitude)
+            .ok_or("Can't get sunset.")?,
+    );
+
+    sun_and_moon.insert(
+        SunAndMoonKeys::Noon,
+        astronomy.noon(today_posix, longitude),
+    );
+    let (midnight, next_day_midnight) = match day_boundary {
+        DayBoundary::Local => (local_midnight(today_posix, 0)?, local_midnight(today_posix, 1)?),
+        DayBoundary::Solar => (
+            astronomy.midnight(today_posix, longitude),
+            astronomy.midnight(today_posix + Duration::days(1).num_seconds(), longitude),
+        ),
+    };
+
+    sun_and_moon.insert(SunAndMoonKeys::Midnight, midnight);
+
+    if use_moon {
+        sun_and_moon.insert(
+            SunAndMoonKeys::Moonrise,
+            astronomy
+                .moonrise(today_posix, longitude, latitude)
+                .ok_or("Can't get moonrise.")?,
+        );
+        sun_and_moon.insert(
+            SunAndMoonKeys::Moonset,
+            astronomy
+                .moonset(today_posix, longitude, latitude)
+                .ok_or("Can't get moonset.")?,
+        );
+    }
+
+    sun_and_moon.insert(SunAndMoonKeys::NextDayMidnight, next_day_midnight);
+
+    // A calendar day doesn't move, only the events within it. Solar midnights
+    // are events too, so with them the whole day shifts.
+    if time_offset_minutes != 0 {
+        let day_shift = match day_boundary {
+            DayBoundary::Local => 0,
+            DayBoundary::Solar => time_offset_minutes * 60,
+        };
+        let (midnight, next_day_midnight) = (midnight + day_shift, next_day_midnight + day_shift);
+
+        for (key, timestamp) in sun_and_moon.iter_mut() {
+            let day_boundary_key = matches!(key, SunAndMoonKeys::Midnight | SunAndMoonKeys::NextDayMidnight);
+
+            if day_boundary_key && day_boundary == DayBoundary::Local {
+                continue;
+            }
+
+            *timestamp += time_offset_minutes * 60;
+
+            if day_boundary_key {
+                continue;
+            }
+
+            // The day can't start before its midnight, the phases before the
+            // event are squeezed to nothing instead.
+            if *timestamp < midnight {
+                eprintln!("Shifted {key:?} falls before the day's midnight, it's moved to midnight.");
+                *timestamp = midnight;
+            }
+
+            if *timestamp > next_day_midnight {
+                eprintln!("Shifted {key:?} falls after the next day's midnight, its phase will be empty.");
+            }
+        }
+    }
+
+    if round_boundaries_to_minute {
+        round_to_minute(&mut sun_and_moon);
+    }
+
+    Ok(sun_and_moon)
+}
+
+
+/// Rounds every time to the nearest minute, except for times that would then
+/// share a minute with a time they used to come before or after. Those keep
+/// their exact value so no phase is reordered or squeezed to nothing.
+fn round_to_minute(sun_and_moon: &mut HashMap<SunAndMoonKeys, i64>) {
+    let rounded = sun_and_moon
+        .iter()
+        .map(|(key, timestamp)| (*key, (timestamp + 30).div_euclid(60) * 60))
+        .collect::<HashMap<SunAndMoonKeys, i64>>();
+
+    let order_kept = |key: &SunAndMoonKeys| {
+        sun_and_moon.keys().all(|other| {
+            sun_and_moon[key].cmp(&sun_and_moon[other]) == rounded[key].cmp(&rounded[other])
+        })
+    };
+
+    let kept = rounded
+        .keys()
+        .filter(|x| order_kept(x))
+        .copied()
+        .collect::<Vec<SunAndMoonKeys>>();
+
+    for key in kept {
+        sun_and_moon.insert(key, rounded[&key]);
+    }
+}
+
+
+fn schedule_covers(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    timestamp: i64
+) -> bool {
+    sun_and_moon[&SunAndMoonKeys::Midnight] <= timestamp
+        && timestamp < sun_and_moon[&SunAndMoonKeys::NextDayMidnight]
+}
+
+
+/// How far through the day's schedule `timestamp` is, from 0.0 at its
+/// midnight to 1.0 at the next one. Times outside it are clamped.
+fn day_progress(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    timestamp: i64
+) -> f64 {
+    let start = sun_and_moon[&SunAndMoonKeys::Midnight];
+    let end = sun_and_moon[&SunAndMoonKeys::NextDayMidnight];
+
+    ((timestamp - start) as f64 / (end - start).max(1) as f64).clamp(0.0, 1.0)
+}
+
+
+/// The day whose schedule covers `timestamp`, with its sun and moon times.
+/// With solar day boundaries that can be the calendar day before or after.
+fn day_containing(
+    timestamp: i64,
+    config: &WallpaperChangerConfig
+) -> Result<(NaiveDateTime, HashMap<SunAndMoonKeys, i64>), String> {
+    let date = Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|x| x.date_naive())
+        .ok_or("Unable to get current day timestamp.")?;
+
+    let mut fallback = None;
+
+    for offset in [0, -1, 1] {
+        let day = (date + Duration::days(offset))
+            .and_hms_opt(0, 0, 0)
+            .ok_or("Unable to get current day timestamp.")?;
+
+        let sun_and_moon = get_day_sun_and_moon_position_times(
+            &Geodate,
+            day.timestamp(),
+            config.longitude,
+            config.latitude,
+            BoundaryOptions::from_config(config),
+        )?;
+
+        if schedule_covers(&sun_and_moon, timestamp) {
+            return Ok((day, sun_and_moon));
+        }
+
+        fallback.get_or_insert((day, sun_and_moon));
+    }
+
+    fallback.ok_or_else(|| "Unable to get current day timestamp.".to_string())
+}
+
+
+fn timestamp_splitter(
+    start: i64,
+    end: i64,
+    chunks: i64
+) -> Vec<i64> {
+    if chunks <= 0 {
+        return vec![];
+    }
+
+    let step = (end - start) / chunks;
+
+    (1..chunks + 1).map(|x| start + x * step).collect()
+}
+
+
+/// Like `timestamp_splitter`, but images with a fixed duration keep it and the
+/// remaining span is split evenly between the rest.
+fn timed_splitter(
+    start: i64,
+    end: i64,
+    durations: &[Option<i64>]
+) -> Result<Vec<i64>, String> {
+    let fixed_total: i64 = durations.iter().flatten().sum();
+    let auto_count = durations.iter().filter(|x| x.is_none()).count() as i64;
+
+    if fixed_total > end - start {
+        return Err(format!(
+            "Fixed image durations ({fixed_total}s) exceed the phase span ({}s).",
+            end - start
+        ));
+    }
+
+    let auto_step = match auto_count {
+        0 => 0,
+        _ => (end - start - fixed_total) / auto_count,
+    };
+
+    let mut timestamp = start;
+    let mut timestamps = durations
+        .iter()
+        .map(|x| {
+            timestamp += x.unwrap_or(auto_step);
+            timestamp
+        })
+        .collect::<Vec<i64>>();
+
+    // With only fixed durations the last image holds until the phase ends.
+    if auto_count == 0 {
+        if let Some(last) = timestamps.last_mut() {
+            *last = end;
+        }
+    }
+
+    Ok(timestamps)
+}
+
+
+fn phase_images<'a>(
+    wallpaper_pack_config: &'a WallpaperPackConfig,
+    phase: &SunAndMoonKeys
+) -> &'a Vec<PackImage> {
+    match phase {
+        SunAndMoonKeys::Midnight | SunAndMoonKeys::NextDayMidnight => &wallpaper_pack_config.midnight,
+        SunAndMoonKeys::Sunrise => &wallpaper_pack_config.sunrise,
+        SunAndMoonKeys::Noon => &wallpaper_pack_config.noon,
+        SunAndMoonKeys::Sunset => &wallpaper_pack_config.sunset,
+        SunAndMoonKeys::Moonrise => &wallpaper_pack_config.moonrise,
+        SunAndMoonKeys::Moonset => &wallpaper_pack_config.moonset,
+    }
+}
+
+
+fn phase_spans(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>
+) -> Vec<(SunAndMoonKeys, SunAndMoonKeys)> {
+    if sun_and_moon.contains_key(&SunAndMoonKeys::Moonset) {
+        vec![
+            (SunAndMoonKeys::Midnight, SunAndMoonKeys::Moonset),
+            (SunAndMoonKeys::Moonset, SunAndMoonKeys::Sunrise),
+            (SunAndMoonKeys::Sunrise, SunAndMoonKeys::Noon),
+            (SunAndMoonKeys::Noon, SunAndMoonKeys::Sunset),
+            (SunAndMoonKeys::Sunset, SunAndMoonKeys::Moonrise),
+            (SunAndMoonKeys::Moonrise, SunAndMoonKeys::NextDayMidnight),
+        ]
+    } else {
+        vec![
+            (SunAndMoonKeys::Midnight, SunAndMoonKeys::Sunrise),
+            (SunAndMoonKeys::Sunrise, SunAndMoonKeys::Noon),
+            (SunAndMoonKeys::Noon, SunAndMoonKeys::Sunset),
+            (SunAndMoonKeys::Sunset, SunAndMoonKeys::NextDayMidnight),
+        ]
+    }
+}
+
+
+/// Where a phase or anchor starts showing its images.
+struct ScheduleBoundary<'a> {
+    /// `None` for anchors.
+    key: Option<SunAndMoonKeys>,
+    name: String,
+    timestamp: i64,
+    images: &'a Vec<PackImage>,
+}
+
+
+/// The day's phase boundaries with the pack's anchors inserted next to the
+/// event they are relative to, ending with the next day's midnight.
+///
+/// An anchor that would land on the far side of a neighbouring phase boundary
+/// is ignored, since its images would otherwise run out of order.
+fn schedule_boundaries<'a>(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    wallpaper_pack_config: &'a WallpaperPackConfig,
+    warn_on_skipped_anchors: bool
+) -> Vec<ScheduleBoundary<'a>> {
+    let mut boundaries = phase_spans(sun_and_moon)
+        .into_iter()
+        .map(|(start, _)| start)
+        .chain([SunAndMoonKeys::NextDayMidnight])
+        .map(|x| ScheduleBoundary {
+            key: Some(x),
+            name: format!("{x:?}"),
+            timestamp: sun_and_moon[&x],
+            images: phase_images(wallpaper_pack_config, &x),
+        })
+        .collect::<Vec<ScheduleBoundary>>();
+
+    // Events outside the day, e.g. a moonrise only after the next midnight,
+    // are moved to its edge.
+    let day_start = sun_and_moon[&SunAndMoonKeys::Midnight];
+    let day_end = sun_and_moon[&SunAndMoonKeys::NextDayMidnight].max(day_start);
+
+    for boundary in boundaries.iter_mut() {
+        boundary.timestamp = boundary.timestamp.clamp(day_start, day_end);
+    }
+
+    match wallpaper_pack_config.phase_order.unwrap_or_default() {
+        PhaseOrder::Sorted => boundaries.sort_by_key(|x| x.timestamp),
+        // A moon event outside its place between the sun events, e.g. a
+        // moonrise before sunset, is moved to the end of it, leaving the
+        // moon's phase empty rather than running backwards. Any other event
+        // out of order squeezes the phase before it to nothing.
+        PhaseOrder::Fixed => {
+            for index in 1..boundaries.len() - 1 {
+                let moon = matches!(boundaries[index].key, Some(SunAndMoonKeys::Moonrise | SunAndMoonKeys::Moonset));
+                let (previous, next) = (boundaries[index - 1].timestamp, boundaries[index + 1].timestamp);
+
+                if moon && !(previous..=next).contains(&boundaries[index].timestamp) {
+                    boundaries[index].timestamp = next;
+                }
+            }
+
+            for index in 1..boundaries.len() {
+                boundaries[index].timestamp = boundaries[index].timestamp.max(boundaries[index - 1].timestamp);
+            }
+        }
+    }
+
+    let mut anchors = wallpaper_pack_config.anchors.iter().collect::<Vec<(&String, &TimeAnchor)>>();
+    anchors.sort_by_key(|(_, x)| x.at.offset_seconds);
+
+    for (name, anchor) in anchors {
+        let Some(key_position) = boundaries.iter().position(|x| x.key == Some(anchor.at.key)) else {
+            if warn_on_skipped_anchors {
+                eprintln!("Anchor '{name}' is relative to {:?}, which is not in the schedule. Ignoring it.", anchor.at.key);
+            }
+            continue;
+        };
+
+        let timestamp = boundaries[key_position].timestamp + anchor.at.offset_seconds;
+
+        // Anchors relative to the same event keep their offsets' order.
+        let mut position = key_position;
+        if anchor.at.offset_seconds < 0 {
+            while position > 0 && boundaries[position - 1].key.is_none() && boundaries[position - 1].timestamp > timestamp {
+                position -= 1;
+            }
+        } else {
+            position += 1;
+            while position < boundaries.len() && boundaries[position].key.is_none() && boundaries[position].timestamp <= timestamp {
+                position += 1;
+            }
+        }
+
+        let in_order = position > 0
+            && position < boundaries.len()
+            && boundaries[position - 1].timestamp <= timestamp
+            && timestamp <= boundaries[position].timestamp;
+
+        if !in_order {
+            if warn_on_skipped_anchors {
+                eprintln!(
+                    "Anchor '{name}' ({}) falls outside the phases next to {:?}. Ignoring it.",
+                    String::from(anchor.at),
+                    anchor.at.key
+                );
+            }
+            continue;
+        }
+
+        boundaries.insert(position, ScheduleBoundary {
+            key: None,
+            name: name.clone(),
+            timestamp,
+            images: &anchor.images,
+        });
+    }
+
+    boundaries
+}
+
+
+/// End timestamps of every schedule slot. Image paths are not built here, they
+/// are resolved with `scheduled_image_path` only when a slot is about to be
+/// shown, which keeps huge directory packs from being duplicated as `PathBuf`s.
+fn map_timestamps(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    wallpaper_pack_config: &WallpaperPackConfig
+) -> Vec<i64> {
+    let mut to_return_timestamps: Vec<i64> = vec![];
+
+    for span in schedule_boundaries(sun_and_moon, wallpaper_pack_config, true).windows(2) {
+        let (start, end) = (&span[0], &span[1]);
+        let images = start.images;
+
+        let durations = images
+            .iter()
+            .map(|x| x.duration_seconds())
+            .collect::<Vec<Option<i64>>>();
+
+        let timestamps = match durations.iter().any(|x| x.is_some()) {
+            false => Ok(timestamp_splitter(
+                start.timestamp,
+                end.timestamp,
+                images.len() as i64
+            )),
+            true => timed_splitter(start.timestamp, end.timestamp, &durations),
+        };
+
+        to_return_timestamps.extend(
+            timestamps.unwrap_or_else(|e| {
+                eprintln!("{} phase: {e}\nSplitting it evenly instead.", start.name);
+                timestamp_splitter(
+                    start.timestamp,
+                    end.timestamp,
+                    images.len() as i64
+                )
+            })
+        );
+    }
+
+    to_return_timestamps
+}
+
+
+/// A phase or anchor of a day's schedule and the schedule slots its images
+/// fill, without the images themselves.
+#[derive(Debug, Clone, PartialEq)]
+struct PhaseSpan {
+    /// `None` for anchors.
+    key: Option<SunAndMoonKeys>,
+    name: String,
+    start: i64,
+    end: i64,
+    slots: Range<usize>,
+}
+
+impl PhaseSpan {
+    /// The images of this phase or anchor in `wallpaper_pack_config`.
+    fn images<'a>(&self, wallpaper_pack_config: &'a WallpaperPackConfig) -> &'a [PackImage] {
+        match self.key {
+            Some(key) => phase_images(wallpaper_pack_config, &key),
+            None => wallpaper_pack_config.anchors.get(&self.name).map(|x| x.images.as_slice()).unwrap_or_default(),
+        }
+    }
+}
+
+
+/// The phases and anchors of a day in schedule order. Computed once per day
+/// along with the slot timestamps, the images of a slot are only looked up
+/// in the pack config when it's shown.
+fn day_phases(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    wallpaper_pack_config: &WallpaperPackConfig
+) -> Vec<PhaseSpan> {
+    let mut phase_start = 0;
+
+    schedule_boundaries(sun_and_moon, wallpaper_pack_config, false)
+        .windows(2)
+        .map(|span| {
+            let phase_end = phase_start + span[0].images.len();
+            let slots = phase_start..phase_end;
+            phase_start = phase_end;
+
+            PhaseSpan {
+                key: span[0].key,
+                name: span[0].name.clone(),
+                start: span[0].timestamp,
+                end: span[1].timestamp,
+                slots,
+            }
+        })
+        .collect()
+}
+
+
+/// How long each schedule slot lasts, in seconds, the first one counting from
+/// the day's midnight.
+fn slot_durations(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    timestamp_seq: &[i64]
+) -> Vec<i64> {
+    let mut slot_start = sun_and_moon[&SunAndMoonKeys::Midnight];
+
+    timestamp_seq
+        .iter()
+        .map(|slot_end| {
+            let duration = slot_end - slot_start;
+            slot_start = *slot_end;
+            duration
+        })
+        .collect()
+}
+
+
+fn print_phase_boundaries(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    config: &WallpaperChangerConfig,
+    day: NaiveDate
+) {
+    let mut boundaries = sun_and_moon.iter().collect::<Vec<(&SunAndMoonKeys, &i64)>>();
+    boundaries.sort_by_key(|(_, timestamp)| **timestamp);
+
+    println!(
+        "Phase boundaries for {day} at latitude {}, longitude {}:",
+        config.latitude,
+        config.longitude
+    );
+
+    for (key, timestamp) in boundaries {
+        let local_time = Local
+            .timestamp_opt(*timestamp, 0)
+            .single()
+            .map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| timestamp.to_string());
+
+        println!("  {:<16} {local_time}", format!("{key:?}"));
+    }
+}
+
+
+/// Prints the image shown at `time`, picked the way the main loop does.
+fn print_image_for(
+    time: NaiveDateTime,
+    config: &WallpaperChangerConfig,
+    wallpaper_pack_config: &WallpaperPackConfig,
+    wallpaper_pack_dir: &String
+) -> Result<(), String> {
+    let timestamp = Local
+        .from_local_datetime(&time)
+        .earliest()
+        .ok_or_else(|| format!("{time} doesn't exist in the local time zone."))?
+        .timestamp();
+
+    let (day, sun_and_moon) = day_containing(timestamp, config)
+        .map_err(|e| format!("No schedule covers {time}: {e}"))?;
+
+    let timestamp_seq = map_timestamps(&sun_and_moon, wallpaper_pack_config);
+    let phases = day_phases(&sun_and_moon, wallpaper_pack_config);
+
+    let selected_index = match config.schedule_mode {
+        ScheduleMode::Phases => select_image_index(&timestamp_seq, timestamp),
+        ScheduleMode::SolarTime => solar_time_index(timestamp_seq.len(), timestamp, config),
+    };
+
+    let Some(index) = selected_index else {
+        println!("No image is shown at {time}, the pack has none.");
+        return Ok(());
+    };
+
+    let index = night_override_index(index, timestamp, config, wallpaper_pack_config);
+
+    println!("At {time}, in the schedule of {}:", day.date());
+
+    let phases_mode = config.schedule_mode == ScheduleMode::Phases;
+
+    if phases_mode
+        && wallpaper_pack_config.night_behavior != NightBehavior::Loop
+        && in_uncovered_night(&sun_and_moon, &phases, timestamp)
+    {
+        println!("  Night without images, shown as {:?}", wallpaper_pack_config.night_behavior);
+        return Ok(());
+    }
+
+    if let Some(default_image) = wallpaper_pack_config.default_image.as_ref().filter(|_| {
+        phases_mode && in_empty_phase(&phases, timestamp)
+    }) {
+        println!("  Phase without images, showing the default image");
+        println!("  Image  {}", Path::new(wallpaper_pack_dir).join(remote::strip_file_scheme(default_image)).display());
+        return Ok(());
+    }
+
+    let slot_start = match index {
+        0 => sun_and_moon[&SunAndMoonKeys::Midnight],
+        _ => timestamp_seq[index - 1],
+    };
+    let format_time = |x: i64| {
+        Local
+            .timestamp_opt(x, 0)
+            .single()
+            .map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| x.to_string())
+    };
+
+    println!("  Phase  {}", phase_of_index(&phases, index).unwrap_or_default());
+    println!(
+        "  Slot   {} of {}, {} to {}",
+        index + 1,
+        timestamp_seq.len(),
+        format_time(slot_start),
+        format_time(timestamp_seq[index])
+    );
+
+    if let Some(image) = scheduled_image_path(&phases, wallpaper_pack_config, wallpaper_pack_dir, index) {
+        println!("  Image  {}", image.display());
+    }
+
+    Ok(())
+}
+
+
+fn print_astronomy(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    latitude: f64,
+    longitude: f64,
+    day: NaiveDate
+) {
+    let mut events = sun_and_moon.iter().collect::<Vec<(&SunAndMoonKeys, &i64)>>();
+    events.sort_by_key(|(_, timestamp)| **timestamp);
+
+    println!("Astronomy for {day} at latitude {latitude}, longitude {longitude}:");
+    println!("  {:<16} {:>12}  {:<25}  UTC", "Event", "POSIX", "Local");
+
+    for (key, timestamp) in events {
+        let local_time = Local
+            .timestamp_opt(*timestamp, 0)
+            .single()
+            .map(|x| x.to_rfc3339())
+            .unwrap_or_default();
+        let utc_time = Utc
+            .timestamp_opt(*timestamp, 0)
+            .single()
+            .map(|x| x.to_rfc3339())
+            .unwrap_or_default();
+
+        println!("  {:<16} {timestamp:>12}  {local_time:<25}  {utc_time}", format!("{key:?}"));
+    }
+}
+
+
+/// The phase or anchor name and the pack entry of the schedule slot at `index`.
+fn schedule_slot<'a>(
+    phases: &[PhaseSpan],
+    wallpaper_pack_config: &'a WallpaperPackConfig,
+    index: usize
+) -> Option<(String, &'a PackImage)> {
+    let phase = phases.iter().find(|x| x.slots.contains(&index))?;
+
+    phase
+        .images(wallpaper_pack_config)
+        .get(index - phase.slots.start)
+        .map(|x| (phase.name.clone(), x))
+}
+
+
+/// The slots of the phase or anchor containing the schedule slot at `index`.
+fn phase_slots(
+    phases: &[PhaseSpan],
+    index: usize
+) -> Option<Range<usize>> {
+    phases.iter().find(|x| x.slots.contains(&index)).map(|x| x.slots.clone())
+}
+
+
+/// The phase or anchor whose images contain the schedule slot at `index`.
+fn phase_of_index(
+    phases: &[PhaseSpan],
+    index: usize
+) -> Option<String> {
+    phases.iter().find(|x| x.slots.contains(&index)).map(|x| x.name.clone())
+}
+
+
+/// The slots skipped since `last_index` was set that `catch_up` steps
+/// through before `index`. None while changes are held or once the day
+/// started over.
+fn missed_indices(
+    catch_up: CatchUp,
+    last_index: Option<usize>,
+    index: usize,
+    holding: bool
+) -> Range<usize> {
+    match (catch_up, last_index, holding) {
+        (CatchUp::Step, Some(last_index), false) if last_index < index => last_index + 1..index,
+        _ => 0..0,
+    }
+}
+
+
+/// Whether `timestamp` falls into a phase or anchor without images.
+fn in_empty_phase(
+    phases: &[PhaseSpan],
+    timestamp: i64
+) -> bool {
+    phases
+        .iter()
+        .find(|x| x.start <= timestamp && timestamp < x.end)
+        .is_some_and(|x| x.slots.is_empty())
+}
+
+
+/// Whether `timestamp` falls into a phase or anchor without images at night.
+fn in_uncovered_night(
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    phases: &[PhaseSpan],
+    timestamp: i64
+) -> bool {
+    let night = timestamp < sun_and_moon[&SunAndMoonKeys::Sunrise]
+        || timestamp >= sun_and_moon[&SunAndMoonKeys::Sunset];
+
+    night && in_empty_phase(phases, timestamp)
+}
+
+
+/// The image the pack's `night_behavior` shows during uncovered nights, `None`
+/// to carry on as in any other empty phase.
+fn night_image(
+    night_behavior: &NightBehavior,
+    desktop_wallpaper: Option<&PathBuf>,
+    output_dir: &Path
+) -> Option<PathBuf> {
+    match night_behavior {
+        NightBehavior::Loop => None,
+        NightBehavior::DesktopDefault => {
+            if desktop_wallpaper.is_none() {
+                eprintln!("The wallpaper set at startup is unknown, the night is shown as usual.");
+            }
+            desktop_wallpaper.cloned()
+        }
+        NightBehavior::Solid(color) => solid_color::render(color, output_dir)
+            .map_err(|e| eprintln!("{e}"))
+            .ok(),
+    }
+}
+
+
+/// What is shown at `timestamp` instead of the scheduled image and the phase
+/// it's logged as: the night image during nights without images, otherwise
+/// the pack's `default_image` in phases and anchors without any. Only the
+/// phases mode has empty phases.
+fn unscheduled_image(
+    night_image: Option<&PathBuf>,
+    sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+    phases: &[PhaseSpan],
+    wallpaper_pack_config: &WallpaperPackConfig,
+    wallpaper_pack_dir: &String,
+    schedule_mode: ScheduleMode,
+    timestamp: i64
+) -> Option<(PathBuf, &'static str)> {
+    if schedule_mode != ScheduleMode::Phases {
+        return None;
+    }
+
+    if let Some(night_image) = night_image.filter(|_| in_uncovered_night(sun_and_moon, phases, timestamp)) {
+        return Some((night_image.clone(), "Night"));
+    }
+
+    wallpaper_pack_config.default_image
+        .as_ref()
+        .filter(|_| in_empty_phase(phases, timestamp))
+        .map(|x| (Path::new(wallpaper_pack_dir).join(remote::strip_file_scheme(x)), "Default"))
+}
+
+
+/// Path of the image shown in the schedule slot at `index`.
+fn scheduled_image_path(
+    phases: &[PhaseSpan],
+    wallpaper_pack_config: &WallpaperPackConfig,
+    wallpaper_pack_dir: &String,
+    index: usize
+) -> Option<PathBuf> {
+    schedule_slot(phases, wallpaper_pack_config, index).map(|(_, x)| {
+        PathBuf::new()
+            .join(wallpaper_pack_dir)
+            .join(x.path())
+    })
+}
+
+
+/// Picks the slot whose end timestamp is the first one after `current_timestamp`,
+/// clamping to the first/last slot when the time falls outside the schedule.
+fn select_image_index(
+    timestamp_seq: &[i64],
+    current_timestamp: i64
+) -> Option<usize> {
+    if timestamp_seq.is_empty() {
+        return None;
+    }
+
+    Some(
+        timestamp_seq
+            .iter()
+            .position(|x| current_timestamp < *x)
+            .unwrap_or(timestamp_seq.len() - 1)
+    )
+}
+
+
+/// Maps the current apparent solar time onto the flattened image list, which
+/// starts with the midnight images just like the solar day does.
+fn solar_time_index(
+    image_count: usize,
+    current_timestamp: i64,
+    config: &WallpaperChangerConfig
+) -> Option<usize> {
+    if image_count == 0 {
+        return None;
+    }
+
+    let sun = solar::sun_position(current_timestamp, config.longitude, config.latitude);
+    let index = (sun.solar_time / 24.0 * image_count as f64) as usize;
+
+    Some(index.min(image_count - 1))
+}
+
+
+/// Replaces a non-midnight selection with the first midnight image when the
+/// sun is below the configured night altitude threshold.
+fn night_override_index(
+    index: usize,
+    current_timestamp: i64,
+    config: &WallpaperChangerConfig,
+    wallpaper_pack_config: &WallpaperPackConfig
+) -> usize {
+    let Some(threshold) = config.night_sun_altitude_threshold else {
+        return index;
+    };
+
+    // Midnight images always open the schedule, so they occupy the first indices.
+    if wallpaper_pack_config.midnight.is_empty() || index < wallpaper_pack_config.midnight.len() {
+        return index;
+    }
+
+    let sun = solar::sun_position(current_timestamp, config.longitude, config.latitude);
+
+    if sun.altitude < threshold {
+        0
+    } else {
+        index
+    }
+}
+
+
+/// Writes `value` to `path` and reads it back, so a read-only directory or a
+/// full disk fails with the path and the cause rather than leaving a stale or
+/// truncated file behind.
+///
+/// It's serialized with `toml` rather than by confy, whose older `toml`
+/// refuses values following a table, like an inline `[pack]`.
+fn store_file<T: Serialize + DeserializeOwned>(
+    path: &Path,
+    value: &T
+) -> Result<(), String> {
+    let contents = toml::to_string(value)
+        .map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, contents))
+        .map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+
+    let written = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read back {}: {e}", path.display()))?;
+
+    toml::from_str::<T>(&written)
+        .map(|_| ())
+        .map_err(|e| format!("{} doesn't read back as written: {e}", path.display()))
+}
+
+
+/// Flushes the change log and marks the daemon stopped in the state file,
+/// so neither is left half written when the loop ends.
+fn shut_down(
+    change_log: Option<&mut ChangeLog>,
+    state: &mut WallpaperChangerState,
+    state_path: &Path
+) -> Result<(), String> {
+    if let Some(change_log) = change_log {
+        change_log.flush()?;
+    }
+
+    state.status = DaemonStatus::Stopped;
+    state.store(state_path)
+}
+
+
+fn load_config(config_path: &String) -> Result<WallpaperChangerConfig, String> {
+    if !Path::new(config_path).exists() {
+        let config = WallpaperChangerConfig::default();
+        store_file(Path::new(config_path), &config)?;
+
+        return Ok(config);
+    }
+
+    let mut raw_config: toml::Table = toml::from_str(
+            &fs::read_to_string(config_path)
+                .ok()
+                .ok_or("Unable to read the config file.")?
+        ).ok().ok_or("Unable to parse the config file.")?;
+
+    let migrated = migration::migrate_config(&mut raw_config)?;
+
+    let config: WallpaperChangerConfig = toml::Value::Table(raw_config)
+        .try_into()
+        .ok()
+        .ok_or("Unable to load the config file.")?;
+
+    if migrated {
+        store_file(Path::new(config_path), &config)
+            .map_err(|e| format!("Unable to save the migrated config file. {e}"))?;
+
+        println!("Migrated the config file to version {}.", config.version);
+    }
+
+    Ok(config)
+}
+
+
+/// The config file with the `--set` overrides and the coordinates given on
+/// the command line applied over it and its `location` resolved.
+fn effective_config(config_path: &String, cli: &Cli) -> Result<WallpaperChangerConfig, String> {
+    let mut config = overrides::apply(load_config(config_path)?, &cli.overrides)?;
+
+    if let Some(location) = &config.location {
+        (config.latitude, config.longitude) = location::parse_location(location)?;
+    }
+
+    if let Some(position) = route::position(&route::parse(&config.route)?, Local::now().timestamp()) {
+        (config.latitude, config.longitude) = position;
+    }
+
+    if let Some(interval) = cli.interval_override {
+        config.poll_interval_seconds = interval;
+    }
+
+    // Coordinates given for this run stay put.
+    if let Some(latitude) = cli.latitude {
+        config.latitude = latitude;
+        config.route.clear();
+    }
+    if let Some(longitude) = cli.longitude {
+        config.longitude = longitude;
+        config.route.clear();
+    }
+
+    if astronomy::near_pole(config.latitude) {
+        eprintln!(
+            "Latitude {} is within {}° of a pole, where the sun doesn't rise and set daily. \
+            Using fixed times instead: moonset 04:00, sunrise 06:00, noon 12:00, sunset 18:00, moonrise 20:00.",
+            config.latitude,
+            astronomy::POLE_EPSILON
+        );
+    }
+
+    Ok(config)
+}
+
+
+/// Whether the wallpaper last set at `last_set_timestamp` is due to be set
+/// again at `current_timestamp`, see `reapply_interval_seconds`.
+fn reapply_due(
+    last_set_timestamp: Option<i64>,
+    current_timestamp: i64,
+    reapply_interval_seconds: u64
+) -> bool {
+    reapply_interval_seconds > 0 && last_set_timestamp.is_some_and(|x| {
+        current_timestamp - x >= reapply_interval_seconds as i64
+    })
+}
+
+
+/// Installs the Ctrl+C handler once and returns the flag it sets, later calls
+/// return the same flag.
+///
+/// The first press only asks the loop to stop, so the shutdown can flush the
+/// change log and store the state. A second press while that is still going
+/// exits immediately with `FORCE_EXIT_CODE`, skipping the rest of it.
+fn set_terminate_handler() -> Result<Arc<AtomicBool>, String> {
+    if let Some(terminate_loop) = TERMINATE_LOOP.get() {
+        return Ok(terminate_loop.clone());
+    }
+
+    let terminate_loop = Arc::new(AtomicBool::new(false));
+    let tl = terminate_loop.clone();
+
+    ctrlc::set_handler(move || {
+        if tl.swap(true, Ordering::SeqCst) {
+            eprintln!("Ctrl+C pressed again, exiting without finishing the shutdown.");
+            std::process::exit(FORCE_EXIT_CODE);
+        }
+    }).ok().ok_or("Unable to set Ctrl+C handler.")?;
+
+    Ok(TERMINATE_LOOP.get_or_init(|| terminate_loop).clone())
+}
+
+
+/// The next day's schedule, computed `PRECOMPUTE_LEAD_SECONDS` ahead so the
+/// rollover only has to swap it in. Only valid while the pack it was built
+/// for stays active.
+struct PrecomputedDay {
+    day: NaiveDateTime,
+    wallpaper_pack: String,
+    sun_and_moon: HashMap<SunAndMoonKeys, i64>,
+    resolved_pack_config: WallpaperPackConfig,
+    timestamp_seq: Vec<i64>,
+    phases: Vec<PhaseSpan>,
+}
+
+
+fn precompute_next_day(
+    today: NaiveDateTime,
+    config: &WallpaperChangerConfig,
+    wallpaper_pack: &str,
+    wallpaper_pack_config: &WallpaperPackConfig,
+    wallpaper_pack_dir: &String,
+    remote_cache_dir: &Path,
+    decode_checker: &mut DecodeChecker
+) -> Result<PrecomputedDay, String> {
+    let day = today + Duration::days(1);
+
+    let sun_and_moon = get_day_sun_and_moon_position_times(
+        &Geodate,
+        day.timestamp(),
+        config.longitude,
+        config.latitude,
+        BoundaryOptions::from_config(config),
+    )?;
+
+    let mut resolved_pack_config = resolve_pack_entries(
+        wallpaper_pack_config,
+        wallpaper_pack_dir,
+        remote_cache_dir,
+        config
+    )?;
+    decode_checker.filter_pack(config.decode_check, &mut resolved_pack_config, wallpaper_pack_dir)?;
+
+    if resolved_pack_config.is_empty() {
+        return Err(format!("Pack '{wallpaper_pack}' contains no images."));
+    }
+
+    let timestamp_seq = map_timestamps(&sun_and_moon, &resolved_pack_config);
+    let phases = day_phases(&sun_and_moon, &resolved_pack_config);
+
+    Ok(PrecomputedDay {
+        day,
+        wallpaper_pack: wallpaper_pack.to_string(),
+        sun_and_moon,
+        resolved_pack_config,
+        timestamp_seq,
+        phases,
+    })
+}
+
+
+fn current_wallpaper_file(config: &WallpaperChangerConfig, cache_dir: &Path) -> PathBuf {
+    config.current_wallpaper_file
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| cache_dir.join("current"))
+}
+
+
+/// Writes the path of the wallpaper just set to `current_wallpaper_file`. The
+/// file is replaced in one step, readers never see half a path.
+fn write_current_wallpaper(current_wallpaper_file: &Path, image: &Path) {
+    let partial_path = current_wallpaper_file.with_extension("part");
+
+    let result = fs::create_dir_all(current_wallpaper_file.parent().unwrap_or(Path::new("")))
+        .and_then(|_| fs::write(&partial_path, format!("{}\n", image.display())))
+        .and_then(|_| fs::rename(&partial_path, current_wallpaper_file));
+
+    if let Err(e) = result {
+        eprintln!("Unable to write {}: {e}", current_wallpaper_file.display());
+    }
+}
+
+
+fn record_change(
+    change_log: Option<&mut ChangeLog>,
+    phase: Option<String>,
+    monitor: Option<&str>,
+    image: &Path,
+    result: &Result<PathBuf, String>
+) {
+    let Some(change_log) = change_log else {
+        return;
+    };
+
+    let mut record = ChangeRecord::new(
+        phase.unwrap_or_default(),
+        image,
+        result.as_ref().err().map(|x| x.as_str())
+    );
+    record.monitor = monitor;
+
+    if let Err(e) = change_log.record(&record) {
+        eprintln!("{e}");
+    }
+}
+
+
+/// Takes in what `monitor::set_images` set: every change is logged and the
+/// schedules that changed move on to their new image.
+fn record_changes(
+    results: Vec<(Change, Result<PathBuf, String>)>,
+    schedules: &mut [MonitorSchedule],
+    current_wallpaper_path: &Path,
+    mut change_log: Option<&mut ChangeLog>,
+    metrics: &mut SessionMetrics
+) {
+    for (change, result) in results {
+        let schedule = &mut schedules[change.schedule];
+
+        record_change(
+            change_log.as_deref_mut(),
+            change.phase,
+            schedule.monitor().as_deref(),
+            &change.image,
+            &result
+        );
+
+        match result {
+            Ok(wallpaper) => {
+                write_current_wallpaper(current_wallpaper_path, &wallpaper);
+                schedule.current_image = Some(change.image);
+                schedule.last_change_timestamp = Some(Local::now().timestamp());
+                metrics.wallpaper_changes += 1;
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                metrics.set_failures += 1;
+            }
+        }
+    }
+}
+
+
+#[derive(Debug)]
+struct SessionMetrics {
+    started: Instant,
+    wallpaper_changes: u64,
+    set_failures: u64,
+    day_rollovers: u64,
+    stale_images_dropped: u64,
+}
+
+impl SessionMetrics {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            wallpaper_changes: 0,
+            set_failures: 0,
+            day_rollovers: 0,
+            stale_images_dropped: 0,
+        }
+    }
+
+    fn print_summary(&self) {
+        let uptime = self.started.elapsed().as_secs();
+
+        println!(
+            "Session summary:\n  \
+            Uptime: {:02}:{:02}:{:02}\n  \
+            Wallpaper changes: {}\n  \
+            Set failures: {}\n  \
+            Day rollovers: {}\n  \
+            Stale images dropped: {}",
+            uptime / 3600,
+            uptime % 3600 / 60,
+            uptime % 60,
+            self.wallpaper_changes,
+            self.set_failures,
+            self.day_rollovers,
+            self.stale_images_dropped,
+        );
+    }
+}
+
+
+/// Whether a change at `current_timestamp` comes sooner than
+/// `min_change_interval_seconds` after the last one, whichever day it was on.
+fn change_too_soon(
+    last_change_timestamp: Option<i64>,
+    current_timestamp: i64,
+    min_change_interval_seconds: u64
+) -> bool {
+    last_change_timestamp.is_some_and(|x| {
+        current_timestamp - x < min_change_interval_seconds as i64
+    })
+}
+
+
+fn main() {
+    if let Err(e) = run() {
+        e.exit();
+    }
+}
+
+
+fn run() -> Result<(), WallpaperChangerError> {
+    let cli = Cli::parse();
+
+    let app_name= "wallpaper_changer_rust".to_string();
+    let config_name = "wallpaper_changer_config.toml".to_string();
+    let state_name = "wallpaper_changer_state.toml".to_string();
+    let pack_env_var = "WALLPAPER_CHANGER_PACK".to_string();
+    let wallpaper_pack_config_name = "wallpaper_pack_config.toml".to_string();
+
+    if let Some(Command::Completions { shell }) = &cli.command {
+        clap_complete::generate(*shell, &mut Cli::command(), &app_name, &mut std::io::stdout());
+        return Ok(());
+    }
+
+    if let Some(Command::InstallAutostart) = &cli.command {
+        return Ok(autostart::install(&app_name)?);
+    }
+
+    if let Some(Command::UninstallAutostart) = &cli.command {
+        return Ok(autostart::uninstall(&app_name)?);
+    }
+
+    let project_dirs: ProjectDirs = ProjectDirs::from(
+        "hr",
+        "IDerdic",
+        &app_name
+    ).ok_or("Unable to create ProjectDirs struct.")?;
+
+    let wallpaper_packs_dir = project_dirs
+        .data_local_dir()
+        .to_path_buf()
+        .join("wallpaper_packs")
+        .to_str()
+        .ok_or("Unable to convert PathBuf to &str.")?
+        .to_string();
+
+    if !Path::new(&wallpaper_packs_dir).exists() {
+        fs::create_dir_all(&wallpaper_packs_dir)
+            .ok()
+            .ok_or("Unable to create wallpaper pack directory tree.")?;
+    }
+
+    let state_path = project_dirs
+        .data_local_dir()
+        .join(&state_name);
+
+    let disabled_path = project_dirs
+        .data_local_dir()
+        .join("disabled");
+
+    if let Some(Command::Disable | Command::Enable) = &cli.command {
+        let disable = matches!(cli.command, Some(Command::Disable));
+
+        state::set_disabled(&disabled_path, disable)?;
+        println!("Wallpaper changes are {}.", if disable { "disabled" } else { "enabled" });
+
+        return Ok(());
+    }
+
+    if let Some(Command::ReorderPack { pack, phase }) = &cli.command {
+        return reorder::run(&wallpaper_packs_dir, pack, phase, &wallpaper_pack_config_name)
+            .map_err(WallpaperChangerError::Pack);
+    }
+
+    if let Some(Command::UpgradePack { pack }) = &cli.command {
+        return upgrade::run(&wallpaper_packs_dir, pack, &wallpaper_pack_config_name)
+            .map_err(WallpaperChangerError::Pack);
+    }
+
+    if let Some(Command::MergePacks { first, second, output }) = &cli.command {
+        return merge::run(&wallpaper_packs_dir, first, second, output, &wallpaper_pack_config_name)
+            .map_err(WallpaperChangerError::Pack);
+    }
+
+    if let Some(Command::CheckUpdates { pack, download }) = &cli.command {
+        return Ok(updates::run(&wallpaper_packs_dir, pack, &wallpaper_pack_config_name, *download)?);
+    }
+
+    let today = Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .ok_or("Unable to get current day timestamp.")?;
+
+    let config_path = project_dirs
+        .config_local_dir()
+        .to_path_buf()
+        .join(&config_name)
+        .to_str()
+        .ok_or("Unable to convert PathBuf to &str.")?
+        .to_string();
+
+    let mut config = effective_config(&config_path, &cli).map_err(WallpaperChangerError::Config)?;
+    select_linked_pack(&mut config, &wallpaper_packs_dir).map_err(WallpaperChangerError::Pack)?;
+
+    if let Some(Command::Watch { lines }) = &cli.command {
+        let change_log_path = config.change_log.path
+            .as_ref()
+            .ok_or_else(|| "No change_log path is configured, there is no log to watch.".to_string())
+            .map_err(WallpaperChangerError::Config)?;
+
+        return Ok(change_log::follow(Path::new(change_log_path), *lines, set_terminate_handler()?)?);
+    }
+
+    if let Some(Command::RemovePack { pack, yes, force }) = &cli.command {
+        return remove::run(&config, &wallpaper_packs_dir, pack, *yes, *force)
+            .map_err(WallpaperChangerError::Pack);
+    }
+
+    if let Some(Command::AnalyzePack { pack }) = &cli.command {
+        let (wallpaper_pack_dir, wallpaper_pack_config) = load_wallpaper_pack(
+            &wallpaper_packs_dir,
+            pack,
+            &wallpaper_pack_config_name
+        ).map_err(WallpaperChangerError::Pack)?;
+
+        let resolved_pack_config = resolve_pack_entries(
+            &wallpaper_pack_config,
+            &wallpaper_pack_dir,
+            &project_dirs.cache_dir().join("remote"),
+            &config
+        ).map_err(WallpaperChangerError::Pack)?;
+
+        return Ok(bench::analyze(today, &config, pack, &resolved_pack_config)?);
+    }
+
+    let temp_dir = config.temp_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join(&app_name));
+
+    if let Some(Command::PreviewTransition { from, to, restore }) = &cli.command {
+        let transition = match &cli.pack {
+            Some(pack) => {
+                let (_, wallpaper_pack_config) = load_wallpaper_pack(
+                    &wallpaper_packs_dir,
+                    pack,
+                    &wallpaper_pack_config_name
+                ).map_err(WallpaperChangerError::Pack)?;
+
+                PlaybackSettings::resolve(&config, &wallpaper_pack_config, None).transition
+            }
+            None => config.transition.clone(),
+        };
+
+        return transition::preview(
+            Path::new(from),
+            Path::new(to),
+            &transition,
+            &temp_dir.join("preview_transition"),
+            *restore
+        ).map_err(WallpaperChangerError::Backend);
+    }
+
+    if let Some(Command::BenchSet { image, times }) = &cli.command {
+        return backend::bench_set(Path::new(image), *times).map_err(WallpaperChangerError::Backend);
+    }
+
+    if cli.check_backend {
+        backend::print_capabilities();
+        return Ok(());
+    }
+
+    if let Some(color) = &cli.color {
+        return solid_color::set(color, &temp_dir.join("solid_color"))
+            .map_err(WallpaperChangerError::Backend);
+    }
+
+    if let Some(day) = cli.dump_astronomy {
+        let day_start = day
+            .and_hms_opt(0, 0, 0)
+            .ok_or("Unable to get day timestamp.")?;
+
+        let sun_and_moon = get_day_sun_and_moon_position_times(
+            &Geodate,
+            day_start.timestamp(),
+            config.longitude,
+            config.latitude,
+            BoundaryOptions {
+                use_moon: true,
+                time_offset_minutes: 0,
+                day_boundary: DayBoundary::Solar,
+                round_to_minute: false,
+            },
+        ).map_err(WallpaperChangerError::Astronomy)?;
+
+        print_astronomy(&sun_and_moon, config.latitude, config.longitude, day);
+        return Ok(());
+    }
+
+    if cli.list_phases {
+        let sun_and_moon = get_day_sun_and_moon_position_times(
+            &Geodate,
+            today.timestamp(),
+            config.longitude,
+            config.latitude,
+            BoundaryOptions::from_config(&config),
+        ).map_err(WallpaperChangerError::Astronomy)?;
+
+        print_phase_boundaries(&sun_and_moon, &config, today.date());
+        return Ok(());
+    }
+
+    // Only runs that would set a wallpaper stop here, printing schedules and
+    // benchmarks still work.
+    let report = matches!(cli.command, Some(Command::Report));
+    let dry_run = cli.bench || cli.timeline || cli.dump_image_for.is_some() || report;
+    // The demo sets the pack's images itself and nothing else.
+    let demo = matches!(cli.command, Some(Command::Demo { .. }));
+
+    if state::is_disabled(&disabled_path) && !dry_run {
+        println!("Wallpaper changes are disabled, run `{app_name} enable` to turn them back on.");
+        return Ok(());
+    }
+
+    // Captured before anything is set, for packs showing it at night.
+    let desktop_wallpaper = wallpaper::get()
+        .ok()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from);
+
+    let mut current_wallpaper_path = current_wallpaper_file(&config, project_dirs.cache_dir());
+
+    let startup_image = match (&config.startup_image, dry_run || demo) {
+        (Some(startup_image), false) => {
+            let startup_image = PathBuf::from(remote::strip_file_scheme(startup_image));
+
+            match transition::apply(None, &startup_image, &Transition::Instant, Path::new("")) {
+                Ok(_) => {
+                    write_current_wallpaper(&current_wallpaper_path, &startup_image);
+                    Some(startup_image)
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let remote_cache_dir = project_dirs.cache_dir().join("remote");
+
+    if let Some(pack_path) = &cli.pack_path {
+        if !Path::new(pack_path).is_dir() {
+            return Err(WallpaperChangerError::Pack(format!("Wallpaper pack path is not a directory: {pack_path}")));
+        }
+    }
+
+    // Composite regions and workspaces each run a schedule of their own, only
+    // when wallpapers are actually set.
+    let multiple_schedules = !dry_run && !demo && cli.pack_path.is_none();
+    let composite_mode = multiple_schedules && !config.composite_regions.is_empty();
+    let mut workspace_mode = multiple_schedules && !composite_mode && !config.workspace_packs.is_empty();
+
+    if workspace_mode && !workspace::supported() {
+        eprintln!("Per-workspace wallpapers aren't supported here, the pack is set on every workspace.");
+        workspace_mode = false;
+    }
+
+    let mut state = WallpaperChangerState::load(&state_path)?;
+    let mut rng = Rng::new(config.random_seed);
+
+    // An explicit pack from the CLI or the environment pins the pack for this
+    // run, bypassing the rotation and power source switching. A pack path is
+    // named after its directory.
+    let pack_override = cli.pack_path
+        .as_ref()
+        .map(|x| Path::new(x).file_name().unwrap_or_default().to_string_lossy().to_string())
+        .or_else(|| cli.pack.clone())
+        .or_else(|| std::env::var(&pack_env_var).ok())
+        .filter(|x| !x.is_empty());
+
+    let pinned_pack = pack_override.is_some();
+
+    if pinned_pack {
+        config.pack_rotation.clear();
+        config.battery_pack = None;
+        config.ac_pack = None;
+    }
+
+    let mut base_wallpaper_pack = match rotate_pack(&config, &mut state, today.date()) {
+        Some(wallpaper_pack) => {
+            state.store(&state_path)?;
+            wallpaper_pack
+        }
+        None => pack_override
+            .or_else(|| {
+                config.random_pack_on_launch
+                    .then(|| random_pack(&config, &wallpaper_packs_dir, &wallpaper_pack_config_name, &mut rng))
+                    .flatten()
+            })
+            .unwrap_or_else(|| config.wallpaper_pack.clone()),
+    };
+
+    let mut power_switching = config.battery_pack.is_some() || config.ac_pack.is_some();
+    let mut power_source = if power_switching { power::detect() } else { None };
+
+    let wallpaper_pack = active_wallpaper_pack(&config, &base_wallpaper_pack, power_source);
+
+    if wallpaper_pack.is_empty() && config.pack.is_none() && !composite_mode && !workspace_mode {
+        println!(
+            "Wallpaper pack is not selected.\nCheck the config folder at path: {config_path}\n\
+            or link a pack as `{CURRENT_PACK_LINK}` in: {wallpaper_packs_dir}"
+        );
+        return Ok(());
+    }
+
+    if config.decode_check != DecodeCheck::Off && cfg!(not(feature = "image")) {
+        eprintln!("Checking that images decode requires the `image` feature.");
+    }
+
+    let overlay_dir = temp_dir.join("overlay");
+    let gamma_dir = temp_dir.join("gamma");
+    let effects_dir = temp_dir.join("effects");
+    let alpha_dir = temp_dir.join("alpha");
+    let orientation_dir = temp_dir.join("orientation");
+    let color_grade_dir = temp_dir.join("color_grade");
+    let weekly_blend_dir = temp_dir.join("weekly_blend");
+
+    let outputs = match (composite_mode, workspace_mode) {
+        (true, _) => config.composite_regions
+            .iter()
+            .enumerate()
+            .map(|(index, x)| (Output::Region(index), x.pack.clone()))
+            .collect::<Vec<(Output, String)>>(),
+        (false, true) => workspace::workspaces(&config)
+            .map_err(WallpaperChangerError::Backend)?
+            .into_iter()
+            .map(|(index, pack)| (Output::Workspace(index), pack))
+            .collect(),
+        (false, false) => vec![(Output::Desktop, wallpaper_pack)],
+    };
+
+    if outputs.is_empty() {
+        return Err(WallpaperChangerError::Config("None of the workspaces in workspace_packs exist.".to_string()));
+    }
+
+    let mut decode_checker = DecodeChecker::default();
+    let mut schedules = vec![];
+
+    for (output, wallpaper_pack) in outputs {
+        let image_processor = ImageProcessor::new(ProcessingDirs {
+            alpha: alpha_dir.clone(),
+            color_grade: color_grade_dir.clone(),
+            effects: effects_dir.clone(),
+            gamma: gamma_dir.clone(),
+            orientation: orientation_dir.clone(),
+            overlay: match output {
+                Output::Workspace(workspace) => overlay_dir.join(format!("workspace{workspace}")),
+                _ => overlay_dir.clone(),
+            },
+        });
+
+        let mut schedule = MonitorSchedule::new(output, wallpaper_pack, image_processor);
+        schedule.pack_path = cli.pack_path.clone().filter(|_| schedule.follows_active_pack);
+
+        schedule
+            .load_pack(&config, &config_path, &wallpaper_packs_dir, &wallpaper_pack_config_name, cli.interval_override)
+            .map_err(WallpaperChangerError::Pack)?;
+        schedule
+            .set_day(Local::now().timestamp(), &config)
+            .map_err(WallpaperChangerError::Astronomy)?;
+        schedule
+            .remap(&config, &remote_cache_dir, &mut decode_checker)
+            .map_err(WallpaperChangerError::Pack)?;
+
+        schedules.push(schedule);
+    }
+
+    // Runs that don't set wallpapers only ever have the desktop's schedule.
+    let MonitorSchedule {
+        wallpaper_pack,
+        wallpaper_pack_dir,
+        wallpaper_pack_config,
+        resolved_pack_config,
+        ..
+    } = &schedules[0];
+
+    if cli.bench {
+        return Ok(bench::run(today, &config, resolved_pack_config)?);
+    }
+
+    if cli.timeline {
+        let sun_and_moon = get_day_sun_and_moon_position_times(
+            &Geodate,
+            today.timestamp(),
+            config.longitude,
+            config.latitude,
+            BoundaryOptions::from_config(&config),
+        ).map_err(WallpaperChangerError::Astronomy)?;
+
+        let timestamp_seq = map_timestamps(&sun_and_moon, resolved_pack_config);
+        let day_phases = day_phases(&sun_and_moon, resolved_pack_config);
+        let phases = (0..timestamp_seq.len())
+            .map(|x| phase_of_index(&day_phases, x).unwrap_or_default())
+            .collect::<Vec<String>>();
+
+        println!("Schedule of '{wallpaper_pack}' for {}:", today.date());
+        println!(
+            "{}",
+            timeline::render(
+                today.timestamp(),
+                sun_and_moon[&SunAndMoonKeys::NextDayMidnight],
+                &timestamp_seq,
+                &phases,
+                timeline::terminal_width()
+            )
+        );
+
+        if cli.histogram {
+            let slots = slot_durations(&sun_and_moon, &timestamp_seq)
+                .into_iter()
+                .enumerate()
+                .map(|(index, duration)| {
+                    let label = schedule_slot(&day_phases, resolved_pack_config, index)
+                        .map(|(phase, image)| format!("{phase} {}", image.path()))
+                        .unwrap_or_default();
+
+                    (label, duration)
+                })
+                .collect::<Vec<(String, i64)>>();
+
+            let poll_interval_seconds = PlaybackSettings::resolve(&config, wallpaper_pack_config, cli.interval_override)
+                .poll_interval_seconds;
+
+            println!();
+            println!("Display time per image:");
+            println!(
+                "{}",
+                timeline::histogram(&slots, poll_interval_seconds as i64, timeline::terminal_width())
+            );
+        }
+
+        return Ok(());
+    }
+
+    if report {
+        return Ok(report::run(
+            today,
+            &config,
+            wallpaper_pack,
+            wallpaper_pack_dir,
+            resolved_pack_config
+        )?);
+    }
+
+    if let Some(Command::Demo { dwell_seconds }) = &cli.command {
+        // A fixed day stands in for the astronomy, only the order matters.
+        let sun_and_moon = get_day_sun_and_moon_position_times(
+            &FixedAstronomy::default(),
+            today.timestamp(),
+            config.longitude,
+            config.latitude,
+            BoundaryOptions::from_config(&config),
+        ).map_err(WallpaperChangerError::Astronomy)?;
+
+        let phases = day_phases(&sun_and_moon, resolved_pack_config);
+        let images = (0..)
+            .map_while(|x| scheduled_image_path(&phases, resolved_pack_config, wallpaper_pack_dir, x))
+            .collect::<Vec<PathBuf>>();
+
+        return demo::run(&images, *dwell_seconds, set_terminate_handler()?)
+            .map_err(WallpaperChangerError::Backend);
+    }
+
+    if let Some(time) = cli.dump_image_for {
+        return print_image_for(time, &config, resolved_pack_config, wallpaper_pack_dir)
+            .map_err(WallpaperChangerError::Astronomy);
+    }
+
+    if !display::displays_available() {
+        return Err(WallpaperChangerError::NoDisplays);
+    }
+
+    let night_dir = temp_dir.join("night");
+
+    for schedule in schedules.iter_mut() {
+        schedule.night_image_path = night_image(
+            &schedule.wallpaper_pack_config.night_behavior,
+            desktop_wallpaper.as_ref(),
+            &night_dir
+        );
+    }
+
+    let unsupported = schedules
+        .iter()
+        .flat_map(|x| x.playback.transitions())
+        .find(|x| **x != Transition::Instant);
+
+    if let Some(transition) = unsupported {
+        transition::warn_if_unsupported(transition);
+    }
+
+    let transition_frames_dir = temp_dir.join("transition");
+
+    let mut composite = match composite_mode {
+        true => Some(Composite::new(&config, &temp_dir.join("composite"))?),
+        false => None,
+    };
+
+    // The first change transitions from the startup image.
+    if let Some(schedule) = schedules.first_mut().filter(|x| x.output == Output::Desktop) {
+        schedule.current_image = startup_image.clone();
+    }
+    if let Some(composite) = composite.as_mut() {
+        composite.canvas = startup_image;
+    }
+
+    let mut quiet_window = config
+        .quiet_hours
+        .as_ref()
+        .map(|x| x.window())
+        .transpose()
+        .map_err(WallpaperChangerError::Config)?;
+
+    let mut route_points = route::parse(&config.route).map_err(WallpaperChangerError::Config)?;
+
+    let mut change_log = config
+        .change_log
+        .path
+        .as_ref()
+        .map(|x| ChangeLog::open(Path::new(x), config.change_log.format))
+        .transpose()?;
+
+    if config.ambient_light.is_some() && cfg!(not(feature = "ambient-light")) {
+        eprintln!("Ambient light input requires the `ambient-light` feature.");
+    }
+
+    let mut ambient_selector = AmbientSelector::default();
+
+    if config.reapply_on_display_change && cfg!(not(feature = "display-info")) {
+        eprintln!("Reapplying on display changes requires the `display-info` feature.");
+    }
+
+    let mut display_topology = if config.reapply_on_display_change { display::topology() } else { None };
+
+    let mut current_timestamp = Local::now().timestamp();
+    let mut last_reapply_timestamp: Option<i64> = None;
+    let mut metrics = SessionMetrics::new();
+
+    let mut weather = Weather::default();
+    let weather_blend_dir = temp_dir.join("weather");
+
+    // Overlays rendered by earlier runs show stale text.
+    let _ = fs::remove_dir_all(&overlay_dir);
+
+    let load_blend_pack = |config: &WallpaperChangerConfig| {
+        config.weekly_blend.as_ref().filter(|_| cfg!(feature = "image")).and_then(|x| {
+            BlendPack::load(x, &wallpaper_packs_dir, &wallpaper_pack_config_name, &remote_cache_dir, config)
+                .map_err(|e| eprintln!("{e}\nShowing the active pack alone."))
+                .ok()
+        })
+    };
+    let mut blend_pack = load_blend_pack(&config);
+
+    if config.gamma != 1.0 && cfg!(not(feature = "image")) {
+        eprintln!("Gamma correction requires the `image` feature.");
+    }
+
+    if config.color_grade.is_some() && cfg!(not(feature = "image")) {
+        eprintln!("Color grading requires the `image` feature.");
+    }
+
+    if config.weekly_blend.is_some() && cfg!(not(feature = "image")) {
+        eprintln!("Weekly blends require the `image` feature.");
+    }
+
+    if config.overlay.is_some() && cfg!(not(feature = "overlay")) {
+        eprintln!("Text overlays require the `overlay` feature.");
+    }
+
+    if schedules.iter().any(|x| !x.wallpaper_pack_config.effects.is_empty()) && cfg!(not(feature = "image")) {
+        eprintln!("Image effects require the `image` feature.");
+    }
+
+    // Blends are reused by name, drop the ones rendered from older images.
+    if config.weather {
+        let _ = fs::remove_dir_all(&weather_blend_dir);
+    }
+
+    let control: SharedControl = Arc::default();
+
+    if let Some(control_address) = &config.control_address {
+        if let Err(e) = control::serve(
+            control_address,
+            control.clone(),
+            wallpaper_packs_dir.clone(),
+            wallpaper_pack_config_name.clone()
+        ) {
+            eprintln!("{e}");
+        }
+    }
+
+    let mut config_watcher = match config.watch_config_files {
+        true => FileWatcher::new().map_err(|e| eprintln!("{e}")).ok(),
+        false => None,
+    };
+    let pack_config_path = |wallpaper_pack_dir: &String| {
+        Path::new(wallpaper_pack_dir).join(&wallpaper_pack_config_name)
+    };
+    let watched_files = |schedules: &[MonitorSchedule]| {
+        std::iter::once(PathBuf::from(&config_path))
+            .chain(schedules.iter().map(|x| pack_config_path(&x.wallpaper_pack_dir)))
+            .collect::<Vec<PathBuf>>()
+    };
+
+    if let Some(config_watcher) = config_watcher.as_mut() {
+        config_watcher.set_files(watched_files(&schedules));
+    }
+
+    let terminate_loop = set_terminate_handler()?;
+
+    state.status = DaemonStatus::Running;
+    state.store(&state_path)?;
+
+    while !terminate_loop.load(Ordering::SeqCst) {
+        if power_switching {
+            power_source = power::detect();
+        }
+
+        let (paused, mut reload_packs) = match control.lock() {
+            Ok(mut control) => {
+                // A switched pack holds until the rotation picks the next one.
+                if let Some(requested_pack) = control.requested_pack.take() {
+                    base_wallpaper_pack = requested_pack;
+
+                    for schedule in schedules.iter_mut().filter(|x| x.follows_active_pack) {
+                        schedule.pack_path = None;
+                    }
+                }
+
+                (control.paused, std::mem::take(&mut control.reload_requested))
+            }
+            Err(_) => (false, false),
+        };
+
+        let paused = paused || state::is_disabled(&disabled_path);
+
+        let changed_files = config_watcher
+            .as_mut()
+            .map(|x| x.changes())
+            .unwrap_or_default();
+
+        // Pack configs saved since the last poll, the schedules showing them
+        // reload them.
+        let mut changed_pack_configs = vec![];
+        // Every schedule moves to the day containing now again, for a changed
+        // config or position.
+        let mut recompute_days = false;
+
+        // A half written or broken file is reported and the running config
+        // kept, the next save is picked up again.
+        for changed_file in changed_files {
+            if changed_file != Path::new(&config_path) {
+                let wallpaper_pack_dir = changed_file
+                    .parent()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                match load_wallpaper_pack_from_dir(wallpaper_pack_dir, &wallpaper_pack_config_name) {
+                    Ok(_) => changed_pack_configs.push(changed_file),
+                    Err(e) => eprintln!("Not reloading the changed pack config: {e}"),
+                }
+                continue;
+            }
+
+            let next_config = effective_config(&config_path, &cli).and_then(|mut x| {
+                select_linked_pack(&mut x, &wallpaper_packs_dir)?;
+                let next_quiet_window = x.quiet_hours.as_ref().map(|x| x.window()).transpose()?;
+                Ok((x, next_quiet_window))
+            });
+
+            let (mut next_config, next_quiet_window) = match next_config {
+                Ok(x) => x,
+                Err(e) => {
+                    eprintln!("Not reloading the changed config: {e}");
+                    continue;
+                }
+            };
+
+            if pinned_pack {
+                next_config.pack_rotation.clear();
+                next_config.battery_pack = None;
+                next_config.ac_pack = None;
+            } else if next_config.pack_rotation.is_empty() && !next_config.random_pack_on_launch {
+                base_wallpaper_pack = next_config.wallpaper_pack.clone();
+            }
+
+            config = next_config;
+            quiet_window = next_quiet_window;
+            route_points = route::parse(&config.route).unwrap_or_default();
+            current_wallpaper_path = current_wallpaper_file(&config, project_dirs.cache_dir());
+            power_switching = config.battery_pack.is_some() || config.ac_pack.is_some();
+            power_source = if power_switching { power::detect() } else { None };
+
+            println!("Reloaded the changed config.");
+            recompute_days = true;
+            reload_packs = true;
+        }
+
+        // Moving along the route shifts the sun and moon times with it.
+        if let Some((latitude, longitude)) = route::position(&route_points, current_timestamp) {
+            let moved = (latitude - config.latitude).abs().max((longitude - config.longitude).abs());
+
+            if moved > route::RECOMPUTE_DEGREES {
+                (config.latitude, config.longitude) = (latitude, longitude);
+                recompute_days = true;
+            }
+        }
+
+        // A newly attached display often comes up with the desktop's default
+        // wallpaper, forgetting the current images makes them get set again.
+        if config.reapply_on_display_change {
+            let next_display_topology = display::topology();
+
+            if next_display_topology != display_topology {
+                display_topology = next_display_topology;
+
+                for schedule in schedules.iter_mut() {
+                    schedule.current_image = None;
+                }
+            }
+        }
+
+        let mut packs_loaded = false;
+        let mut holding = vec![false; schedules.len()];
+        let mut catch_up_steps = vec![];
+
+        for schedule_index in 0..schedules.len() {
+            let schedule = &mut schedules[schedule_index];
+            let mut remap_schedule = false;
+
+            // The new day's schedule is built from the same instant it is then
+            // selected with, so the first midnight image shows without a gap.
+            if current_timestamp >= schedule.day_end() {
+                // A pack switched by the rotation is reloaded and remapped below.
+                if !schedule.swap_in_next_day(current_timestamp) {
+                    schedule
+                        .set_day(current_timestamp, &config)
+                        .map_err(WallpaperChangerError::Astronomy)?;
+                    remap_schedule = true;
+                }
+
+                if schedule.follows_active_pack {
+                    if let Some(rotated_pack) = rotate_pack(&config, &mut state, schedule.day.date()) {
+                        state.store(&state_path)?;
+                        base_wallpaper_pack = rotated_pack;
+                    }
+                }
+
+                if schedule_index == 0 {
+                    metrics.day_rollovers += 1;
+                }
+            } else if recompute_days {
+                schedule
+                    .set_day(current_timestamp, &config)
+                    .map_err(WallpaperChangerError::Astronomy)?;
+                remap_schedule = true;
+            }
+
+            let next_wallpaper_pack = match schedule.follows_active_pack {
+                true => active_wallpaper_pack(&config, &base_wallpaper_pack, power_source),
+                false => schedule.wallpaper_pack.clone(),
+            };
+            let pack_config_changed = changed_pack_configs.contains(&pack_config_path(&schedule.wallpaper_pack_dir));
+
+            // A pack that failed to load is tried again on every poll.
+            let reload_pack = next_wallpaper_pack != schedule.wallpaper_pack
+                || reload_packs
+                || pack_config_changed
+                || schedule.pack_error.is_some();
+
+            if reload_pack {
+                let wallpaper_pack_dir = schedule.wallpaper_pack_dir.clone();
+                schedule.wallpaper_pack = next_wallpaper_pack;
+
+                let loaded = schedule.reload_pack(
+                    &config,
+                    &config_path,
+                    &wallpaper_packs_dir,
+                    &wallpaper_pack_config_name,
+                    cli.interval_override
+                );
+
+                if loaded {
+                    schedule.night_image_path = night_image(
+                        &schedule.wallpaper_pack_config.night_behavior,
+                        desktop_wallpaper.as_ref(),
+                        &night_dir
+                    );
+
+                    packs_loaded |= schedule.wallpaper_pack_dir != wallpaper_pack_dir;
+                    remap_schedule = true;
+                }
+            }
+
+            // A pack directory deleted or moved while running holds the
+            // wallpaper until it's back, unless the configured pack can stand in.
+            match schedule.check_pack_dir() {
+                PackDirChange::Missing => {
+                    let fallback_available = schedule.follows_active_pack
+                        && schedule.pack_path.is_none()
+                        && schedule.wallpaper_pack != config.wallpaper_pack
+                        && (config.pack.is_some() || Path::new(&wallpaper_packs_dir).join(&config.wallpaper_pack).is_dir());
+
+                    match fallback_available {
+                        true => {
+                            eprintln!("Falling back to the configured pack '{}'.", config.wallpaper_pack);
+                            base_wallpaper_pack = config.wallpaper_pack.clone();
+                        }
+                        false => eprintln!("Holding the current wallpaper until it's back."),
+                    }
+                }
+                PackDirChange::Back => remap_schedule = true,
+                PackDirChange::Unchanged => {}
+            }
+
+            let remapped = remap_schedule
+                && schedule.missing_pack_dir.is_none()
+                && schedule.remap_or_hold(&config, &remote_cache_dir, &mut decode_checker);
+
+            if remapped && schedule.follows_active_pack {
+                blend_pack = load_blend_pack(&config);
+            }
+
+            let paused = paused || schedule.holding();
+
+            let selected_index = schedule.selection_cache.get_or_select(current_timestamp, &schedule.timestamp_seq, || {
+                match config.schedule_mode {
+                    ScheduleMode::Phases => select_image_index(&schedule.timestamp_seq, current_timestamp),
+                    ScheduleMode::SolarTime => solar_time_index(schedule.timestamp_seq.len(), current_timestamp, &config),
+                }
+            });
+
+            let Some(index) = selected_index else {
+                continue;
+            };
+
+            let sun_and_moon = &schedule.sun_and_moon;
+            let phases = &schedule.phases;
+            let resolved_pack_config = &schedule.resolved_pack_config;
+            let wallpaper_pack_dir = &schedule.wallpaper_pack_dir;
+
+            let index = night_override_index(
+                index,
+                current_timestamp,
+                &config,
+                resolved_pack_config
+            );
+
+            let index = match (&config.ambient_light, phase_slots(phases, index)) {
+                (Some(ambient_light), Some(slots)) => ambient_selector.select(ambient_light, index, slots, |x| {
+                    scheduled_image_path(phases, resolved_pack_config, wallpaper_pack_dir, x)
+                }),
+                _ => index,
+            };
+
+            let too_soon = change_too_soon(
+                schedule.last_change_timestamp,
+                current_timestamp,
+                config.min_change_interval_seconds
+            );
+
+            // Held changes are not lost, they are set once the hold ends.
+            let quiet = paused || too_soon || quiet_window.is_some_and(|x| {
+                Local
+                    .timestamp_opt(current_timestamp, 0)
+                    .single()
+                    .is_some_and(|now| x.contains(now.time()))
+            });
+
+            holding[schedule_index] = quiet;
+
+            let missed_indices = missed_indices(config.catch_up, schedule.last_index, index, quiet);
+
+            let grade = config.color_grade.as_ref().and_then(|x| {
+                x.at(solar::sun_position(current_timestamp, config.longitude, config.latitude).altitude)
+            });
+
+            let blend_weight = config.weekly_blend.as_ref().map(|x| {
+                let now = Local
+                    .timestamp_opt(current_timestamp, 0)
+                    .single()
+                    .unwrap_or_else(Local::now);
+
+                weekly_blend::weight(x.curve, now.naive_local())
+            }).unwrap_or_default();
+
+            // Only the active pack turns into the blend pack over the week.
+            let blend_pack = blend_pack.as_ref().filter(|_| schedule.follows_active_pack);
+
+            for missed_index in missed_indices {
+                let Some(image) = scheduled_image_path(
+                    phases,
+                    resolved_pack_config,
+                    wallpaper_pack_dir,
+                    missed_index
+                ) else {
+                    continue;
+                };
+                let phase = phase_of_index(phases, missed_index);
+
+                let image = match blend_pack {
+                    Some(blend_pack) => blend_pack.blended_image(
+                        &image,
+                        phases,
+                        missed_index,
+                        blend_weight,
+                        &weekly_blend_dir
+                    ),
+                    None => image,
+                };
+                let image = orientation::oriented_image(&image, &orientation_dir);
+                let image = alpha::flattened_image(
+                    &image,
+                    &config.alpha_background,
+                    config.processing_strip_rows,
+                    &alpha_dir
+                );
+                let image = effects::applied_image(
+                    &image,
+                    effects::for_phase(&resolved_pack_config.effects, phase.as_deref()),
+                    config.processing_strip_rows,
+                    &effects_dir
+                );
+                let image = color_grade::graded_image(&image, grade, config.processing_strip_rows, &color_grade_dir);
+                let image = gamma::corrected_image(&image, config.gamma, config.processing_strip_rows, &gamma_dir);
+
+                catch_up_steps.push(Change {
+                    schedule: schedule_index,
+                    image,
+                    phase,
+                    transition: Transition::Instant,
+                });
+            }
+
+            let unscheduled = unscheduled_image(
+                schedule.night_image_path.as_ref(),
+                sun_and_moon,
+                phases,
+                resolved_pack_config,
+                wallpaper_pack_dir,
+                config.schedule_mode,
+                current_timestamp
+            );
+
+            let image = match &unscheduled {
+                Some((image, _)) => Some(image.clone()),
+                None => scheduled_image_path(
+                    phases,
+                    resolved_pack_config,
+                    wallpaper_pack_dir,
+                    index
+                ),
+            };
+
+            let image = match (&resolved_pack_config.overcast_dir, config.weather) {
+                (Some(overcast_dir), true) => image.map(|x| {
+                    let overcast = x
+                        .strip_prefix(wallpaper_pack_dir)
+                        .map(|relative| Path::new(wallpaper_pack_dir).join(overcast_dir).join(relative))
+                        .unwrap_or_default();
+
+                    weather.select_image(&x, &overcast, config.latitude, config.longitude, &weather_blend_dir)
+                }),
+                _ => image,
+            };
+
+            // Only scheduled images have a counterpart in the blend pack.
+            let image = match (blend_pack, unscheduled.is_none()) {
+                (Some(blend_pack), true) => image.map(|x| {
+                    blend_pack.blended_image(
+                        &x,
+                        phases,
+                        index,
+                        blend_weight,
+                        &weekly_blend_dir
+                    )
+                }),
+                _ => image,
+            };
+
+            let phase = match unscheduled {
+                Some((_, phase)) => Some(phase.to_string()),
+                None => phase_of_index(phases, index),
+            };
+
+            // The text changes at most once a minute, the rendered image is
+            // reused until then. Regions are only slices of the wallpaper, the
+            // text would be cut up.
+            let overlay = config.overlay
+                .as_ref()
+                .filter(|_| cfg!(feature = "overlay"))
+                .filter(|_| !matches!(schedule.output, Output::Region(_)))
+                .map(|overlay| {
+                    let now = Local
+                        .timestamp_opt(current_timestamp, 0)
+                        .single()
+                        .unwrap_or_else(Local::now);
+
+                    let text = overlay.text(now, phase.as_deref(), day_progress(sun_and_moon, current_timestamp));
+
+                    (overlay.clone(), text)
+                });
+
+            let effects = effects::for_phase(&resolved_pack_config.effects, phase.as_deref());
+
+            schedule.last_index = Some(index);
+
+            // Flattening, effects, grading, gamma and the overlay are applied
+            // on the processing thread, the image is set once it's back.
+            if let Some(image) = image.filter(|_| !quiet) {
+                schedule.image_processor.request(Job {
+                    image,
+                    alpha_background: config.alpha_background.clone(),
+                    gamma: config.gamma,
+                    effects,
+                    color_grade: grade,
+                    strip_rows: config.processing_strip_rows,
+                    overlay,
+                    phase,
+                });
+            }
+        }
+
+        if packs_loaded {
+            if let Some(config_watcher) = config_watcher.as_mut() {
+                config_watcher.set_files(watched_files(&schedules));
+            }
+        }
+
+        // Missed images are set one after the other while the current ones
+        // are processed. Ctrl+C skips the rest of them.
+        for step in catch_up_steps {
+            let results = monitor::set_images(&schedules, vec![step], composite.as_mut(), &transition_frames_dir);
+            record_changes(results, &mut schedules, &current_wallpaper_path, change_log.as_mut(), &mut metrics);
+
+            if !demo::dwell(time::Duration::from_millis(CATCH_UP_STEP_MILLIS), &terminate_loop) {
+                break;
+            }
+        }
+
+        if let Ok(mut control) = control.lock() {
+            control.wallpaper_pack = schedules[0].wallpaper_pack.clone();
+            control.current_image = composite
+                .as_ref()
+                .and_then(|x| x.canvas.clone())
+                .or_else(|| schedules[0].current_image.clone());
+            control.day_progress = day_progress(&schedules[0].sun_and_moon, current_timestamp);
+        }
+
+        for schedule in schedules.iter_mut() {
+            schedule.precompute_next_day(current_timestamp, &config, &remote_cache_dir, &mut decode_checker);
+        }
+
+        // The schedules share the poll, it lasts the shortest of their intervals.
+        let poll_interval_seconds = schedules
+            .iter()
+            .map(|x| x.playback.poll_interval_seconds)
+            .min()
+            .unwrap_or(1);
+        let poll_end = Instant::now() + time::Duration::from_secs(poll_interval_seconds);
+
+        let processed = schedules
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, schedule)| {
+                schedule.image_processor
+                    .wait(poll_end.saturating_duration_since(Instant::now()))
+                    .filter(|x| !holding[index] && schedule.current_image.as_ref() != Some(&x.image))
+                    .map(|x| (index, x))
+            })
+            .collect::<Vec<(usize, Processed)>>();
+
+        current_timestamp = Local::now().timestamp();
+
+        let changes = processed
+            .into_iter()
+            .map(|(index, processed)| Change {
+                schedule: index,
+                transition: schedules[index].transition_for(processed.phase.as_deref()),
+                image: processed.image,
+                phase: processed.phase,
+            })
+            .collect::<Vec<Change>>();
+
+        if !changes.is_empty() {
+            let results = monitor::set_images(&schedules, changes, composite.as_mut(), &transition_frames_dir);
+            record_changes(results, &mut schedules, &current_wallpaper_path, change_log.as_mut(), &mut metrics);
+        }
+
+        let last_set_timestamp = schedules
+            .iter()
+            .filter_map(|x| x.last_change_timestamp)
+            .max()
+            .max(last_reapply_timestamp);
+
+        if reapply_due(last_set_timestamp, current_timestamp, config.reapply_interval_seconds) {
+            if let Err(e) = monitor::reapply(&schedules, composite.as_ref(), &transition_frames_dir) {
+                eprintln!("Unable to reapply the wallpaper: {e}");
+            }
+
+            last_reapply_timestamp = Some(current_timestamp);
+        }
+    }
+
+    println!("The program was terminated using ctrl+c.");
+
+    shut_down(change_log.as_mut(), &mut state, &state_path)?;
+
+    metrics.stale_images_dropped = schedules.iter().map(|x| x.image_processor.dropped()).sum();
+    metrics.print_summary();
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    const HOUR: i64 = 3600;
+
+
+    /// 2024-03-01, a day without daylight saving changes, as a day timestamp.
+    fn test_day() -> i64 {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().timestamp()
+    }
+
+
+    fn boundary_options(day_boundary: DayBoundary, time_offset_minutes: i64) -> BoundaryOptions {
+        BoundaryOptions {
+            use_moon: true,
+            time_offset_minutes,
+            day_boundary,
+            round_to_minute: false,
+        }
+    }
+
+
+    #[test]
+    fn time_offset_shifts_events_but_not_the_local_day() {
+        let shifted = get_day_sun_and_moon_position_times(
+            &FixedAstronomy::default(),
+            test_day(),
+            0.0,
+            0.0,
+            boundary_options(DayBoundary::Local, 30)
+        ).unwrap();
+
+        assert_eq!(shifted[&SunAndMoonKeys::Sunrise], test_day() + 6 * HOUR + 30 * 60);
+        assert_eq!(shifted[&SunAndMoonKeys::Sunset], test_day() + 18 * HOUR + 30 * 60);
+        assert_eq!(shifted[&SunAndMoonKeys::Midnight], local_midnight(test_day(), 0).unwrap());
+        assert_eq!(shifted[&SunAndMoonKeys::NextDayMidnight], local_midnight(test_day(), 1).unwrap());
+    }
+
+
+    #[test]
+    fn negative_time_offset_never_moves_events_before_midnight() {
+        for day_boundary in [DayBoundary::Local, DayBoundary::Solar] {
+            let shifted = get_day_sun_and_moon_position_times(
+                &FixedAstronomy::default(),
+                test_day(),
+                0.0,
+                0.0,
+                boundary_options(day_boundary, -MAX_TIME_OFFSET_MINUTES)
+            ).unwrap();
+
+            let midnight = shifted[&SunAndMoonKeys::Midnight];
+
+            assert!(shifted.values().all(|x| *x >= midnight), "{day_boundary:?}: {shifted:?}");
+            // Moonset at 04:00 lands 8 hours before the day, sunset at 18:00
+            // six hours into it.
+            assert_eq!(shifted[&SunAndMoonKeys::Moonset], midnight.max(test_day() - 8 * HOUR));
+            assert_eq!(shifted[&SunAndMoonKeys::Sunset], (test_day() + 6 * HOUR).max(midnight));
+        }
+    }
+
+
+    fn fixed_day(options: BoundaryOptions) -> HashMap<SunAndMoonKeys, i64> {
+        get_day_sun_and_moon_position_times(&FixedAstronomy::default(), test_day(), 0.0, 0.0, options).unwrap()
+    }
+
+
+    fn pack_config(toml: &str) -> WallpaperPackConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+
+    /// Every slot's image path built up front, the way schedules were stored
+    /// before their images were resolved lazily.
+    fn eager_image_paths(
+        sun_and_moon: &HashMap<SunAndMoonKeys, i64>,
+        wallpaper_pack_config: &WallpaperPackConfig,
+        wallpaper_pack_dir: &str
+    ) -> Vec<PathBuf> {
+        schedule_boundaries(sun_and_moon, wallpaper_pack_config, false)
+            .windows(2)
+            .flat_map(|x| x[0].images.iter().map(|image| Path::new(wallpaper_pack_dir).join(image.path())))
+            .collect()
+    }
+
+
+    #[test]
+    fn lazily_resolved_paths_match_eager_ones() {
+        let sun_and_moon = fixed_day(boundary_options(DayBoundary::Local, 0));
+        let wallpaper_pack_dir = "/packs/mountains".to_string();
+
+        for phase_order in ["fixed", "sorted"] {
+            let wallpaper_pack_config = pack_config(&format!(r#"
+                phase_order = "{phase_order}"
+                midnight = ["night_1.png", "night_2.png"]
+                sunrise = ["dawn.png"]
+                noon = ["day_1.png", {{ image = "day_2.png", duration_seconds = 600 }}, "day_3.png"]
+                sunset = ["dusk.png"]
+                moonrise = []
+                moonset = ["late.png"]
+
+                [anchors.golden_hour]
+                at = "sunset - 60m"
+                images = ["gold_1.png", "gold_2.png"]
+            "#));
+
+            let timestamp_seq = map_timestamps(&sun_and_moon, &wallpaper_pack_config);
+            let phases = day_phases(&sun_and_moon, &wallpaper_pack_config);
+
+            let lazy = (0..timestamp_seq.len())
+                .map(|x| scheduled_image_path(&phases, &wallpaper_pack_config, &wallpaper_pack_dir, x).unwrap())
+                .collect::<Vec<PathBuf>>();
+
+            assert_eq!(lazy, eager_image_paths(&sun_and_moon, &wallpaper_pack_config, &wallpaper_pack_dir));
+            assert_eq!(scheduled_image_path(&phases, &wallpaper_pack_config, &wallpaper_pack_dir, lazy.len()), None);
+            assert_eq!(phase_of_index(&phases, 0).as_deref(), Some("Midnight"));
+        }
+    }
+
+
+    #[test]
+    fn lazy_schedule_needs_less_memory_than_eager_paths() {
+        let sun_and_moon = fixed_day(boundary_options(DayBoundary::Local, 0));
+        let wallpaper_pack_dir = "/home/user/.local/share/wallpaper_changer_rust/wallpaper_packs/photos".to_string();
+
+        let images = |phase: &str| (0..2500).map(|x| PackImage::Path(format!("{phase}/IMG_{x:05}.jpg"))).collect();
+        let wallpaper_pack_config = WallpaperPackConfig {
+            midnight: images("midnight"),
+            sunrise: images("sunrise"),
+            noon: images("noon"),
+            sunset: images("sunset"),
+            ..WallpaperPackConfig::default()
+        };
+
+        let eager = eager_image_paths(&sun_and_moon, &wallpaper_pack_config, &wallpaper_pack_dir);
+        let eager_bytes = eager.capacity() * std::mem::size_of::<PathBuf>()
+            + eager.iter().map(|x| x.capacity()).sum::<usize>();
+
+        let timestamp_seq = map_timestamps(&sun_and_moon, &wallpaper_pack_config);
+        let phases = day_phases(&sun_and_moon, &wallpaper_pack_config);
+        let lazy_bytes = timestamp_seq.capacity() * std::mem::size_of::<i64>()
+            + phases.capacity() * std::mem::size_of::<PhaseSpan>()
+            + phases.iter().map(|x| x.name.capacity()).sum::<usize>();
+
+        assert_eq!(timestamp_seq.len(), eager.len());
+        assert!(lazy_bytes * 10 < eager_bytes, "lazy {lazy_bytes} bytes, eager {eager_bytes} bytes");
+    }
+
+
+    #[test]
+    fn catch_up_steps_through_the_slots_missed_during_a_long_sleep() {
+        let sun_and_moon = fixed_day(boundary_options(DayBoundary::Local, 0));
+        let wallpaper_pack_config = pack_config(r#"
+            midnight = ["night.png"]
+            sunrise = ["dawn_1.png", "dawn_2.png"]
+            noon = ["day_1.png", "day_2.png"]
+            sunset = ["dusk.png"]
+        "#);
+        let timestamp_seq = map_timestamps(&sun_and_moon, &wallpaper_pack_config);
+        let phases = day_phases(&sun_and_moon, &wallpaper_pack_config);
+
+        // The machine slept from just after sunrise into the evening.
+        let before = select_image_index(&timestamp_seq, sun_and_moon[&SunAndMoonKeys::Sunrise] + 60).unwrap();
+        let after = select_image_index(&timestamp_seq, sun_and_moon[&SunAndMoonKeys::Sunset] + 60).unwrap();
+
+        assert_eq!(missed_indices(CatchUp::Step, Some(before), after, false), before + 1..after);
+        // The second dawn image and both day images.
+        assert_eq!(
+            missed_indices(CatchUp::Step, Some(before), after, false)
+                .map(|x| phase_of_index(&phases, x).unwrap())
+                .collect::<Vec<String>>(),
+            ["Sunrise", "Noon", "Noon"]
+        );
+        assert!(missed_indices(CatchUp::Jump, Some(before), after, false).is_empty());
+        assert!(missed_indices(CatchUp::Step, Some(before), after, true).is_empty());
+        assert!(missed_indices(CatchUp::Step, None, after, false).is_empty());
+        // A new day starts over rather than stepping backwards.
+        assert!(missed_indices(CatchUp::Step, Some(after), before, false).is_empty());
+    }
+
+
+    #[test]
+    fn moon_phases_are_left_out_without_the_moon() {
+        let wallpaper_pack_config = pack_config(r#"
+            midnight = ["night.png"]
+            moonrise = ["moon.png"]
+            moonset = ["late.png"]
+        "#);
+        let config = WallpaperChangerConfig {
+            use_moon: false,
+            ..WallpaperChangerConfig::default()
+        };
+        let wallpaper_pack_dir = "/packs/night".to_string();
+
+        let resolved = resolve_pack_entries(&wallpaper_pack_config, &wallpaper_pack_dir, Path::new("/cache"), &config).unwrap();
+
+        assert!(resolved.moonrise.is_empty() && resolved.moonset.is_empty());
+        assert_eq!(resolved.midnight, wallpaper_pack_config.midnight);
+
+        let with_moon = resolve_pack_entries(
+            &wallpaper_pack_config,
+            &wallpaper_pack_dir,
+            Path::new("/cache"),
+            &WallpaperChangerConfig::default()
+        ).unwrap();
+
+        assert_eq!(with_moon.moonrise, wallpaper_pack_config.moonrise);
+    }
+
+
+    #[test]
+    fn time_offset_is_limited_to_half_a_day() {
+        let result = get_day_sun_and_moon_position_times(
+            &FixedAstronomy::default(),
+            test_day(),
+            0.0,
+            0.0,
+            boundary_options(DayBoundary::Local, MAX_TIME_OFFSET_MINUTES + 1)
+        );
+
+        assert!(result.is_err());
+    }
+
+
+    #[test]
+    fn pack_playback_settings_override_the_global_ones() {
+        let config = WallpaperChangerConfig { poll_interval_seconds: 30, ..WallpaperChangerConfig::default() };
+        let wallpaper_pack_config = pack_config(r#"
+            poll_interval_seconds = 5
+            transition = { style = "crossfade", duration_seconds = 4 }
+        "#);
+        let global = PlaybackSettings::resolve(&config, &WallpaperPackConfig::default(), None);
+        assert_eq!(global.poll_interval_seconds, 30);
+        assert_eq!(global.transition, Transition::Instant);
+        let pack = PlaybackSettings::resolve(&config, &wallpaper_pack_config, None);
+        assert_eq!(pack.poll_interval_seconds, 5);
+        assert!(matches!(pack.transition, Transition::Crossfade { duration_seconds: 4, .. }));
+
+        // The command line wins over both, and polling never spins.
+        assert_eq!(PlaybackSettings::resolve(&config, &wallpaper_pack_config, Some(60)).poll_interval_seconds, 60);
+        assert_eq!(PlaybackSettings::resolve(&config, &wallpaper_pack_config, Some(0)).poll_interval_seconds, 1);
+    }
+
+
+    #[test]
+    fn selection_clamps_to_the_edges_of_the_schedule() {
+        let timestamp_seq = [100, 200, 300];
+
+        assert_eq!(select_image_index(&timestamp_seq, 0), Some(0));
+        assert_eq!(select_image_index(&timestamp_seq, 100), Some(1));
+        assert_eq!(select_image_index(&timestamp_seq, 299), Some(2));
+        assert_eq!(select_image_index(&timestamp_seq, 300), Some(2));
+        assert_eq!(select_image_index(&timestamp_seq, 10_000), Some(2));
+        assert_eq!(select_image_index(&[], 100), None);
+    }
+
+
+    #[test]
+    fn missing_pack_phases_are_empty() {
+        let wallpaper_pack_config = pack_config(r#"noon = ["day.png"]"#);
+
+        assert_eq!(wallpaper_pack_config.noon, [PackImage::Path("day.png".to_string())]);
+        assert!(wallpaper_pack_config.midnight.is_empty() && wallpaper_pack_config.moonset.is_empty());
+
+        // A phase without images gets no slots instead of failing.
+        assert_eq!(timestamp_splitter(0, 100, 0), Vec::<i64>::new());
+        assert_eq!(timestamp_splitter(0, 100, -1), Vec::<i64>::new());
+        assert_eq!(timestamp_splitter(0, 100, 4), [25, 50, 75, 100]);
+        assert_eq!(timestamp_splitter(10, 20, 1), [20]);
+    }
+
+
+    #[test]
+    fn pack_rotation_advances_once_a_day_and_wraps_around() {
+        let config = WallpaperChangerConfig {
+            pack_rotation: vec!["forest".to_string(), "desert".to_string(), "coast".to_string()],
+            ..WallpaperChangerConfig::default()
+        };
+        let mut state = WallpaperChangerState::default();
+        let day = |x: u32| NaiveDate::from_ymd_opt(2024, 3, x).unwrap();
+
+        let packs = [1, 1, 2, 3, 4, 4, 5]
+            .map(|x| rotate_pack(&config, &mut state, day(x)).unwrap());
+
+        assert_eq!(packs, ["forest", "forest", "desert", "coast", "forest", "forest", "desert"]);
+        assert_eq!((state.rotation_index, state.rotation_date), (1, Some(day(5))));
+
+        // An index left over from a longer rotation starts over.
+        state.rotation_index = 7;
+        assert_eq!(rotate_pack(&config, &mut state, day(5)).as_deref(), Some("desert"));
+        assert_eq!(rotate_pack(&WallpaperChangerConfig::default(), &mut state, day(6)), None);
+    }
+
+
+    #[test]
+    fn directory_images_are_listed_in_natural_order() {
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_natural_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("img5.png")).unwrap();
+        for name in ["img100.png", "img10.png", "img2.png", "img1.JPG", "notes.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert_eq!(list_directory_images(&dir).unwrap(), ["img1.JPG", "img2.png", "img10.png", "img100.png"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+
+    #[test]
+    fn fixed_image_durations_leave_the_rest_of_the_phase_to_the_others() {
+        assert_eq!(timed_splitter(0, 1000, &[None, Some(200), None]), Ok(vec![400, 600, 1000]));
+        assert_eq!(timed_splitter(0, 1000, &[Some(100), Some(200)]), Ok(vec![100, 1000]));
+        assert!(timed_splitter(0, 1000, &[Some(600), None, Some(600)]).is_err());
+
+        let sun_and_moon = HashMap::from([
+            (SunAndMoonKeys::Midnight, 0),
+            (SunAndMoonKeys::Sunrise, 6 * HOUR),
+            (SunAndMoonKeys::Noon, 12 * HOUR),
+            (SunAndMoonKeys::Sunset, 18 * HOUR),
+            (SunAndMoonKeys::NextDayMidnight, 24 * HOUR),
+        ]);
+        let wallpaper_pack_config = pack_config(r#"
+            midnight = ["night.png"]
+            sunrise = [{ image = "first_light.png", duration_seconds = 300 }, "dawn_1.png", "dawn_2.png"]
+            noon = [{ image = "day_1.png", duration_seconds = 50000 }, "day_2.png"]
+        "#);
+        let sunrise = 6 * HOUR;
+        assert_eq!(map_timestamps(&sun_and_moon, &wallpaper_pack_config), [
+            // The night image until sunrise.
+            sunrise,
+            sunrise + 300,
+            sunrise + 300 + (6 * HOUR - 300) / 2,
+            sunrise + 6 * HOUR,
+            // Longer than the afternoon, so it's split evenly instead.
+            15 * HOUR,
+            18 * HOUR,
+        ]);
+    }
+
+
+    #[test]
+    fn fixed_image_durations_must_be_positive() {
+        assert_eq!(pack_config(r#"noon = [{ image = "day.png", duration_seconds = 600 }]"#).validate(), Ok(()));
+
+        for duration_seconds in [0, -600] {
+            let wallpaper_pack_config = pack_config(&format!(r#"
+                noon = ["day_1.png", {{ image = "day_2.png", duration_seconds = {duration_seconds} }}]
+            "#));
+
+            assert_eq!(
+                wallpaper_pack_config.validate(),
+                Err("duration_seconds of 'day_2.png' in noon must be positive.".to_string())
+            );
+        }
+
+        let wallpaper_pack_config = pack_config(r#"
+            [anchors.golden_hour]
+            at = "sunset - 60m"
+            images = [{ image = "gold.png", duration_seconds = 0 }]
+        "#);
+        assert!(wallpaper_pack_config.validate().unwrap_err().contains("golden_hour"));
+    }
+
+
+    #[test]
+    fn shutting_down_flushes_the_log_and_stores_the_stopped_state() {
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_shutdown_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (state_path, log_path) = (dir.join("state.toml"), dir.join("changes.log"));
+
+        let mut change_log = ChangeLog::open(&log_path, change_log::LogFormat::Plain).unwrap();
+        let mut state = WallpaperChangerState {
+            status: DaemonStatus::Running,
+            ..WallpaperChangerState::default()
+        };
+        state.store(&state_path).unwrap();
+        change_log.record(&ChangeRecord::new("Noon".to_string(), Path::new("day.png"), None)).unwrap();
+
+        shut_down(Some(&mut change_log), &mut state, &state_path).unwrap();
+
+        assert_eq!(WallpaperChangerState::load(&state_path).unwrap().status, DaemonStatus::Stopped);
+        assert!(fs::read_to_string(&log_path).unwrap().ends_with(" Noon day.png ok\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+
+    #[test]
+    fn the_day_boundary_picks_local_or_solar_midnight() {
+        // West of its time zone's meridian the sun is lowest after 00:00.
+        let astronomy = FixedAstronomy {
+            midnight: 40 * 60,
+            ..FixedAstronomy::default()
+        };
+        let wallpaper_pack_config = pack_config(r#"
+            midnight = ["night.png"]
+            noon = ["day.png"]
+        "#);
+        let day = |day_boundary| {
+            get_day_sun_and_moon_position_times(&astronomy, test_day(), 0.0, 0.0, boundary_options(day_boundary, 0)).unwrap()
+        };
+        let (local, solar) = (day(DayBoundary::Local), day(DayBoundary::Solar));
+
+        let (local_start, local_end) = (local_midnight(test_day(), 0).unwrap(), local_midnight(test_day(), 1).unwrap());
+        assert_eq!((local[&SunAndMoonKeys::Midnight], local[&SunAndMoonKeys::NextDayMidnight]), (local_start, local_end));
+        assert!(schedule_covers(&local, local_end - 1) && !schedule_covers(&local, local_end));
+
+        let (solar_start, solar_end) = (test_day() + 40 * 60, test_day() + 24 * HOUR + 40 * 60);
+        assert_eq!((solar[&SunAndMoonKeys::Midnight], solar[&SunAndMoonKeys::NextDayMidnight]), (solar_start, solar_end));
+        assert!(schedule_covers(&solar, solar_end - 1) && !schedule_covers(&solar, solar_end));
+
+        // The midnight phase starts with the day either way.
+        assert_eq!(day_phases(&local, &wallpaper_pack_config)[0].start, local_start);
+        assert_eq!(day_phases(&solar, &wallpaper_pack_config)[0].start, solar_start);
+        assert_eq!(day_phases(&solar, &wallpaper_pack_config).last().unwrap().end, solar_end);
+    }
+
+
+    #[test]
+    fn the_default_image_fills_phases_without_images() {
+        let sun_and_moon = fixed_day(boundary_options(DayBoundary::Solar, 0));
+        let wallpaper_pack_config = pack_config(r#"
+            default_image = "fallback.png"
+            sunrise = ["dawn.png"]
+            noon = ["day.png"]
+        "#);
+        let wallpaper_pack_dir = "/packs/daytime".to_string();
+        let phases = day_phases(&sun_and_moon, &wallpaper_pack_config);
+        let shown = |wallpaper_pack_config: &WallpaperPackConfig, schedule_mode, hour| {
+            unscheduled_image(None, &sun_and_moon, &phases, wallpaper_pack_config, &wallpaper_pack_dir, schedule_mode, test_day() + hour * HOUR)
+        };
+        let fallback = Some((PathBuf::from("/packs/daytime/fallback.png"), "Default"));
+
+        assert_eq!(shown(&wallpaper_pack_config, ScheduleMode::Phases, 2), fallback);
+        assert_eq!(shown(&wallpaper_pack_config, ScheduleMode::Phases, 9), None);
+        assert_eq!(shown(&wallpaper_pack_config, ScheduleMode::Phases, 15), None);
+        assert_eq!(shown(&wallpaper_pack_config, ScheduleMode::Phases, 21), fallback);
+
+        // Without a default the neighbouring images carry on, solar time
+        // slots have no gaps.
+        assert_eq!(shown(&WallpaperPackConfig::default(), ScheduleMode::Phases, 2), None);
+        assert_eq!(shown(&wallpaper_pack_config, ScheduleMode::SolarTime, 2), None);
+    }
+
+
+    #[test]
+    fn changes_are_held_until_the_minimum_interval_has_passed() {
+        let next_day_midnight = local_midnight(test_day(), 1).unwrap();
+
+        assert!(!change_too_soon(None, test_day(), 600));
+        assert!(change_too_soon(Some(test_day()), test_day() + 599, 600));
+        assert!(!change_too_soon(Some(test_day()), test_day() + 600, 600));
+        assert!(!change_too_soon(Some(test_day()), test_day(), 0));
+
+        // The new day's first image waits for the interval too.
+        assert!(change_too_soon(Some(next_day_midnight - 30), next_day_midnight + 10, 600));
+        assert!(!change_too_soon(Some(next_day_midnight - 30), next_day_midnight + 570, 600));
+    }
+
+
+    #[test]
+    fn each_night_behavior_fills_nights_without_images() {
+        let night_dir = std::env::temp_dir().join(format!("wallpaper_changer_night_{}", std::process::id()));
+        let desktop_wallpaper = PathBuf::from("/home/user/desktop.png");
+
+        assert_eq!(night_image(&NightBehavior::Loop, Some(&desktop_wallpaper), &night_dir), None);
+        assert_eq!(night_image(&NightBehavior::DesktopDefault, Some(&desktop_wallpaper), &night_dir), Some(desktop_wallpaper.clone()));
+        assert_eq!(night_image(&NightBehavior::DesktopDefault, None, &night_dir), None);
+        assert_eq!(
+            night_image(&NightBehavior::Solid("#101018".to_string()), None, &night_dir),
+            Some(night_dir.join("solid_101018.png")).filter(|_| cfg!(feature = "image"))
+        );
+        assert_eq!(night_image(&NightBehavior::Solid("dusk".to_string()), None, &night_dir), None);
+
+        let sun_and_moon = fixed_day(boundary_options(DayBoundary::Solar, 0));
+        let wallpaper_pack_config = pack_config(r#"
+            default_image = "fallback.png"
+            sunrise = ["dawn.png"]
+            noon = ["day.png"]
+            moonrise = ["moon.png"]
+        "#);
+        let phases = day_phases(&sun_and_moon, &wallpaper_pack_config);
+        let shown = |hour| {
+            unscheduled_image(
+                Some(&desktop_wallpaper),
+                &sun_and_moon,
+                &phases,
+                &wallpaper_pack_config,
+                &"/packs/daytime".to_string(),
+                ScheduleMode::Phases,
+                test_day() + hour * HOUR
+            ).map(|(_, phase)| phase)
+        };
+
+        // Ahead of the default image at night, the moonrise has its own.
+        assert_eq!(shown(2), Some("Night"));
+        assert_eq!(shown(9), None);
+        assert_eq!(shown(19), Some("Night"));
+        assert_eq!(shown(21), None);
+
+        let _ = fs::remove_dir_all(&night_dir);
+    }
+
+
+    #[test]
+    fn rounding_to_the_minute_keeps_the_events_in_order() {
+        let astronomy = FixedAstronomy {
+            moonset: Some(6 * HOUR - 10),
+            sunrise: Some(6 * HOUR + 20),
+            noon: 12 * HOUR + 29,
+            sunset: Some(18 * HOUR + 31),
+            moonrise: Some(20 * HOUR - 30),
+            ..FixedAstronomy::default()
+        };
+        let options = BoundaryOptions {
+            round_to_minute: true,
+            ..boundary_options(DayBoundary::Solar, 0)
+        };
+
+        let rounded = get_day_sun_and_moon_position_times(&astronomy, test_day(), 0.0, 0.0, options).unwrap();
+        let at = |x: SunAndMoonKeys| rounded[&x] - test_day();
+
+        assert_eq!(at(SunAndMoonKeys::Noon), 12 * HOUR);
+        assert_eq!(at(SunAndMoonKeys::Sunset), 18 * HOUR + 60);
+        assert_eq!(at(SunAndMoonKeys::Moonrise), 20 * HOUR);
+        // Both would round to 06:00, so both stay as they were.
+        assert_eq!(at(SunAndMoonKeys::Moonset), 6 * HOUR - 10);
+        assert_eq!(at(SunAndMoonKeys::Sunrise), 6 * HOUR + 20);
+
+        let mut order = rounded.iter().map(|(key, x)| (*x, format!("{key:?}"))).collect::<Vec<(i64, String)>>();
+        order.sort();
+        assert_eq!(
+            order.into_iter().map(|(_, key)| key).collect::<Vec<String>>(),
+            ["Midnight", "Moonset", "Sunrise", "Noon", "Sunset", "Moonrise", "NextDayMidnight"]
+        );
+    }
+
+
+    #[test]
+    fn schedules_near_the_poles_follow_the_clock() {
+        let late_sunrise = FixedAstronomy {
+            sunrise: Some(7 * HOUR),
+            ..FixedAstronomy::default()
+        };
+        let midnight = local_midnight(test_day(), 0).unwrap();
+        let options = boundary_options(DayBoundary::Local, 0);
+
+        for latitude in [89.999, 90.0, -89.999, -90.0] {
+            for day in [test_day(), test_day() + 120 * 24 * HOUR] {
+                let day_midnight = local_midnight(day, 0).unwrap();
+                let sun_and_moon = get_day_sun_and_moon_position_times(&Geodate, day, 0.0, latitude, options).unwrap();
+
+                assert_eq!(sun_and_moon[&SunAndMoonKeys::Moonset], day_midnight + 4 * HOUR, "{latitude}");
+                assert_eq!(sun_and_moon[&SunAndMoonKeys::Sunrise], day_midnight + 6 * HOUR, "{latitude}");
+                assert_eq!(sun_and_moon[&SunAndMoonKeys::Sunset], day_midnight + 18 * HOUR, "{latitude}");
+                assert_eq!(sun_and_moon[&SunAndMoonKeys::Moonrise], day_midnight + 20 * HOUR, "{latitude}");
+            }
+
+            let sun_and_moon = get_day_sun_and_moon_position_times(&late_sunrise, test_day(), 0.0, latitude, options).unwrap();
+            assert_eq!(sun_and_moon[&SunAndMoonKeys::Sunrise], midnight + 6 * HOUR, "{latitude}");
+        }
+
+        // Just outside the epsilon the sky is used again.
+        for latitude in [90.0 - 2.0 * astronomy::POLE_EPSILON, -90.0 + 2.0 * astronomy::POLE_EPSILON] {
+            let sun_and_moon = get_day_sun_and_moon_position_times(&late_sunrise, test_day(), 0.0, latitude, options).unwrap();
+            assert_eq!(sun_and_moon[&SunAndMoonKeys::Sunrise], test_day() + 7 * HOUR, "{latitude}");
+        }
+    }
+
+
+    #[test]
+    fn moon_phases_before_sunrise_keep_their_images_in_sorted_order() {
+        let astronomy = FixedAstronomy {
+            moonset: Some(2 * HOUR),
+            moonrise: Some(5 * HOUR),
+            ..FixedAstronomy::default()
+        };
+        let sun_and_moon = get_day_sun_and_moon_position_times(
+            &astronomy,
+            test_day(),
+            0.0,
+            0.0,
+            boundary_options(DayBoundary::Solar, 0)
+        ).unwrap();
+        let spans = |phase_order: &str| {
+            let wallpaper_pack_config = pack_config(&format!(r#"
+                phase_order = "{phase_order}"
+                midnight = ["night.png"]
+                moonset = ["late.png"]
+                moonrise = ["moon.png"]
+                sunrise = ["dawn.png"]
+                noon = ["day.png"]
+                sunset = ["dusk.png"]
+            "#));
+            let phases = day_phases(&sun_and_moon, &wallpaper_pack_config);
+
+            phases
+                .iter()
+                .map(|x| {
+                    let image = x.slots.clone().map(|slot| {
+                        scheduled_image_path(&phases, &wallpaper_pack_config, &"/packs/moon".to_string(), slot).unwrap()
+                    });
+
+                    (x.name.clone(), (x.start - test_day()) / HOUR, (x.end - test_day()) / HOUR, image.collect::<Vec<PathBuf>>())
+                })
+                .collect::<Vec<(String, i64, i64, Vec<PathBuf>)>>()
+        };
+        let span = |name: &str, start, end, image: &str| {
+            (name.to_string(), start, end, vec![Path::new("/packs/moon").join(image)])
+        };
+
+        assert_eq!(spans("sorted"), [
+            span("Midnight", 0, 2, "night.png"),
+            span("Moonset", 2, 5, "late.png"),
+            span("Moonrise", 5, 6, "moon.png"),
+            span("Sunrise", 6, 12, "dawn.png"),
+            span("Noon", 12, 18, "day.png"),
+            span("Sunset", 18, 24, "dusk.png"),
+        ]);
+
+        // The fixed order only has a place for the moonrise after sunset,
+        // where it gets no time at all.
+        assert_eq!(spans("fixed"), [
+            span("Midnight", 0, 2, "night.png"),
+            span("Moonset", 2, 6, "late.png"),
+            span("Sunrise", 6, 12, "dawn.png"),
+            span("Noon", 12, 18, "day.png"),
+            span("Sunset", 18, 24, "dusk.png"),
+            span("Moonrise", 24, 24, "moon.png"),
+        ]);
+    }
+
+
+    #[test]
+    fn a_fixed_seed_picks_the_same_random_pack_every_launch() {
+        let wallpaper_packs_dir = std::env::temp_dir().join(format!("wallpaper_changer_random_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&wallpaper_packs_dir);
+        for pack in ["coast", "desert", "forest", "mountains", "tundra"] {
+            fs::create_dir_all(wallpaper_packs_dir.join(pack)).unwrap();
+            fs::write(wallpaper_packs_dir.join(pack).join("wallpaper_pack_config.toml"), "noon = [\"day.png\"]").unwrap();
+        }
+
+        let wallpaper_packs_dir = wallpaper_packs_dir.to_string_lossy().to_string();
+        let config_name = "wallpaper_pack_config.toml".to_string();
+        let picks = |seed| {
+            (0..5)
+                .map(|_| random_pack(&WallpaperChangerConfig::default(), &wallpaper_packs_dir, &config_name, &mut Rng::new(seed)).unwrap())
+                .collect::<Vec<String>>()
+        };
+
+        for seed in [1, 2, 3] {
+            let picks = picks(Some(seed));
+            assert!(picks.iter().all(|x| *x == picks[0]), "{seed}: {picks:?}");
+        }
+
+        fs::remove_dir_all(&wallpaper_packs_dir).unwrap();
+    }
+
+
+    #[test]
+    fn sampling_keeps_the_first_and_last_images() {
+        let images = (0..100).collect::<Vec<usize>>();
+
+        assert_eq!(sample_evenly(&images, 5), [0, 25, 50, 74, 99]);
+        assert_eq!(sample_evenly(&images, 2), [0, 99]);
+        assert_eq!(sample_evenly(&images, 1), [0]);
+        assert!(sample_evenly(&images, 0).is_empty());
+        assert_eq!(sample_evenly(&images, 100), images);
+        assert_eq!(sample_evenly(&images, 500), images);
+
+        for count in 2..100 {
+            let sampled = sample_evenly(&images, count);
+
+            assert_eq!(sampled.len(), count);
+            assert_eq!((sampled[0], sampled[count - 1]), (0, 99));
+            assert!(sampled.windows(2).all(|x| x[0] < x[1]), "{count}: {sampled:?}");
+        }
+    }
+
+
+    #[test]
+    fn day_progress_runs_from_midnight_to_midnight() {
+        let today = fixed_day(boundary_options(DayBoundary::Solar, 0));
+        let tomorrow = get_day_sun_and_moon_position_times(
+            &FixedAstronomy::default(),
+            test_day() + 24 * HOUR,
+            0.0,
+            0.0,
+            boundary_options(DayBoundary::Solar, 0)
+        ).unwrap();
+
+        assert_eq!(day_progress(&today, test_day()), 0.0);
+        assert_eq!(day_progress(&today, test_day() + 6 * HOUR), 0.25);
+        assert_eq!(day_progress(&today, test_day() + 18 * HOUR), 0.75);
+        assert!(day_progress(&today, test_day() + 24 * HOUR - 1) > 0.9999);
+
+        // The next midnight ends one day and starts the next from zero.
+        assert_eq!(day_progress(&today, test_day() + 24 * HOUR), 1.0);
+        assert_eq!(day_progress(&tomorrow, test_day() + 24 * HOUR), 0.0);
+        assert_eq!(day_progress(&tomorrow, test_day() + 36 * HOUR), 0.5);
+
+        assert_eq!(day_progress(&today, test_day() - HOUR), 0.0);
+        assert_eq!(day_progress(&today, test_day() + 30 * HOUR), 1.0);
+    }
+
+
+    #[test]
+    fn wallpapers_are_reapplied_once_the_interval_has_passed() {
+        assert!(!reapply_due(None, 1000, 60));
+        assert!(!reapply_due(Some(1000), 1059, 60));
+        assert!(reapply_due(Some(1000), 1060, 60));
+        assert!(reapply_due(Some(1000), 5000, 60));
+        // 0 turns reapplying off.
+        assert!(!reapply_due(Some(1000), 5000, 0));
+    }
+
+
+    #[cfg(unix)]
+    #[test]
+    fn storing_into_a_read_only_directory_fails_with_the_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_read_only_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Root writes into read-only directories anyway, a file standing in
+        // for the directory can't be written through by anyone.
+        let path = match fs::write(dir.join("config.toml"), "") {
+            Ok(()) => dir.join("config.toml").join("config.toml"),
+            Err(_) => dir.join("config.toml"),
+        };
+
+        let error = store_file(&path, &WallpaperChangerConfig::default()).unwrap_err();
+
+        assert!(error.starts_with(&format!("Unable to write {}: ", path.display())), "{error}");
+        assert!(!path.exists());
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+
+    #[test]
+    fn phase_transitions_take_precedence_over_the_pack_and_global_ones() {
+        let config = WallpaperChangerConfig {
+            transition: Transition::Crossfade {
+                duration_seconds: 2,
+                frames: 10,
+                easing: Default::default(),
+            },
+            ..WallpaperChangerConfig::default()
+        };
+        let wallpaper_pack_config = pack_config(r#"
+            transition = { style = "crossfade", duration_seconds = 4 }
+
+            [transitions.sunrise]
+            style = "crossfade"
+            duration_seconds = 10
+
+            [transitions.midnight]
+            style = "instant"
+
+            [transitions.golden_hour]
+            style = "crossfade"
+            duration_seconds = 20
+        "#);
+        let duration = |playback: &PlaybackSettings, phase| playback.transition_for(phase).duration_seconds();
+
+        let playback = PlaybackSettings::resolve(&config, &wallpaper_pack_config, None);
+        assert_eq!(duration(&playback, Some("Sunrise")), 10);
+        assert_eq!(duration(&playback, Some("Midnight")), 0);
+        assert_eq!(duration(&playback, Some("golden_hour")), 20);
+        assert_eq!(duration(&playback, Some("Noon")), 4);
+        assert_eq!(duration(&playback, None), 4);
+
+        let wallpaper_pack_config = WallpaperPackConfig {
+            transition: None,
+            ..wallpaper_pack_config
+        };
+        let playback = PlaybackSettings::resolve(&config, &wallpaper_pack_config, None);
+        assert_eq!(duration(&playback, Some("Sunrise")), 10);
+        assert_eq!(duration(&playback, Some("Noon")), 2);
+    }
+
+
+    #[test]
+    fn an_inline_pack_is_loaded_from_the_config() {
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_inline_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("packs").join("forest")).unwrap();
+        fs::write(dir.join("packs").join("forest").join("wallpaper_pack_config.toml"), "noon = [\"trees.png\"]").unwrap();
+        fs::write(dir.join("config.toml"), r#"
+            wallpaper_pack = ""
+
+            [pack]
+            midnight = ["images/night.png"]
+            noon = ["images/day.png"]
+        "#).unwrap();
+
+        let config_path = dir.join("config.toml").to_string_lossy().to_string();
+        let wallpaper_packs_dir = dir.join("packs").to_string_lossy().to_string();
+        let config_name = "wallpaper_pack_config.toml".to_string();
+        let config = load_config(&config_path).unwrap();
+
+        let (wallpaper_pack_dir, wallpaper_pack_config) =
+            load_configured_pack(&config, &config_path, &wallpaper_packs_dir, &config.wallpaper_pack, &config_name).unwrap();
+
+        assert_eq!(wallpaper_pack_dir, dir.to_string_lossy());
+        assert_eq!(wallpaper_pack_config.noon, [PackImage::Path("images/day.png".to_string())]);
+
+        // Packs picked by name still come from the packs directory.
+        let (_, forest) = load_configured_pack(&config, &config_path, &wallpaper_packs_dir, &"forest".to_string(), &config_name).unwrap();
+        assert_eq!(forest.noon, [PackImage::Path("trees.png".to_string())]);
+
+        // The unversioned config was migrated and stored with its pack.
+        assert!(fs::read_to_string(&config_path).unwrap().contains("[pack]"));
+        assert_eq!(load_config(&config_path).unwrap().pack.unwrap().midnight, [PackImage::Path("images/night.png".to_string())]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
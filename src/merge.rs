@@ -0,0 +1,328 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::migration::CURRENT_PACK_VERSION;
+use crate::remove::check_pack_name;
+use crate::{list_directory_images, load_wallpaper_pack, remote, PackImage, WallpaperPackConfig};
+
+
+/// Copies images of the source packs into the merged one, each distinct
+/// content once.
+struct ImageCopier {
+    output_dir: PathBuf,
+    /// Content hash to the images already copied with it.
+    copied: HashMap<String, Vec<String>>,
+    taken: HashSet<String>,
+    duplicates: usize,
+}
+
+impl ImageCopier {
+    /// Where `entry` of a pack goes in the merged pack: the same relative path
+    /// when it stays inside the pack, otherwise just its file name.
+    fn destination(entry: &str) -> String {
+        let path = Path::new(entry);
+
+        match path.components().all(|x| matches!(x, Component::Normal(_))) {
+            true => entry.to_string(),
+            false => path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        }
+    }
+
+    /// `destination`, or `name-2.ext`, `name-3.ext` and so on when taken.
+    fn free_name(&self, destination: &str) -> String {
+        let path = Path::new(destination);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().map(|x| format!(".{}", x.to_string_lossy())).unwrap_or_default();
+
+        (1..)
+            .map(|x| match x {
+                1 => destination.to_string(),
+                _ => path.with_file_name(format!("{stem}-{x}{extension}")).to_string_lossy().to_string(),
+            })
+            .find(|x| !self.taken.contains(x))
+            .unwrap_or_default()
+    }
+
+    /// Copies `source` unless an image with the same content already was,
+    /// returning its path in the merged pack.
+    fn copy(&mut self, source: &Path, entry: &str) -> Result<String, String> {
+        let bytes = fs::read(source)
+            .map_err(|e| format!("Unable to read {}: {e}", source.display()))?;
+        let key = remote::bytes_key(&bytes);
+
+        // Hashes only narrow it down, the content decides.
+        for copied in self.copied.get(&key).into_iter().flatten() {
+            if fs::read(self.output_dir.join(copied)).is_ok_and(|x| x == bytes) {
+                self.duplicates += 1;
+                return Ok(copied.clone());
+            }
+        }
+
+        let destination = self.free_name(&Self::destination(entry));
+        let destination_path = self.output_dir.join(&destination);
+
+        fs::create_dir_all(destination_path.parent().unwrap_or(Path::new("")))
+            .and_then(|_| fs::write(&destination_path, &bytes))
+            .map_err(|e| format!("Unable to write {}: {e}", destination_path.display()))?;
+
+        self.taken.insert(destination.clone());
+        self.copied.entry(key).or_default().push(destination.clone());
+
+        Ok(destination)
+    }
+
+    /// The entries of `images` pointing into the merged pack. Directories are
+    /// listed image by image and URLs are kept as they are.
+    fn copy_images(
+        &mut self,
+        wallpaper_pack_dir: &str,
+        images: &[PackImage]
+    ) -> Result<Vec<PackImage>, String> {
+        let mut copied = vec![];
+
+        for pack_image in images {
+            let entry = pack_image.path();
+
+            if remote::is_remote(entry) {
+                copied.push(pack_image.clone());
+                continue;
+            }
+
+            let entry = remote::strip_file_scheme(entry);
+            let entry_path = Path::new(wallpaper_pack_dir).join(entry);
+
+            if entry_path.is_dir() {
+                for image in list_directory_images(&entry_path)? {
+                    let image_entry = Path::new(entry).join(&image).to_string_lossy().to_string();
+                    let destination = self.copy(&entry_path.join(&image), &image_entry)?;
+                    copied.push(pack_image.with_path(destination));
+                }
+                continue;
+            }
+
+            if !entry_path.is_file() {
+                eprintln!("  Skipping {}, it doesn't exist.", entry_path.display());
+                continue;
+            }
+
+            let destination = self.copy(&entry_path, entry)?;
+            copied.push(pack_image.with_path(destination));
+        }
+
+        Ok(copied)
+    }
+}
+
+
+/// `first` and `second` combined, with their images copied by `copier`.
+fn merge(
+    copier: &mut ImageCopier,
+    (first_dir, first_config): (&str, &WallpaperPackConfig),
+    (second_dir, second_config): (&str, &WallpaperPackConfig),
+    second: &str
+) -> Result<WallpaperPackConfig, String> {
+    let mut concat = |first_images: &[PackImage], second_images: &[PackImage]| -> Result<Vec<PackImage>, String> {
+        Ok([
+            copier.copy_images(first_dir, first_images)?,
+            copier.copy_images(second_dir, second_images)?,
+        ].concat())
+    };
+
+    let mut merged = first_config.clone();
+
+    merged.midnight = concat(&first_config.midnight, &second_config.midnight)?;
+    merged.moonset = concat(&first_config.moonset, &second_config.moonset)?;
+    merged.sunrise = concat(&first_config.sunrise, &second_config.sunrise)?;
+    merged.noon = concat(&first_config.noon, &second_config.noon)?;
+    merged.sunset = concat(&first_config.sunset, &second_config.sunset)?;
+    merged.moonrise = concat(&first_config.moonrise, &second_config.moonrise)?;
+
+    for anchor in merged.anchors.values_mut() {
+        anchor.images = copier.copy_images(first_dir, &anchor.images)?;
+    }
+
+    for (name, anchor) in &second_config.anchors {
+        let mut anchor = anchor.clone();
+        anchor.images = copier.copy_images(second_dir, &anchor.images)?;
+
+        let name = match merged.anchors.contains_key(name) {
+            true => format!("{name}_{second}"),
+            false => name.clone(),
+        };
+        merged.anchors.insert(name, anchor);
+    }
+
+    for (phase, effects) in &second_config.effects {
+        merged.effects.entry(phase.clone()).or_insert(*effects);
+    }
+
+    for (phase, transition) in &second_config.transitions {
+        merged.transitions.entry(phase.clone()).or_insert_with(|| transition.clone());
+    }
+
+    merged.default_image = match (&first_config.default_image, &second_config.default_image) {
+        (Some(image), _) => copier.copy_images(first_dir, &[PackImage::Path(image.clone())])?.pop(),
+        (None, Some(image)) => copier.copy_images(second_dir, &[PackImage::Path(image.clone())])?.pop(),
+        (None, None) => None,
+    }.map(|x| x.path().to_string());
+
+    merged.poll_interval_seconds = first_config.poll_interval_seconds.or(second_config.poll_interval_seconds);
+    merged.transition = first_config.transition.clone().or(second_config.transition.clone());
+    merged.phase_order = first_config.phase_order.or(second_config.phase_order);
+
+    // Variants and overcast images live in subdirectories under the names
+    // of the originals, which the merge may have changed. The manifest's
+    // hashes describe another pack.
+    merged.aspect_variants.clear();
+    merged.overcast_dir = None;
+    merged.update_manifest = None;
+    merged.version = Some(CURRENT_PACK_VERSION);
+
+    Ok(merged)
+}
+
+
+/// Creates the pack `output` out of `first` and `second`: every phase lists
+/// the images of `first` followed by those of `second`, anchors of both are
+/// kept and the other settings come from `first`, or from `second` where
+/// `first` leaves them unset. Images with the same content are copied once,
+/// names taken by another image get a number.
+pub fn run(
+    wallpaper_packs_dir: &String,
+    first: &String,
+    second: &String,
+    output: &String,
+    wallpaper_pack_config_name: &String
+) -> Result<(), String> {
+    let (first_dir, first_config) = load_wallpaper_pack(wallpaper_packs_dir, first, wallpaper_pack_config_name)?;
+    let (second_dir, second_config) = load_wallpaper_pack(wallpaper_packs_dir, second, wallpaper_pack_config_name)?;
+
+    check_pack_name(output).map_err(|e| format!("Invalid name for the merged pack: {e}"))?;
+
+    let output_dir = Path::new(wallpaper_packs_dir).join(output);
+
+    if output_dir.exists() {
+        return Err(format!("Pack '{output}' already exists in: {wallpaper_packs_dir}"));
+    }
+
+    let mut copier = ImageCopier {
+        output_dir: output_dir.clone(),
+        copied: HashMap::new(),
+        taken: HashSet::new(),
+        duplicates: 0,
+    };
+
+    let result = merge(&mut copier, (&first_dir, &first_config), (&second_dir, &second_config), second)
+        .and_then(|x| toml::to_string(&x).map_err(|e| format!("Unable to serialize the merged pack config: {e}")))
+        .and_then(|x| {
+            fs::create_dir_all(&output_dir)
+                .and_then(|_| fs::write(output_dir.join(wallpaper_pack_config_name), x))
+                .map_err(|e| format!("Unable to write the merged pack config: {e}"))
+        });
+
+    // A half merged pack would load as a broken one.
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&output_dir);
+        return Err(e);
+    }
+
+    println!(
+        "Merged '{first}' and '{second}' into '{output}' with {} image(s), {} duplicate(s) shared.",
+        copier.taken.len(),
+        copier.duplicates
+    );
+
+    if [&first_config, &second_config].iter().any(|x| !x.aspect_variants.is_empty() || x.overcast_dir.is_some()) {
+        println!("  aspect_variants and overcast_dir aren't merged, add them to '{output}' by hand.");
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    const CONFIG_NAME: &str = "wallpaper_pack_config.toml";
+
+
+    /// A packs directory with `first` and `second`, both showing the same
+    /// image at noon under different names and a different `day.png`.
+    fn packs_dir(name: &str) -> String {
+        let directory = std::env::temp_dir().join(format!("wallpaper_changer_merge_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        for (pack, config, images) in [
+            ("first", "noon = [\"day.png\"]\nsunset = [\"dusk.png\"]\n", [("day.png", "sun"), ("dusk.png", "red")]),
+            ("second", "noon = [\"noon.png\"]\nmidnight = [\"day.png\"]\n", [("noon.png", "sun"), ("day.png", "moon")]),
+        ] {
+            let pack_dir = directory.join(pack);
+            fs::create_dir_all(&pack_dir).unwrap();
+            fs::write(pack_dir.join(CONFIG_NAME), config).unwrap();
+
+            for (image, content) in images {
+                fs::write(pack_dir.join(image), content).unwrap();
+            }
+        }
+
+        directory.to_string_lossy().to_string()
+    }
+
+
+    fn merge_into(wallpaper_packs_dir: &String, output: &str) -> Result<(), String> {
+        run(
+            wallpaper_packs_dir,
+            &"first".to_string(),
+            &"second".to_string(),
+            &output.to_string(),
+            &CONFIG_NAME.to_string()
+        )
+    }
+
+
+    #[test]
+    fn merge_shares_duplicates_and_renames_clashes() {
+        let wallpaper_packs_dir = packs_dir("clashes");
+
+        merge_into(&wallpaper_packs_dir, "both").unwrap();
+
+        let (output_dir, merged) = load_wallpaper_pack(
+            &wallpaper_packs_dir,
+            &"both".to_string(),
+            &CONFIG_NAME.to_string()
+        ).unwrap();
+
+        let paths = |x: &[PackImage]| x.iter().map(|x| x.path().to_string()).collect::<Vec<String>>();
+
+        assert_eq!(paths(&merged.noon), ["day-2.png", "day-2.png"]);
+        assert_eq!(paths(&merged.sunset), ["dusk.png"]);
+        assert_eq!(paths(&merged.midnight), ["day.png"]);
+        assert_eq!(merged.version, Some(CURRENT_PACK_VERSION));
+        assert_eq!(fs::read_to_string(Path::new(&output_dir).join("day-2.png")).unwrap(), "sun");
+
+        fs::remove_dir_all(wallpaper_packs_dir).unwrap();
+    }
+
+
+    #[test]
+    fn merge_refuses_bad_output_names() {
+        let wallpaper_packs_dir = packs_dir("names");
+
+        assert_eq!(
+            merge_into(&wallpaper_packs_dir, ""),
+            Err("Invalid name for the merged pack: The pack name is empty.".to_string())
+        );
+        assert_eq!(
+            merge_into(&wallpaper_packs_dir, "../escaped"),
+            Err("Invalid name for the merged pack: '../escaped' is not a pack name.".to_string())
+        );
+        assert!(merge_into(&wallpaper_packs_dir, "nested/pack").is_err());
+        assert!(merge_into(&wallpaper_packs_dir, "second").is_err());
+        assert!(!Path::new(&wallpaper_packs_dir).join("nested").exists());
+
+        fs::remove_dir_all(wallpaper_packs_dir).unwrap();
+    }
+}
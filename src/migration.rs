@@ -0,0 +1,140 @@
+use toml::{Table, Value};
+
+
+/// Version 2 added the `version` field itself and moved the change log
+/// settings into their own table.
+pub const CURRENT_CONFIG_VERSION: i64 = 2;
+
+
+/// Version 2 added `night_behavior` and the `version` field itself.
+pub const CURRENT_PACK_VERSION: i64 = 2;
+
+
+/// v1 configs predate the `version` field and keep the change log settings at
+/// the top level, as `change_log_path` and `change_log_format`. They become
+/// `path` and `format` of the `[change_log]` table. Every other field added
+/// since has a default and is written out when the config is stored.
+fn migrate_v1_to_v2(config: &mut Table) {
+    let mut change_log = Table::new();
+
+    for (v1_name, v2_name) in [("change_log_path", "path"), ("change_log_format", "format")] {
+        if let Some(x) = config.remove(v1_name) {
+            change_log.insert(v2_name.to_string(), x);
+        }
+    }
+
+    if !change_log.is_empty() {
+        config.insert("change_log".to_string(), Value::Table(change_log));
+    }
+}
+
+
+/// Upgrades a raw config table to the current version in place, returning
+/// whether anything had to be migrated.
+pub fn migrate_config(config: &mut Table) -> Result<bool, String> {
+    let mut version = config
+        .get("version")
+        .and_then(|x| x.as_integer())
+        .unwrap_or(1);
+
+    if version > CURRENT_CONFIG_VERSION {
+        return Err(format!(
+            "Config version {version} is newer than the supported version {CURRENT_CONFIG_VERSION}."
+        ));
+    }
+
+    let migrated = version < CURRENT_CONFIG_VERSION;
+
+    while version < CURRENT_CONFIG_VERSION {
+        match version {
+            1 => migrate_v1_to_v2(config),
+            _ => return Err(format!("No migration from config version {version}.")),
+        }
+
+        version += 1;
+    }
+
+    config.insert("version".to_string(), Value::Integer(version));
+
+    Ok(migrated)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change_log::LogFormat;
+    use crate::WallpaperChangerConfig;
+
+
+    const V1_CONFIG: &str = r#"
+longitude = 15.97
+latitude = 45.81
+wallpaper_pack = "mountains"
+poll_interval_seconds = 30
+change_log_path = "/tmp/wallpaper_changes.log"
+change_log_format = "json"
+
+[transition]
+style = "instant"
+"#;
+
+
+    const V2_CONFIG: &str = r#"
+version = 2
+longitude = 15.97
+latitude = 45.81
+wallpaper_pack = "mountains"
+poll_interval_seconds = 30
+
+[change_log]
+path = "/tmp/wallpaper_changes.log"
+format = "json"
+
+[transition]
+style = "instant"
+"#;
+
+
+    #[test]
+    fn v1_config_is_migrated_to_v2() {
+        let mut config: Table = toml::from_str(V1_CONFIG).unwrap();
+
+        assert_eq!(migrate_config(&mut config), Ok(true));
+        assert_eq!(config, toml::from_str::<Table>(V2_CONFIG).unwrap());
+
+        let config: WallpaperChangerConfig = Value::Table(config).try_into().unwrap();
+
+        assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+        assert_eq!(config.change_log.path.as_deref(), Some("/tmp/wallpaper_changes.log"));
+        assert_eq!(config.change_log.format, LogFormat::Json);
+        assert_eq!(config.wallpaper_pack, "mountains");
+        assert_eq!(config.poll_interval_seconds, 30);
+    }
+
+
+    #[test]
+    fn v1_config_without_a_change_log_gets_no_table() {
+        let mut config: Table = toml::from_str("wallpaper_pack = \"mountains\"").unwrap();
+
+        assert_eq!(migrate_config(&mut config), Ok(true));
+        assert_eq!(config, toml::from_str::<Table>("version = 2\nwallpaper_pack = \"mountains\"").unwrap());
+    }
+
+
+    #[test]
+    fn current_config_is_left_alone() {
+        let mut config: Table = toml::from_str(V2_CONFIG).unwrap();
+
+        assert_eq!(migrate_config(&mut config), Ok(false));
+        assert_eq!(config, toml::from_str::<Table>(V2_CONFIG).unwrap());
+    }
+
+
+    #[test]
+    fn newer_config_is_refused() {
+        let mut config: Table = toml::from_str("version = 3").unwrap();
+
+        assert!(migrate_config(&mut config).is_err());
+    }
+}
@@ -0,0 +1,872 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+
+use crate::composite::Composite;
+use crate::decode::DecodeChecker;
+use crate::processing::ImageProcessor;
+use crate::selection::SelectionCache;
+use crate::transition::{self, Transition};
+use crate::workspace;
+use crate::{
+    day_containing,
+    day_phases,
+    load_configured_pack,
+    load_wallpaper_pack_from_dir,
+    map_timestamps,
+    precompute_next_day,
+    resolve_pack_entries,
+    schedule_covers,
+    slot_durations,
+    PhaseSpan,
+    PlaybackSettings,
+    PrecomputedDay,
+    SunAndMoonKeys,
+    WallpaperChangerConfig,
+    WallpaperPackConfig,
+    PRECOMPUTE_LEAD_SECONDS,
+};
+
+
+/// Where the images of a schedule are set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Output {
+    /// The wallpaper of the whole desktop.
+    Desktop,
+    /// The wallpaper of one workspace, by its zero based index.
+    Workspace(usize),
+    /// A slice of the composite wallpaper, by its index in `composite_regions`.
+    Region(usize),
+}
+
+
+/// How the directory of a schedule's pack changed since it was last checked.
+#[derive(Debug, PartialEq)]
+pub(crate) enum PackDirChange {
+    Unchanged,
+    /// Deleted or moved, the schedule holds until it's back.
+    Missing,
+    /// Back after going missing, or replaced by the directory of another pack.
+    Back,
+}
+
+
+/// A pack running on its own schedule next to others, driven by the loop's
+/// clock: its day, its slots and the image last set from it. The desktop's
+/// wallpaper is a single schedule, composite regions and workspaces get one
+/// each.
+pub(crate) struct MonitorSchedule {
+    pub(crate) output: Output,
+    /// Whether the pack follows the rotation, the power source and pack
+    /// switches. Only the desktop's does, regions and workspaces keep theirs.
+    pub(crate) follows_active_pack: bool,
+    pub(crate) wallpaper_pack: String,
+    /// Directory the pack is loaded from instead of by name, see `--pack-path`.
+    pub(crate) pack_path: Option<String>,
+    pub(crate) wallpaper_pack_dir: String,
+    pub(crate) wallpaper_pack_config: WallpaperPackConfig,
+    pub(crate) resolved_pack_config: WallpaperPackConfig,
+    pub(crate) day: NaiveDateTime,
+    pub(crate) sun_and_moon: HashMap<SunAndMoonKeys, i64>,
+    pub(crate) timestamp_seq: Vec<i64>,
+    pub(crate) phases: Vec<PhaseSpan>,
+    pub(crate) selection_cache: SelectionCache,
+    pub(crate) playback: PlaybackSettings,
+    pub(crate) night_image_path: Option<PathBuf>,
+    pub(crate) current_image: Option<PathBuf>,
+    pub(crate) last_index: Option<usize>,
+    pub(crate) last_change_timestamp: Option<i64>,
+    /// The transition and the shortest slot it was last shortened to, to
+    /// warn once.
+    pub(crate) shortened_transition: Option<(Transition, i64)>,
+    pub(crate) precomputed_day: Option<PrecomputedDay>,
+    /// Whether the next day was computed ahead already, or tried to be.
+    next_day_attempted: bool,
+    pub(crate) missing_pack_dir: Option<String>,
+    /// Why the pack last failed to reload or remap, reported once. It's
+    /// tried again on every poll until it succeeds.
+    pub(crate) pack_error: Option<String>,
+    /// Whether the last remap failed, the schedule holds until one succeeds.
+    remap_failed: bool,
+    pub(crate) image_processor: ImageProcessor,
+}
+
+
+impl MonitorSchedule {
+    /// A schedule for `wallpaper_pack`, its pack is loaded by `load_pack` and
+    /// its day set by `set_day`.
+    pub(crate) fn new(
+        output: Output,
+        wallpaper_pack: String,
+        image_processor: ImageProcessor
+    ) -> Self {
+        Self {
+            output,
+            follows_active_pack: output == Output::Desktop,
+            wallpaper_pack,
+            pack_path: None,
+            wallpaper_pack_dir: String::new(),
+            wallpaper_pack_config: WallpaperPackConfig::default(),
+            resolved_pack_config: WallpaperPackConfig::default(),
+            day: NaiveDateTime::default(),
+            sun_and_moon: HashMap::new(),
+            timestamp_seq: vec![],
+            phases: vec![],
+            selection_cache: SelectionCache::default(),
+            playback: PlaybackSettings::resolve(&WallpaperChangerConfig::default(), &WallpaperPackConfig::default(), None),
+            night_image_path: None,
+            current_image: None,
+            last_index: None,
+            last_change_timestamp: None,
+            shortened_transition: None,
+            precomputed_day: None,
+            next_day_attempted: false,
+            missing_pack_dir: None,
+            pack_error: None,
+            remap_failed: false,
+            image_processor,
+        }
+    }
+
+    /// Names the output in the change log, the desktop goes unnamed.
+    pub(crate) fn monitor(&self) -> Option<String> {
+        match self.output {
+            Output::Desktop => None,
+            Output::Workspace(x) => Some(format!("workspace{}", x + 1)),
+            Output::Region(x) => Some(format!("region{}", x + 1)),
+        }
+    }
+
+    /// When the current day's schedule ends, right away before it's set.
+    pub(crate) fn day_end(&self) -> i64 {
+        self.sun_and_moon
+            .get(&SunAndMoonKeys::NextDayMidnight)
+            .copied()
+            .unwrap_or(i64::MIN)
+    }
+
+    /// Loads the pack from its path or by its name. Its images are resolved
+    /// by the next `remap`.
+    pub(crate) fn load_pack(
+        &mut self,
+        config: &WallpaperChangerConfig,
+        config_path: &String,
+        wallpaper_packs_dir: &String,
+        wallpaper_pack_config_name: &String,
+        interval_override: Option<u64>
+    ) -> Result<(), String> {
+        (self.wallpaper_pack_dir, self.wallpaper_pack_config) = match &self.pack_path {
+            Some(pack_path) => load_wallpaper_pack_from_dir(pack_path.clone(), wallpaper_pack_config_name),
+            None => load_configured_pack(
+                config,
+                config_path,
+                wallpaper_packs_dir,
+                &self.wallpaper_pack,
+                wallpaper_pack_config_name
+            ),
+        }?;
+
+        self.playback = PlaybackSettings::resolve(config, &self.wallpaper_pack_config, interval_override);
+        self.discard_next_day();
+
+        Ok(())
+    }
+
+    /// Loads the pack again while running. A pack that fails to, e.g. deleted
+    /// or saved half written, is reported and the loaded one kept. Returns
+    /// whether it loaded.
+    pub(crate) fn reload_pack(
+        &mut self,
+        config: &WallpaperChangerConfig,
+        config_path: &String,
+        wallpaper_packs_dir: &String,
+        wallpaper_pack_config_name: &String,
+        interval_override: Option<u64>
+    ) -> bool {
+        match self.load_pack(config, config_path, wallpaper_packs_dir, wallpaper_pack_config_name, interval_override) {
+            Ok(()) => true,
+            Err(e) => {
+                self.report_pack_error(e);
+                false
+            }
+        }
+    }
+
+    /// Moves the schedule to the day containing `timestamp`.
+    pub(crate) fn set_day(
+        &mut self,
+        timestamp: i64,
+        config: &WallpaperChangerConfig
+    ) -> Result<(), String> {
+        (self.day, self.sun_and_moon) = day_containing(timestamp, config)?;
+        self.discard_next_day();
+
+        Ok(())
+    }
+
+    fn discard_next_day(&mut self) {
+        self.precomputed_day = None;
+        self.next_day_attempted = false;
+    }
+
+    /// Computes the next day ahead once `timestamp` is within
+    /// `PRECOMPUTE_LEAD_SECONDS` of the current day's end. A failure is
+    /// reported and left to the rollover, which computes the day itself.
+    pub(crate) fn precompute_next_day(
+        &mut self,
+        timestamp: i64,
+        config: &WallpaperChangerConfig,
+        remote_cache_dir: &Path,
+        decode_checker: &mut DecodeChecker
+    ) {
+        if self.next_day_attempted
+            || self.missing_pack_dir.is_some()
+            || self.pack_error.is_some()
+            || timestamp < self.day_end().saturating_sub(PRECOMPUTE_LEAD_SECONDS)
+        {
+            return;
+        }
+
+        self.next_day_attempted = true;
+
+        let next_day = precompute_next_day(
+            self.day,
+            config,
+            &self.wallpaper_pack,
+            &self.wallpaper_pack_config,
+            &self.wallpaper_pack_dir,
+            remote_cache_dir,
+            decode_checker
+        );
+
+        match next_day {
+            Ok(next_day) => self.precomputed_day = Some(next_day),
+            Err(e) => eprintln!("Unable to compute the next day ahead, it's computed at the rollover: {e}"),
+        }
+    }
+
+    /// Resolves the pack's images again and maps them onto the current day,
+    /// failing for a pack without any, also after a reload or pack switch.
+    pub(crate) fn remap(
+        &mut self,
+        config: &WallpaperChangerConfig,
+        remote_cache_dir: &Path,
+        decode_checker: &mut DecodeChecker
+    ) -> Result<(), String> {
+        let mut resolved_pack_config = resolve_pack_entries(
+            &self.wallpaper_pack_config,
+            &self.wallpaper_pack_dir,
+            remote_cache_dir,
+            config
+        )?;
+        decode_checker.filter_pack(config.decode_check, &mut resolved_pack_config, &self.wallpaper_pack_dir)?;
+
+        // Checked after resolving, a pack of empty directories is just as useless.
+        if resolved_pack_config.is_empty() {
+            return Err(format!("Pack '{}' contains no images.", self.wallpaper_pack));
+        }
+
+        self.timestamp_seq = map_timestamps(&self.sun_and_moon, &resolved_pack_config);
+        self.phases = day_phases(&self.sun_and_moon, &resolved_pack_config);
+        self.resolved_pack_config = resolved_pack_config;
+        self.selection_cache.invalidate();
+
+        // Indices of the old schedule mean nothing in the new one.
+        self.last_index = None;
+
+        Ok(())
+    }
+
+    /// Remaps the schedule while running. A pack left without images, e.g.
+    /// emptied while running, is reported and the previous schedule kept,
+    /// held until a later remap succeeds. Returns whether it remapped.
+    pub(crate) fn remap_or_hold(
+        &mut self,
+        config: &WallpaperChangerConfig,
+        remote_cache_dir: &Path,
+        decode_checker: &mut DecodeChecker
+    ) -> bool {
+        self.remap_failed = match self.remap(config, remote_cache_dir, decode_checker) {
+            Ok(()) => {
+                self.pack_error = None;
+                false
+            }
+            Err(e) => {
+                self.report_pack_error(e);
+                true
+            }
+        };
+
+        !self.remap_failed
+    }
+
+    /// Reports `error` unless it's the one reported last.
+    fn report_pack_error(&mut self, error: String) {
+        if self.pack_error.as_ref() != Some(&error) {
+            eprintln!("{error}\nKeeping the previous schedule until the pack loads again.");
+        }
+
+        self.pack_error = Some(error);
+    }
+
+    /// Checks that the pack's directory still exists, reporting when it goes
+    /// missing or comes back.
+    pub(crate) fn check_pack_dir(&mut self) -> PackDirChange {
+        let pack_dir_exists = Path::new(&self.wallpaper_pack_dir).is_dir();
+
+        if !pack_dir_exists && self.missing_pack_dir.as_ref() != Some(&self.wallpaper_pack_dir) {
+            eprintln!(
+                "The directory of pack '{}' no longer exists: {}",
+                self.wallpaper_pack,
+                self.wallpaper_pack_dir
+            );
+
+            self.missing_pack_dir = Some(self.wallpaper_pack_dir.clone());
+            return PackDirChange::Missing;
+        }
+
+        if pack_dir_exists && self.missing_pack_dir.is_some() {
+            if self.missing_pack_dir.as_ref() == Some(&self.wallpaper_pack_dir) {
+                println!("The directory of pack '{}' is back.", self.wallpaper_pack);
+            }
+
+            self.missing_pack_dir = None;
+            return PackDirChange::Back;
+        }
+
+        PackDirChange::Unchanged
+    }
+
+    /// Whether the schedule holds its wallpaper, as its pack's directory is
+    /// gone or its images couldn't be mapped.
+    pub(crate) fn holding(&self) -> bool {
+        self.missing_pack_dir.is_some() || self.remap_failed
+    }
+
+    /// Swaps in the precomputed next day when it's the day covering
+    /// `timestamp` and was built for the current pack, returning whether it
+    /// was.
+    pub(crate) fn swap_in_next_day(&mut self, timestamp: i64) -> bool {
+        self.next_day_attempted = false;
+
+        let next_day = self.precomputed_day.take().filter(|x| {
+            x.wallpaper_pack == self.wallpaper_pack && schedule_covers(&x.sun_and_moon, timestamp)
+        });
+
+        let Some(next_day) = next_day else {
+            return false;
+        };
+
+        self.day = next_day.day;
+        self.sun_and_moon = next_day.sun_and_moon;
+        self.resolved_pack_config = next_day.resolved_pack_config;
+        self.timestamp_seq = next_day.timestamp_seq;
+        self.phases = next_day.phases;
+        self.selection_cache.invalidate();
+        self.last_index = None;
+
+        true
+    }
+
+    /// The transition into an image of `phase`, shortened when it's longer
+    /// than the shortest slot of the day.
+    pub(crate) fn transition_for(&mut self, phase: Option<&str>) -> Transition {
+        // Empty phases leave slots of no length, no image is shown in them.
+        let shortest_slot = slot_durations(&self.sun_and_moon, &self.timestamp_seq)
+            .into_iter()
+            .filter(|x| *x > 0)
+            .min()
+            .unwrap_or(i64::MAX);
+
+        let transition = self.playback.transition_for(phase);
+
+        match transition::fit_to_slot(transition, shortest_slot) {
+            Some(fitted) => {
+                let shortened = Some((transition.clone(), shortest_slot));
+
+                if self.shortened_transition != shortened {
+                    eprintln!(
+                        "The {}s transition is longer than the shortest image slot today, shortening it to {}s.",
+                        transition.duration_seconds(),
+                        fitted.duration_seconds()
+                    );
+                    self.shortened_transition = shortened;
+                }
+
+                fitted
+            }
+            None => transition.clone(),
+        }
+    }
+}
+
+
+/// A new image for one of the schedules.
+#[derive(Debug)]
+pub(crate) struct Change {
+    /// Index of the schedule.
+    pub(crate) schedule: usize,
+    pub(crate) image: PathBuf,
+    pub(crate) phase: Option<String>,
+    pub(crate) transition: Transition,
+}
+
+
+/// Sets the images of `changes`, each transitioning from its schedule's
+/// current image, and returns what was set for each: the image itself, or
+/// the canvas for composite regions. Regions are combined into one canvas,
+/// set once with the transition of the first of them, and wait until every
+/// region has an image.
+pub(crate) fn set_images(
+    schedules: &[MonitorSchedule],
+    changes: Vec<Change>,
+    composite: Option<&mut Composite>,
+    frames_dir: &Path
+) -> Vec<(Change, Result<PathBuf, String>)> {
+    let region_changes = changes
+        .iter()
+        .filter(|x| matches!(schedules[x.schedule].output, Output::Region(_)))
+        .collect::<Vec<&Change>>();
+
+    let canvas = match (composite, region_changes.first()) {
+        (Some(composite), Some(first)) => {
+            let images = schedules
+                .iter()
+                .enumerate()
+                .map(|(index, schedule)| {
+                    region_changes
+                        .iter()
+                        .find(|x| x.schedule == index)
+                        .map(|x| x.image.clone())
+                        .or_else(|| schedule.current_image.clone())
+                })
+                .collect::<Option<Vec<PathBuf>>>();
+
+            images.map(|images| composite.set(&images, &first.transition, frames_dir))
+        }
+        _ => None,
+    };
+
+    changes
+        .into_iter()
+        .filter_map(|change| {
+            let schedule = &schedules[change.schedule];
+            let from = schedule.current_image.as_deref();
+
+            let result = match schedule.output {
+                Output::Desktop => transition::apply(from, &change.image, &change.transition, frames_dir)
+                    .map(|_| change.image.clone()),
+                Output::Workspace(workspace) => transition::apply_with(
+                    from,
+                    &change.image,
+                    &change.transition,
+                    &frames_dir.join(format!("workspace{workspace}")),
+                    |x| workspace::set_workspace_wallpaper(workspace, x)
+                ).map(|_| change.image.clone()),
+                Output::Region(_) => canvas.clone()?,
+            };
+
+            Some((change, result))
+        })
+        .collect()
+}
+
+
+/// Sets every schedule's current image again, the composite canvas once.
+pub(crate) fn reapply(
+    schedules: &[MonitorSchedule],
+    composite: Option<&Composite>,
+    frames_dir: &Path
+) -> Result<(), String> {
+    for schedule in schedules {
+        let Some(image) = &schedule.current_image else {
+            continue;
+        };
+
+        match schedule.output {
+            Output::Desktop => transition::apply(None, image, &Transition::Instant, frames_dir)?,
+            Output::Workspace(workspace) => workspace::set_workspace_wallpaper(workspace, image)?,
+            Output::Region(_) => {}
+        }
+    }
+
+    match composite.and_then(|x| x.canvas.as_ref()) {
+        Some(canvas) => transition::apply(None, canvas, &Transition::Instant, frames_dir),
+        None => Ok(()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    use crate::processing::ProcessingDirs;
+    use crate::{scheduled_image_path, select_image_index};
+
+
+    fn schedule(wallpaper_pack_dir: &Path) -> MonitorSchedule {
+        let processed_dir = wallpaper_pack_dir.join("processed");
+        let image_processor = ImageProcessor::new(ProcessingDirs {
+            alpha: processed_dir.clone(),
+            color_grade: processed_dir.clone(),
+            effects: processed_dir.clone(),
+            gamma: processed_dir.clone(),
+            orientation: processed_dir.clone(),
+            overlay: processed_dir,
+        });
+
+        let mut schedule = MonitorSchedule::new(Output::Desktop, "photos".to_string(), image_processor);
+        schedule.wallpaper_pack_dir = wallpaper_pack_dir.to_string_lossy().to_string();
+        schedule.wallpaper_pack_config = toml::from_str("midnight = [\"night\"]\nnoon = [\"day\"]").unwrap();
+
+        schedule
+    }
+
+
+    #[test]
+    fn remapping_a_pack_emptied_while_running_holds() {
+        let wallpaper_pack_dir = std::env::temp_dir()
+            .join(format!("wallpaper_changer_monitor_empty_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&wallpaper_pack_dir);
+        std::fs::create_dir_all(wallpaper_pack_dir.join("night")).unwrap();
+        std::fs::create_dir_all(wallpaper_pack_dir.join("day")).unwrap();
+        std::fs::write(wallpaper_pack_dir.join("day").join("sun.png"), "sun").unwrap();
+
+        let config = WallpaperChangerConfig::default();
+        let mut schedule = schedule(&wallpaper_pack_dir);
+        let mut decode_checker = DecodeChecker::default();
+
+        schedule.set_day(0, &config).unwrap();
+        schedule.remap(&config, &wallpaper_pack_dir, &mut decode_checker).unwrap();
+        assert!(!schedule.timestamp_seq.is_empty());
+
+        // Remapping fails once the last image is removed, the loop keeps the
+        // previous schedule and holds until the pack has images again.
+        std::fs::remove_file(wallpaper_pack_dir.join("day").join("sun.png")).unwrap();
+        let timestamp_seq = schedule.timestamp_seq.clone();
+
+        assert_eq!(
+            schedule.remap(&config, &wallpaper_pack_dir, &mut decode_checker),
+            Err("Pack 'photos' contains no images.".to_string())
+        );
+        assert!(!schedule.remap_or_hold(&config, &wallpaper_pack_dir, &mut decode_checker));
+        assert_eq!(schedule.timestamp_seq, timestamp_seq);
+        assert_eq!(schedule.pack_error, Some("Pack 'photos' contains no images.".to_string()));
+        assert!(schedule.holding());
+
+        std::fs::write(wallpaper_pack_dir.join("day").join("moon.png"), "moon").unwrap();
+
+        assert!(schedule.remap_or_hold(&config, &wallpaper_pack_dir, &mut decode_checker));
+        assert_eq!(schedule.pack_error, None);
+        assert!(!schedule.holding());
+
+        std::fs::remove_dir_all(&wallpaper_pack_dir).unwrap();
+    }
+
+
+    #[test]
+    fn a_pack_deleted_or_broken_while_running_keeps_its_schedule() {
+        let wallpaper_pack_dir = std::env::temp_dir()
+            .join(format!("wallpaper_changer_monitor_deleted_{}", std::process::id()));
+        let pack_config_path = wallpaper_pack_dir.join("wallpaper_pack_config.toml");
+        let pack_config = "midnight = [\"night.png\"]\nnoon = [\"day.png\"]";
+        let write_pack = || {
+            std::fs::create_dir_all(&wallpaper_pack_dir).unwrap();
+            std::fs::write(&pack_config_path, pack_config).unwrap();
+            std::fs::write(wallpaper_pack_dir.join("night.png"), "night").unwrap();
+            std::fs::write(wallpaper_pack_dir.join("day.png"), "day").unwrap();
+        };
+        let _ = std::fs::remove_dir_all(&wallpaper_pack_dir);
+        write_pack();
+
+        let config = WallpaperChangerConfig::default();
+        let config_name = "wallpaper_pack_config.toml".to_string();
+        let packs_dir = String::new();
+        let mut schedule = schedule(&wallpaper_pack_dir);
+        let mut decode_checker = DecodeChecker::default();
+        schedule.pack_path = Some(wallpaper_pack_dir.to_string_lossy().to_string());
+
+        // One poll of the loop reloading the pack.
+        let mut poll = |schedule: &mut MonitorSchedule| {
+            let loaded = schedule.reload_pack(&config, &String::new(), &packs_dir, &config_name, None);
+            let back = schedule.check_pack_dir() == PackDirChange::Back;
+
+            (loaded || back)
+                && schedule.missing_pack_dir.is_none()
+                && schedule.remap_or_hold(&config, &wallpaper_pack_dir, &mut decode_checker)
+        };
+
+        schedule.set_day(0, &config).unwrap();
+        assert!(poll(&mut schedule));
+        let timestamp_seq = schedule.timestamp_seq.clone();
+
+        // A typo in the pack config keeps the loaded one and its schedule.
+        std::fs::write(&pack_config_path, "midnight = [\"night.png\"").unwrap();
+
+        assert!(!poll(&mut schedule));
+        assert!(schedule.pack_error.is_some());
+        assert!(!schedule.holding());
+        assert_eq!(schedule.wallpaper_pack_config.noon.len(), 1);
+        assert_eq!(schedule.timestamp_seq, timestamp_seq);
+
+        // Deleting the pack holds the wallpaper until it's back.
+        std::fs::remove_dir_all(&wallpaper_pack_dir).unwrap();
+
+        assert!(!poll(&mut schedule));
+        assert!(!poll(&mut schedule));
+        assert!(schedule.missing_pack_dir.is_some());
+        assert!(schedule.holding());
+        assert_eq!(schedule.timestamp_seq, timestamp_seq);
+
+        write_pack();
+
+        assert!(poll(&mut schedule));
+        assert_eq!(schedule.pack_error, None);
+        assert!(!schedule.holding());
+
+        std::fs::remove_dir_all(&wallpaper_pack_dir).unwrap();
+    }
+
+
+    #[test]
+    fn the_new_day_shows_its_midnight_image_right_at_the_rollover() {
+        let wallpaper_pack_dir = std::env::temp_dir()
+            .join(format!("wallpaper_changer_monitor_rollover_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&wallpaper_pack_dir);
+        std::fs::create_dir_all(wallpaper_pack_dir.join("night")).unwrap();
+        std::fs::create_dir_all(wallpaper_pack_dir.join("day")).unwrap();
+        std::fs::write(wallpaper_pack_dir.join("night").join("moon.png"), "moon").unwrap();
+        std::fs::write(wallpaper_pack_dir.join("day").join("sun.png"), "sun").unwrap();
+
+        let config = WallpaperChangerConfig {
+            latitude: 90.0,
+            ..WallpaperChangerConfig::default()
+        };
+        let mut decode_checker = DecodeChecker::default();
+        let today = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap().timestamp();
+
+        for precompute in [false, true] {
+            let mut schedule = schedule(&wallpaper_pack_dir);
+            schedule.set_day(today, &config).unwrap();
+            schedule.remap(&config, &wallpaper_pack_dir, &mut decode_checker).unwrap();
+            let rollover = schedule.day_end();
+            let image = |schedule: &MonitorSchedule, timestamp| {
+                let index = select_image_index(&schedule.timestamp_seq, timestamp).unwrap();
+                scheduled_image_path(&schedule.phases, &schedule.resolved_pack_config, &schedule.wallpaper_pack_dir, index).unwrap()
+            };
+
+            if precompute {
+                schedule.precompute_next_day(rollover - 60, &config, &wallpaper_pack_dir, &mut decode_checker);
+            }
+
+            assert_eq!(image(&schedule, rollover - 1), wallpaper_pack_dir.join("day").join("sun.png"));
+
+            // What the loop does on the first poll of the new day.
+            assert!(rollover >= schedule.day_end());
+            if !schedule.swap_in_next_day(rollover) {
+                assert!(!precompute);
+                schedule.set_day(rollover, &config).unwrap();
+                schedule.remap(&config, &wallpaper_pack_dir, &mut decode_checker).unwrap();
+            }
+
+            assert_eq!(schedule.sun_and_moon[&SunAndMoonKeys::Midnight], rollover);
+            assert_eq!(image(&schedule, rollover), wallpaper_pack_dir.join("night").join("moon.png"));
+        }
+
+        std::fs::remove_dir_all(&wallpaper_pack_dir).unwrap();
+    }
+
+
+    #[test]
+    fn images_added_to_a_directory_pack_show_up_at_the_next_remap() {
+        let wallpaper_pack_dir = std::env::temp_dir()
+            .join(format!("wallpaper_changer_monitor_added_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&wallpaper_pack_dir);
+        std::fs::create_dir_all(wallpaper_pack_dir.join("night")).unwrap();
+        std::fs::create_dir_all(wallpaper_pack_dir.join("day")).unwrap();
+        std::fs::write(wallpaper_pack_dir.join("night").join("moon.png"), "moon").unwrap();
+        std::fs::write(wallpaper_pack_dir.join("day").join("sun_1.png"), "sun").unwrap();
+
+        let config = WallpaperChangerConfig::default();
+        let mut schedule = schedule(&wallpaper_pack_dir);
+        let mut decode_checker = DecodeChecker::default();
+        let day_images = |schedule: &MonitorSchedule| {
+            schedule.resolved_pack_config.noon.iter().map(|x| x.path().to_string()).collect::<Vec<String>>()
+        };
+
+        schedule.set_day(0, &config).unwrap();
+        schedule.remap(&config, &wallpaper_pack_dir, &mut decode_checker).unwrap();
+        assert_eq!(day_images(&schedule), ["day/sun_1.png"]);
+        let slots = schedule.timestamp_seq.len();
+
+        // Nothing is reloaded, the directory is listed again.
+        std::fs::write(wallpaper_pack_dir.join("day").join("sun_2.png"), "sun").unwrap();
+        schedule.remap(&config, &wallpaper_pack_dir, &mut decode_checker).unwrap();
+
+        assert_eq!(day_images(&schedule), ["day/sun_1.png", "day/sun_2.png"]);
+        assert_eq!(schedule.timestamp_seq.len(), slots + 1);
+
+        std::fs::remove_dir_all(&wallpaper_pack_dir).unwrap();
+    }
+
+
+    #[test]
+    fn two_outputs_keep_independent_schedules() {
+        let packs_dir = std::env::temp_dir()
+            .join(format!("wallpaper_changer_monitor_independent_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&packs_dir);
+        for (pack, images) in [("photos", ["night", "day"]), ("city", ["dawn", "dusk"])] {
+            for image in images {
+                std::fs::create_dir_all(packs_dir.join(pack).join(image)).unwrap();
+                std::fs::write(packs_dir.join(pack).join(image).join(format!("{image}.png")), image).unwrap();
+            }
+        }
+
+        let config = WallpaperChangerConfig {
+            latitude: 90.0,
+            ..WallpaperChangerConfig::default()
+        };
+        let mut decode_checker = DecodeChecker::default();
+        let today = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap().timestamp();
+
+        let mut desktop = schedule(&packs_dir.join("photos"));
+        let mut workspace = schedule(&packs_dir.join("city"));
+        workspace.output = Output::Workspace(1);
+        workspace.wallpaper_pack = "city".to_string();
+        workspace.wallpaper_pack_config = toml::from_str("sunrise = [\"dawn\"]\nsunset = [\"dusk\"]").unwrap();
+
+        for schedule in [&mut desktop, &mut workspace] {
+            schedule.set_day(today, &config).unwrap();
+            schedule.remap(&config, &packs_dir, &mut decode_checker).unwrap();
+        }
+
+        let image = |schedule: &MonitorSchedule, timestamp| {
+            let index = select_image_index(&schedule.timestamp_seq, timestamp).unwrap();
+            scheduled_image_path(&schedule.phases, &schedule.resolved_pack_config, &schedule.wallpaper_pack_dir, index).unwrap()
+        };
+        let sunrise = desktop.sun_and_moon[&SunAndMoonKeys::Sunrise];
+        let noon = desktop.sun_and_moon[&SunAndMoonKeys::Noon];
+
+        assert_eq!(workspace.monitor(), Some("workspace2".to_string()));
+        assert_ne!(desktop.timestamp_seq, workspace.timestamp_seq);
+        assert_eq!(image(&desktop, noon), packs_dir.join("photos").join("day").join("day.png"));
+        assert_eq!(image(&desktop, sunrise), image(&desktop, noon));
+        assert_eq!(image(&workspace, sunrise), packs_dir.join("city").join("dawn").join("dawn.png"));
+
+        // Emptying one pack holds its output, the other keeps running.
+        let desktop_seq = desktop.timestamp_seq.clone();
+        let workspace_seq = workspace.timestamp_seq.clone();
+        std::fs::remove_file(packs_dir.join("city").join("dawn").join("dawn.png")).unwrap();
+        std::fs::remove_file(packs_dir.join("city").join("dusk").join("dusk.png")).unwrap();
+
+        assert!(!workspace.remap_or_hold(&config, &packs_dir, &mut decode_checker));
+        assert!(desktop.remap_or_hold(&config, &packs_dir, &mut decode_checker));
+        assert!(workspace.holding());
+        assert!(!desktop.holding());
+        assert_eq!(workspace.timestamp_seq, workspace_seq);
+        assert_eq!(desktop.timestamp_seq, desktop_seq);
+
+        // Moving one to the next day leaves the other on its own.
+        let rollover = desktop.day_end();
+        desktop.set_day(rollover, &config).unwrap();
+
+        assert_eq!(desktop.sun_and_moon[&SunAndMoonKeys::Midnight], rollover);
+        assert_eq!(workspace.day_end(), rollover);
+
+        std::fs::remove_dir_all(&packs_dir).unwrap();
+    }
+
+
+    #[test]
+    fn transitions_longer_than_the_shortest_slot_are_shortened() {
+        let crossfade = |duration_seconds| Transition::Crossfade {
+            duration_seconds,
+            frames: 12,
+            easing: Default::default(),
+        };
+        let mut schedule = schedule(Path::new("/packs/photos"));
+        schedule.sun_and_moon = HashMap::from([
+            (SunAndMoonKeys::Midnight, 0),
+            (SunAndMoonKeys::NextDayMidnight, 86400),
+        ]);
+        // Slots of an hour, a minute and a half and the rest of the day.
+        schedule.timestamp_seq = vec![3600, 3690, 86400];
+
+        schedule.playback.transition = crossfade(300);
+        assert_eq!(schedule.transition_for(None), crossfade(90));
+        assert_eq!(schedule.shortened_transition, Some((crossfade(300), 90)));
+
+        schedule.playback.transition = crossfade(90);
+        assert_eq!(schedule.transition_for(None), crossfade(90));
+
+        schedule.playback.transition = Transition::Instant;
+        assert_eq!(schedule.transition_for(None), Transition::Instant);
+    }
+
+
+    #[test]
+    fn empty_slots_leave_the_transition_alone() {
+        let crossfade = Transition::Crossfade {
+            duration_seconds: 300,
+            frames: 12,
+            easing: Default::default(),
+        };
+        let mut schedule = schedule(Path::new("/packs/photos"));
+        schedule.sun_and_moon = HashMap::from([
+            (SunAndMoonKeys::Midnight, 0),
+            (SunAndMoonKeys::NextDayMidnight, 86400),
+        ]);
+        // A moon phase moved out of place by the fixed order gets no time.
+        schedule.timestamp_seq = vec![43200, 43200, 86400];
+        schedule.playback.transition = crossfade.clone();
+
+        assert_eq!(schedule.transition_for(None), crossfade);
+        assert_eq!(schedule.shortened_transition, None);
+    }
+
+
+    #[test]
+    fn the_precomputed_next_day_matches_a_fresh_one() {
+        let wallpaper_pack_dir = std::env::temp_dir()
+            .join(format!("wallpaper_changer_monitor_next_day_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&wallpaper_pack_dir);
+        std::fs::create_dir_all(wallpaper_pack_dir.join("night")).unwrap();
+        std::fs::create_dir_all(wallpaper_pack_dir.join("day")).unwrap();
+        std::fs::write(wallpaper_pack_dir.join("night").join("moon.png"), "moon").unwrap();
+        std::fs::write(wallpaper_pack_dir.join("day").join("sun_1.png"), "sun").unwrap();
+        std::fs::write(wallpaper_pack_dir.join("day").join("sun_2.png"), "sun").unwrap();
+
+        // At the pole the fixed clock times are used, the same every day.
+        let config = WallpaperChangerConfig {
+            latitude: 90.0,
+            ..WallpaperChangerConfig::default()
+        };
+        let mut decode_checker = DecodeChecker::default();
+        let today = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap().timestamp();
+
+        let mut ahead = schedule(&wallpaper_pack_dir);
+        ahead.set_day(today, &config).unwrap();
+        ahead.remap(&config, &wallpaper_pack_dir, &mut decode_checker).unwrap();
+        let rollover = ahead.day_end();
+
+        ahead.precompute_next_day(rollover - PRECOMPUTE_LEAD_SECONDS - 1, &config, &wallpaper_pack_dir, &mut decode_checker);
+        assert!(ahead.precomputed_day.is_none());
+        ahead.precompute_next_day(rollover - 60, &config, &wallpaper_pack_dir, &mut decode_checker);
+        assert!(ahead.swap_in_next_day(rollover));
+
+        let mut fresh = schedule(&wallpaper_pack_dir);
+        fresh.set_day(rollover, &config).unwrap();
+        fresh.remap(&config, &wallpaper_pack_dir, &mut decode_checker).unwrap();
+
+        assert_eq!(ahead.day, fresh.day);
+        assert_eq!(ahead.sun_and_moon, fresh.sun_and_moon);
+        assert_eq!(ahead.timestamp_seq, fresh.timestamp_seq);
+        assert_eq!(ahead.phases, fresh.phases);
+        assert_eq!(ahead.resolved_pack_config.noon, fresh.resolved_pack_config.noon);
+
+        std::fs::remove_dir_all(&wallpaper_pack_dir).unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+
+fn take_chunk(chars: &mut Peekable<Chars>) -> Option<(bool, String)> {
+    let first = chars.next()?;
+    let is_digit = first.is_ascii_digit();
+
+    let mut chunk = String::from(first);
+    while let Some(next) = chars.next_if(|x| x.is_ascii_digit() == is_digit) {
+        chunk.push(next);
+    }
+
+    Some((is_digit, chunk))
+}
+
+
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+
+/// Orders strings the way humans expect, so `img2.png` sorts before `img10.png`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        let ordering = match (take_chunk(&mut a_chars), take_chunk(&mut b_chars)) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some((true, a_chunk)), Some((true, b_chunk))) => compare_numbers(&a_chunk, &b_chunk),
+            (Some((_, a_chunk)), Some((_, b_chunk))) => {
+                a_chunk.to_lowercase().cmp(&b_chunk.to_lowercase())
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn numbers_sort_by_value() {
+        let mut names = vec!["img100.png", "img10.png", "img2.png", "img1.png", "IMG3.png", "img02.png", "dawn.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(names, ["dawn.png", "img1.png", "img02.png", "img2.png", "IMG3.png", "img10.png", "img100.png"]);
+        assert_eq!(natural_cmp("img2.png", "img10.png"), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "img10"), Ordering::Equal);
+    }
+}
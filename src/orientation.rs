@@ -0,0 +1,220 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::processing;
+
+
+/// EXIF lives in the first segments of a JPEG, within its first 64 KiB.
+const HEADER_BYTES: u64 = 128 * 1024;
+const ORIENTATION_TAG: u16 = 0x0112;
+
+
+/// The orientation from the first IFD of a TIFF structure, 1 to 8.
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+
+    let u16_at = |x: usize| {
+        tiff.get(x..x + 2).map(|x| match big_endian {
+            true => u16::from_be_bytes([x[0], x[1]]),
+            false => u16::from_le_bytes([x[0], x[1]]),
+        })
+    };
+    let u32_at = |x: usize| {
+        tiff.get(x..x + 4).map(|x| match big_endian {
+            true => u32::from_be_bytes([x[0], x[1], x[2], x[3]]),
+            false => u32::from_le_bytes([x[0], x[1], x[2], x[3]]),
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)? as usize;
+
+    (0..entries)
+        .map(|x| ifd + 2 + x * 12)
+        .find(|x| u16_at(*x) == Some(ORIENTATION_TAG))
+        .and_then(|x| u16_at(x + 8))
+        .filter(|x| (1..=8).contains(x))
+}
+
+
+/// The EXIF orientation of a JPEG starting with `bytes`, `None` for other
+/// formats and JPEGs without one.
+fn jpeg_orientation(bytes: &[u8]) -> Option<u16> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut position = 2;
+
+    while let Some(&[0xFF, marker, high, low]) = bytes.get(position..position + 4) {
+        // The image data starts, there is no metadata after it.
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([high, low]) as usize;
+        let segment = bytes.get(position + 4..position + 2 + length)?;
+
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return tiff_orientation(&segment[6..]);
+        }
+
+        position += 2 + length;
+    }
+
+    None
+}
+
+
+/// The EXIF orientation of `image`, read from its header.
+fn read_orientation(image: &Path) -> Option<u16> {
+    let mut header = vec![];
+
+    File::open(image)
+        .and_then(|x| x.take(HEADER_BYTES).read_to_end(&mut header))
+        .ok()?;
+
+    jpeg_orientation(&header)
+}
+
+
+#[cfg(feature = "image")]
+fn apply(
+    image: image::DynamicImage,
+    orientation: Option<u16>
+) -> image::DynamicImage {
+    match orientation {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => image,
+    }
+}
+
+
+/// Decodes `image` turned upright by its EXIF orientation, for every step
+/// reading pack images, which the `image` crate would otherwise see as
+/// stored.
+#[cfg(feature = "image")]
+pub fn open(image: &Path) -> Result<image::DynamicImage, String> {
+    image::open(image)
+        .map(|x| apply(x, read_orientation(image)))
+        .map_err(|e| format!("Unable to open image {}: {e}", image.display()))
+}
+
+
+/// `image` rotated and flipped upright by its EXIF orientation, so photos
+/// taken in portrait aren't processed or set sideways. Images without an
+/// orientation, or already upright, are used as they are.
+///
+/// Upright copies are cached like gamma corrected ones, under the image and
+/// its modification time.
+pub fn oriented_image(
+    image: &Path,
+    orientation_dir: &Path
+) -> PathBuf {
+    if cfg!(not(feature = "image")) || !image.is_file() {
+        return image.to_path_buf();
+    }
+
+    if matches!(read_orientation(image), None | Some(1)) {
+        return image.to_path_buf();
+    }
+
+    processing::cached_output(image, "", orientation_dir, |output| save_upright(image, output))
+}
+
+
+#[cfg(feature = "image")]
+fn save_upright(
+    image: &Path,
+    output: &Path
+) -> Result<(), String> {
+    open(image)?
+        .save_with_format(output, image::ImageFormat::Png)
+        .map_err(|e| format!("Unable to save upright image: {e}"))
+}
+
+
+#[cfg(not(feature = "image"))]
+fn save_upright(
+    _image: &Path,
+    _output: &Path
+) -> Result<(), String> {
+    Err("Honoring EXIF orientation requires the `image` feature.".to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// A JPEG header with an EXIF segment holding `orientation`, big endian.
+    fn exif_header(orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+        tiff.extend(ORIENTATION_TAG.to_be_bytes());
+        tiff.extend([0, 3, 0, 0, 0, 1]);
+        tiff.extend(orientation.to_be_bytes());
+        tiff.extend([0; 6]);
+
+        let mut header = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        header.extend((2 + 6 + tiff.len() as u16).to_be_bytes());
+        header.extend(b"Exif\0\0");
+        header.extend(tiff);
+
+        header
+    }
+
+
+    #[test]
+    fn the_orientation_is_read_from_the_exif_segment() {
+        assert_eq!(jpeg_orientation(&exif_header(6)), Some(6));
+        assert_eq!(jpeg_orientation(&exif_header(9)), None);
+        assert_eq!(jpeg_orientation(&[0xFF, 0xD8, 0xFF, 0xDA, 0, 2]), None);
+        assert_eq!(jpeg_orientation(b"\x89PNG\r\n\x1a\n"), None);
+    }
+
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn sideways_photos_are_turned_upright() {
+        use image::{Rgb, RgbImage};
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_orientation_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Stored 8 wide and 4 high, white on the left, taken in portrait.
+        let stored = dir.join("stored.jpg");
+        RgbImage::from_fn(8, 4, |x, _| if x < 4 { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) })
+            .save(&stored)
+            .unwrap();
+        let bytes = std::fs::read(&stored).unwrap();
+        let mut tagged = exif_header(6);
+        tagged.extend(&bytes[2..]);
+        let photo = dir.join("photo.jpg");
+        std::fs::write(&photo, tagged).unwrap();
+
+        assert_eq!(oriented_image(&stored, &dir.join("orientation")), stored);
+
+        // Orientation 6 is turned a quarter clockwise, the left side ends up on top.
+        let upright = oriented_image(&photo, &dir.join("orientation"));
+        assert_ne!(upright, photo);
+        let upright = image::open(upright).unwrap().to_rgb8();
+        assert_eq!(upright.dimensions(), (4, 8));
+        assert!(upright.get_pixel(2, 0)[0] > 200);
+        assert!(upright.get_pixel(2, 7)[0] < 50);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
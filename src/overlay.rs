@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::remote;
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+
+/// Text drawn onto every wallpaper before it is set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Overlay {
+    /// `{time}`, `{date}`, `{phase}` and `{day_progress}`, how far through
+    /// the day from midnight as 0.00 to 1.00, are replaced with the current
+    /// values, a `\n` starts a new line.
+    pub template: String,
+    /// TrueType or OpenType font file the text is drawn with.
+    pub font_path: String,
+    /// Height of a line in pixels.
+    pub font_size: f32,
+    pub position: OverlayPosition,
+    /// Distance in pixels from the edges of the wallpaper.
+    pub margin: u32,
+    /// `#RRGGBB` or `#RGB`.
+    pub color: String,
+    /// From 0.0 (invisible) to 1.0, the glyph coverage is scaled by it.
+    pub opacity: f32,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self {
+            template: "{time}".to_string(),
+            font_path: "".to_string(),
+            font_size: 48.0,
+            position: OverlayPosition::BottomRight,
+            margin: 32,
+            color: "#FFFFFF".to_string(),
+            opacity: 0.8,
+        }
+    }
+}
+
+
+impl Overlay {
+    pub fn text(
+        &self,
+        now: DateTime<Local>,
+        phase: Option<&str>,
+        day_progress: f64
+    ) -> String {
+        self.template
+            .replace("{time}", &now.format("%H:%M").to_string())
+            .replace("{date}", &now.format("%Y-%m-%d").to_string())
+            .replace("{phase}", phase.unwrap_or_default())
+            .replace("{day_progress}", &format!("{day_progress:.2}"))
+    }
+}
+
+
+#[cfg(feature = "overlay")]
+fn draw(
+    overlay: &Overlay,
+    font: &ab_glyph::FontVec,
+    image: &Path,
+    text: &str,
+    output: &Path
+) -> Result<(), String> {
+    use ab_glyph::{point, Font, PxScale, ScaleFont};
+
+    let rgb = crate::solid_color::parse_hex_color(&overlay.color)?;
+    let scaled_font = font.as_scaled(PxScale::from(overlay.font_size));
+    let line_height = scaled_font.height() + scaled_font.line_gap();
+
+    let mut glyphs = vec![];
+    let mut text_width = 0.0_f32;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let mut caret = point(0.0, scaled_font.ascent() + line_index as f32 * line_height);
+        let mut previous = None;
+
+        for character in line.chars() {
+            let glyph_id = scaled_font.glyph_id(character);
+
+            if let Some(previous) = previous {
+                caret.x += scaled_font.kern(previous, glyph_id);
+            }
+
+            glyphs.push(glyph_id.with_scale_and_position(overlay.font_size, caret));
+            caret.x += scaled_font.h_advance(glyph_id);
+            previous = Some(glyph_id);
+        }
+
+        text_width = text_width.max(caret.x);
+    }
+
+    let text_height = text.lines().count() as f32 * line_height;
+
+    let mut canvas = image::open(image)
+        .map_err(|e| format!("Unable to open image {}: {e}", image.display()))?
+        .to_rgba8();
+
+    let (width, height) = (canvas.width() as f32, canvas.height() as f32);
+    let margin = overlay.margin as f32;
+
+    let (left, top) = match overlay.position {
+        OverlayPosition::TopLeft => (margin, margin),
+        OverlayPosition::TopRight => (width - margin - text_width, margin),
+        OverlayPosition::BottomLeft => (margin, height - margin - text_height),
+        OverlayPosition::BottomRight => (width - margin - text_width, height - margin - text_height),
+        OverlayPosition::Center => ((width - text_width) / 2.0, (height - text_height) / 2.0),
+    };
+
+    let opacity = overlay.opacity.clamp(0.0, 1.0);
+
+    for mut glyph in glyphs {
+        glyph.position = point(glyph.position.x + left, glyph.position.y + top);
+
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+
+        outlined.draw(|x, y, coverage| {
+            let (x, y) = (bounds.min.x as i64 + x as i64, bounds.min.y as i64 + y as i64);
+
+            if x < 0 || y < 0 || x >= canvas.width() as i64 || y >= canvas.height() as i64 {
+                return;
+            }
+
+            let alpha = coverage * opacity;
+            let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+
+            for channel in 0..3 {
+                pixel[channel] = (pixel[channel] as f32 * (1.0 - alpha) + rgb[channel] as f32 * alpha)
+                    .round() as u8;
+            }
+        });
+    }
+
+    std::fs::create_dir_all(output.parent().unwrap_or(Path::new("")))
+        .ok()
+        .ok_or("Unable to create overlay directory.")?;
+
+    canvas
+        .save(output)
+        .map_err(|e| format!("Unable to save overlay: {e}"))
+}
+
+
+/// Draws the overlay onto images, loading the font on first use and keeping
+/// the last rendered image around so an unchanged text isn't drawn again.
+#[derive(Debug, Default)]
+pub struct OverlayRenderer {
+    #[cfg(feature = "overlay")]
+    font: Option<ab_glyph::FontVec>,
+    last_render: Option<(PathBuf, String, PathBuf)>,
+    /// Render before the last one, removed once it can no longer be the
+    /// source of a transition.
+    stale_render: Option<PathBuf>,
+}
+
+impl OverlayRenderer {
+    #[cfg(feature = "overlay")]
+    fn render(
+        &mut self,
+        overlay: &Overlay,
+        image: &Path,
+        text: &str,
+        output: &Path
+    ) -> Result<(), String> {
+        if self.font.is_none() {
+            let data = std::fs::read(&overlay.font_path)
+                .map_err(|e| format!("Unable to read overlay font {}: {e}", overlay.font_path))?;
+
+            self.font = Some(
+                ab_glyph::FontVec::try_from_vec(data)
+                    .map_err(|e| format!("Unable to load overlay font {}: {e}", overlay.font_path))?
+            );
+        }
+
+        draw(overlay, self.font.as_ref().ok_or("Overlay font is not loaded.")?, image, text, output)
+    }
+
+    #[cfg(not(feature = "overlay"))]
+    fn render(
+        &mut self,
+        _overlay: &Overlay,
+        _image: &Path,
+        _text: &str,
+        _output: &Path
+    ) -> Result<(), String> {
+        Err("Text overlays require the `overlay` feature.".to_string())
+    }
+
+    /// `image` with `text` drawn on it, rendered into `overlay_dir`. Falls
+    /// back to the image itself when the overlay can't be drawn.
+    pub fn apply(
+        &mut self,
+        overlay: &Overlay,
+        image: &Path,
+        text: &str,
+        overlay_dir: &Path
+    ) -> PathBuf {
+        if let Some((last_image, last_text, rendered)) = &self.last_render {
+            if last_image == image && last_text == text {
+                return rendered.clone();
+            }
+        }
+
+        let output = overlay_dir.join(format!(
+            "{}.png",
+            remote::url_key(&format!("{}\n{text}", image.display()))
+        ));
+
+        // A failure is remembered like a render, so it is reported once per
+        // text instead of on every poll.
+        if let Err(e) = self.render(overlay, image, text, &output) {
+            eprintln!("{e}");
+            self.last_render = Some((image.to_path_buf(), text.to_string(), image.to_path_buf()));
+            return image.to_path_buf();
+        }
+
+        if let Some(stale_render) = self.stale_render.take().filter(|x| *x != output) {
+            let _ = std::fs::remove_file(stale_render);
+        }
+
+        self.stale_render = self.last_render
+            .take()
+            .filter(|(last_image, _, rendered)| last_image != rendered)
+            .map(|(_, _, x)| x);
+        self.last_render = Some((image.to_path_buf(), text.to_string(), output.clone()));
+
+        output
+    }
+}
@@ -0,0 +1,146 @@
+use serde_json::{Map, Value};
+
+use crate::WallpaperChangerConfig;
+
+
+/// Parses the value of a `--set` like a TOML value, so `60`, `40.5`, `true`
+/// and `["a", "b"]` keep their types. Anything else is taken as a string.
+fn parse_value(value: &str) -> Value {
+    toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut x| x.remove("value"))
+        .and_then(|x| serde_json::to_value(x).ok())
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+
+/// Why `key` is unknown, when it's missing from `root`, the config as it was
+/// read back after the overrides. Keys the config doesn't have are dropped
+/// while reading it, at any depth.
+fn unknown_key(
+    root: &Value,
+    key: &str
+) -> Option<String> {
+    let mut table = root;
+    let mut parent = vec![];
+
+    for segment in key.trim().split('.') {
+        let object = table.as_object()?;
+
+        let Some(value) = object.get(segment) else {
+            let mut keys = object.keys().cloned().collect::<Vec<String>>();
+            keys.sort();
+
+            return Some(match parent.is_empty() {
+                true => format!("Unknown config key '{segment}'. Valid keys: {}", keys.join(", ")),
+                false => format!(
+                    "Unknown config key '{segment}' in '{key}'. Valid keys of '{}': {}",
+                    parent.join("."),
+                    keys.join(", ")
+                ),
+            });
+        };
+
+        parent.push(segment);
+        table = value;
+    }
+
+    None
+}
+
+
+/// Applies `key=value` overrides on top of `config`. Keys of nested tables are
+/// joined with dots, e.g. `transition.duration_seconds=5`.
+pub fn apply(
+    config: WallpaperChangerConfig,
+    overrides: &[String]
+) -> Result<WallpaperChangerConfig, String> {
+    if overrides.is_empty() {
+        return Ok(config);
+    }
+
+    // Unlike TOML, JSON keeps unset options as nulls, so every key is there.
+    let mut root = serde_json::to_value(&config)
+        .map_err(|e| format!("Unable to apply config overrides: {e}"))?;
+
+    for set in overrides {
+        let (key, value) = set
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid override '{set}', expected KEY=VALUE."))?;
+
+        let mut path = key.trim().split('.').peekable();
+        let mut table = root
+            .as_object_mut()
+            .ok_or_else(|| "The config is not a table.".to_string())?;
+
+        let first = path.peek().copied().unwrap_or_default();
+
+        if !table.contains_key(first) {
+            let mut keys = table.keys().cloned().collect::<Vec<String>>();
+            keys.sort();
+
+            return Err(format!("Unknown config key '{first}'. Valid keys: {}", keys.join(", ")));
+        }
+
+        while let Some(segment) = path.next() {
+            if path.peek().is_none() {
+                table.insert(segment.to_string(), parse_value(value.trim()));
+                break;
+            }
+
+            let entry = table
+                .entry(segment.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+
+            if entry.is_null() {
+                *entry = Value::Object(Map::new());
+            }
+
+            table = entry
+                .as_object_mut()
+                .ok_or_else(|| format!("Config key '{segment}' in '{key}' is not a table."))?;
+        }
+    }
+
+    let config: WallpaperChangerConfig = serde_json::from_value(root)
+        .map_err(|e| format!("Invalid config overrides: {e}"))?;
+
+    let applied = serde_json::to_value(&config)
+        .map_err(|e| format!("Unable to apply config overrides: {e}"))?;
+
+    for set in overrides {
+        let key = set.split_once('=').map(|(key, _)| key).unwrap_or_default();
+
+        if let Some(e) = unknown_key(&applied, key) {
+            return Err(e);
+        }
+    }
+
+    Ok(config)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn apply_one(set: &str) -> Result<WallpaperChangerConfig, String> {
+        apply(WallpaperChangerConfig::default(), &[set.to_string()])
+    }
+
+
+    #[test]
+    fn nested_keys_are_checked_against_the_config() {
+        let config = apply_one("change_log.format=json").unwrap();
+        assert_eq!(config.change_log.format, crate::change_log::LogFormat::Json);
+
+        let config = apply_one("workspace_packs.2=photos").unwrap();
+        assert_eq!(config.workspace_packs["2"], "photos");
+
+        assert!(apply_one("fromat=json").unwrap_err().starts_with("Unknown config key 'fromat'."));
+        assert!(apply_one("change_log.fromat=json")
+            .unwrap_err()
+            .starts_with("Unknown config key 'fromat' in 'change_log.fromat'. Valid keys of 'change_log': format, path"));
+    }
+}
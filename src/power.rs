@@ -0,0 +1,28 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "battery"), allow(dead_code))]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+
+/// Returns `None` when the power source can't be determined. Machines without
+/// a battery are reported as running on AC.
+#[cfg(feature = "battery")]
+pub fn detect() -> Option<PowerSource> {
+    let manager = battery::Manager::new().ok()?;
+
+    for battery in manager.batteries().ok()? {
+        if battery.ok()?.state() == battery::State::Discharging {
+            return Some(PowerSource::Battery);
+        }
+    }
+
+    Some(PowerSource::Ac)
+}
+
+
+#[cfg(not(feature = "battery"))]
+pub fn detect() -> Option<PowerSource> {
+    None
+}
@@ -0,0 +1,391 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::alpha;
+use crate::color_grade::{self, Grade};
+use crate::effects::{self, Effects};
+use crate::gamma;
+use crate::orientation;
+use crate::overlay::{Overlay, OverlayRenderer};
+use crate::remote;
+
+
+/// Processed images waiting to be picked up by the loop. The worker waits
+/// once this many are queued.
+const RESULT_QUEUE: usize = 1;
+
+
+/// What to do to a scheduled image before it is set.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub image: PathBuf,
+    pub alpha_background: String,
+    pub gamma: f64,
+    pub effects: Option<Effects>,
+    pub color_grade: Option<Grade>,
+    /// Rows processed at a time, 0 for whole images.
+    pub strip_rows: u32,
+    /// The overlay and the text to draw with it.
+    pub overlay: Option<(Overlay, String)>,
+    /// Passed through to the result for the change log.
+    pub phase: Option<String>,
+}
+
+/// An image ready to be set.
+#[derive(Debug)]
+pub struct Processed {
+    pub image: PathBuf,
+    pub phase: Option<String>,
+}
+
+
+/// Where each processing step keeps its output.
+pub struct ProcessingDirs {
+    pub alpha: PathBuf,
+    pub color_grade: PathBuf,
+    pub effects: PathBuf,
+    pub gamma: PathBuf,
+    pub orientation: PathBuf,
+    pub overlay: PathBuf,
+}
+
+
+/// Holds at most one job and whether the processor was dropped. A job the
+/// worker hasn't started yet is replaced by a newer one.
+type JobSlot = Arc<(Mutex<(Option<(u64, Job)>, bool)>, Condvar)>;
+
+
+/// Turns images upright, flattens transparent ones and applies effects, color
+/// grading, gamma correction and overlays on a background thread, so a slow
+/// render doesn't hold up the loop. Only the latest requested image matters:
+/// queued jobs are replaced and results that were overtaken are dropped.
+pub struct ImageProcessor {
+    jobs: JobSlot,
+    results: Receiver<(u64, Processed)>,
+    sequence: u64,
+    dropped: u64,
+}
+
+
+/// The cached result of a processing step on `image`, named after the image,
+/// its modification time and `tag`, so it survives restarts and is redone
+/// when the image is edited. A missing one is written as a PNG by `render` to
+/// a `.part` file and renamed into place once complete, so an interrupted
+/// render is never picked up as a finished one. The image itself is used
+/// when it can't be rendered.
+pub fn cached_output(
+    image: &Path,
+    tag: &str,
+    cache_dir: &Path,
+    render: impl FnOnce(&Path) -> Result<(), String>
+) -> PathBuf {
+    let modified = fs::metadata(image)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+
+    let key = match tag {
+        "" => remote::url_key(&format!("{}\n{modified}", image.display())),
+        tag => remote::url_key(&format!("{}\n{modified}\n{tag}", image.display())),
+    };
+
+    let output = cache_dir.join(format!("{key}.png"));
+
+    if output.is_file() {
+        return output;
+    }
+
+    let partial_path = cache_dir.join(format!("{key}.part"));
+
+    let result = fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Unable to create {}: {e}", cache_dir.display()))
+        .and_then(|_| render(&partial_path))
+        .and_then(|_| {
+            fs::rename(&partial_path, &output).map_err(|e| format!("Unable to save {}: {e}", output.display()))
+        });
+
+    match result {
+        Ok(_) => output,
+        Err(e) => {
+            let _ = fs::remove_file(&partial_path);
+            eprintln!("{e}");
+            image.to_path_buf()
+        }
+    }
+}
+
+
+fn process(
+    job: Job,
+    overlay_renderer: &mut OverlayRenderer,
+    directories: &ProcessingDirs
+) -> Processed {
+    let image = orientation::oriented_image(&job.image, &directories.orientation);
+    let image = alpha::flattened_image(&image, &job.alpha_background, job.strip_rows, &directories.alpha);
+    let image = effects::applied_image(&image, job.effects, job.strip_rows, &directories.effects);
+    let image = color_grade::graded_image(&image, job.color_grade, job.strip_rows, &directories.color_grade);
+    let image = gamma::corrected_image(&image, job.gamma, job.strip_rows, &directories.gamma);
+
+    let image = match &job.overlay {
+        Some((overlay, text)) => overlay_renderer.apply(overlay, &image, text, &directories.overlay),
+        None => image,
+    };
+
+    Processed {
+        image,
+        phase: job.phase,
+    }
+}
+
+
+fn work(
+    jobs: JobSlot,
+    results: SyncSender<(u64, Processed)>,
+    directories: ProcessingDirs
+) {
+    let mut overlay_renderer = OverlayRenderer::default();
+    let (slot, job_ready) = &*jobs;
+
+    loop {
+        let (sequence, job) = {
+            let Ok(mut slot) = slot.lock() else {
+                return;
+            };
+
+            loop {
+                if slot.1 {
+                    return;
+                }
+
+                if let Some(job) = slot.0.take() {
+                    break job;
+                }
+
+                slot = match job_ready.wait(slot) {
+                    Ok(x) => x,
+                    Err(_) => return,
+                };
+            }
+        };
+
+        let processed = process(job, &mut overlay_renderer, &directories);
+
+        if results.send((sequence, processed)).is_err() {
+            return;
+        }
+    }
+}
+
+
+impl ImageProcessor {
+    pub fn new(directories: ProcessingDirs) -> Self {
+        let jobs = JobSlot::default();
+        let (result_sender, results) = mpsc::sync_channel(RESULT_QUEUE);
+
+        let worker_jobs = jobs.clone();
+        thread::spawn(move || work(worker_jobs, result_sender, directories));
+
+        Self {
+            jobs,
+            results,
+            sequence: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Queues `job`, replacing the queued one if the worker hasn't got to it
+    /// yet. Processing an image again is cheap, both steps keep their output.
+    pub fn request(&mut self, job: Job) {
+        let (slot, job_ready) = &*self.jobs;
+
+        let Ok(mut slot) = slot.lock() else {
+            return;
+        };
+
+        self.sequence += 1;
+
+        if slot.0.replace((self.sequence, job)).is_some() {
+            self.dropped += 1;
+        }
+
+        job_ready.notify_one();
+    }
+
+    /// Waits up to `timeout` for the latest requested image, returning early
+    /// once it's ready.
+    pub fn wait(&mut self, timeout: Duration) -> Option<Processed> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            match self.results.recv_timeout(remaining) {
+                Ok((sequence, processed)) if sequence == self.sequence => return Some(processed),
+                Ok(_) => self.dropped += 1,
+                Err(RecvTimeoutError::Timeout) => return None,
+                // The worker is gone, a poll still has to last the full interval.
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(remaining);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Jobs replaced before they were started plus results that came in after
+    /// a newer image was requested.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+
+/// `work` applied to every item on up to `threads` threads, capped at the
+/// available CPUs, the results in the items' order. With one thread the
+/// items are processed in turn on the calling one.
+#[cfg(feature = "image")]
+pub fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    work: impl Fn(&T) -> R + Sync
+) -> Vec<R> {
+    let available = thread::available_parallelism().map(|x| x.get()).unwrap_or(1);
+    let threads = threads.clamp(1, available).min(items.len());
+
+    if threads <= 1 {
+        return items.iter().map(work).collect();
+    }
+
+    let work = &work;
+
+    thread::scope(|scope| {
+        items
+            .chunks(items.len().div_ceil(threads))
+            .map(|chunk| scope.spawn(move || chunk.iter().map(work).collect::<Vec<R>>()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|x| x.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+
+impl Drop for ImageProcessor {
+    fn drop(&mut self) {
+        let (slot, job_ready) = &*self.jobs;
+
+        if let Ok(mut slot) = slot.lock() {
+            slot.1 = true;
+        }
+
+        job_ready.notify_one();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn job(image: &str) -> Job {
+        Job {
+            image: PathBuf::from(image),
+            alpha_background: "#000000".to_string(),
+            gamma: 1.0,
+            effects: None,
+            color_grade: None,
+            strip_rows: 0,
+            overlay: None,
+            phase: Some(image.to_string()),
+        }
+    }
+
+
+    #[test]
+    fn only_the_latest_requested_image_comes_back() {
+        let processed_dir = std::env::temp_dir().join(format!("wallpaper_changer_processor_{}", std::process::id()));
+        let mut image_processor = ImageProcessor::new(ProcessingDirs {
+            alpha: processed_dir.clone(),
+            color_grade: processed_dir.clone(),
+            effects: processed_dir.clone(),
+            gamma: processed_dir.clone(),
+            orientation: processed_dir.clone(),
+            overlay: processed_dir,
+        });
+
+        // Whether the worker got to the older jobs or they were replaced
+        // first, none of them is handed back.
+        for image in ["/images/dawn.png", "/images/day.png", "/images/dusk.png"] {
+            image_processor.request(job(image));
+        }
+
+        let processed = image_processor.wait(Duration::from_secs(10)).unwrap();
+
+        assert_eq!(processed.image, Path::new("/images/dusk.png"));
+        assert_eq!(processed.phase.as_deref(), Some("/images/dusk.png"));
+        assert_eq!(image_processor.dropped(), 2);
+        assert!(image_processor.wait(Duration::from_millis(10)).is_none());
+
+        image_processor.request(job("/images/night.png"));
+
+        assert_eq!(image_processor.wait(Duration::from_secs(10)).unwrap().image, Path::new("/images/night.png"));
+        assert_eq!(image_processor.dropped(), 2);
+    }
+
+
+    #[test]
+    fn cached_outputs_are_renamed_into_place_and_reused() {
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_cached_output_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (image, cache_dir) = (dir.join("day.png"), dir.join("cache"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&image, "day").unwrap();
+
+        let render = |output: &Path| {
+            assert_eq!(output.extension().unwrap(), "part");
+            fs::write(output, "rendered").map_err(|e| e.to_string())
+        };
+
+        let output = cached_output(&image, "1.5", &cache_dir, render);
+
+        assert_eq!(output.extension().unwrap(), "png");
+        assert_eq!(fs::read_to_string(&output).unwrap(), "rendered");
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1);
+
+        // Cached from now on, under another tag it's rendered again.
+        assert_eq!(cached_output(&image, "1.5", &cache_dir, |_| panic!("rendered again")), output);
+        assert_ne!(cached_output(&image, "2.2", &cache_dir, render), output);
+
+        // A failed render leaves nothing behind and falls back to the image.
+        let failed = cached_output(&image, "0.5", &cache_dir, |output| {
+            fs::write(output, "half").unwrap();
+            Err("out of memory".to_string())
+        });
+
+        assert_eq!(failed, image);
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn parallel_results_keep_the_order_of_the_items() {
+        let items = (0..37).collect::<Vec<u64>>();
+        let serial = parallel_map(&items, 1, |x| x * x);
+
+        for threads in [0, 2, 3, 8, 64] {
+            assert_eq!(parallel_map(&items, threads, |x| x * x), serial, "{threads} threads");
+        }
+
+        assert!(parallel_map(&[] as &[u64], 4, |x| x * x).is_empty());
+    }
+}
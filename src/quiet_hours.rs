@@ -0,0 +1,70 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+
+/// Daily window, in local `HH:MM` time, during which the wallpaper isn't changed.
+/// `end` earlier than `start` means the window spans midnight.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuietWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .ok()
+        .ok_or_else(|| format!("Unable to parse quiet hours time '{time}', expected HH:MM."))
+}
+
+
+impl QuietHours {
+    pub fn window(&self) -> Result<QuietWindow, String> {
+        Ok(QuietWindow {
+            start: parse_time(&self.start)?,
+            end: parse_time(&self.end)?,
+        })
+    }
+}
+
+
+impl QuietWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn quiet_windows_can_span_midnight() {
+        let time = |x: &str| parse_time(x).unwrap();
+        let window = QuietHours { start: "22:00".to_string(), end: "06:30".to_string() }.window().unwrap();
+
+        for quiet in ["22:00", "23:59", "00:00", "03:15", "06:29"] {
+            assert!(window.contains(time(quiet)), "{quiet}");
+        }
+        for changing in ["06:30", "12:00", "21:59"] {
+            assert!(!window.contains(time(changing)), "{changing}");
+        }
+
+        let window = QuietHours { start: "09:00".to_string(), end: "17:00".to_string() }.window().unwrap();
+        assert!(window.contains(time("09:00")) && !window.contains(time("17:00")) && !window.contains(time("23:00")));
+
+        assert!(QuietHours { start: "22".to_string(), end: "06:30".to_string() }.window().is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// The one source of randomness, shared by every randomized feature so a
+/// `random_seed` makes all of them repeat. Currently only
+/// `random_pack_on_launch` draws from it.
+///
+/// SplitMix64: tiny, fast and good enough to pick things, not for secrets.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Seeded with `seed`, or from the clock when there is none, so every
+    /// launch differs.
+    pub fn new(seed: Option<u64>) -> Self {
+        let state = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+
+        Self { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut x = self.state;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    }
+
+    /// A number in `0..bound`, 0 when `bound` is 0.
+    pub fn below(&mut self, bound: usize) -> usize {
+        match bound {
+            0 => 0,
+            _ => (self.next_u64() % bound as u64) as usize,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn a_fixed_seed_repeats_the_same_draws() {
+        let draws = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..50).map(|x| rng.below(x)).collect::<Vec<usize>>()
+        };
+
+        assert_eq!(draws(Some(42)), draws(Some(42)));
+        assert_ne!(draws(Some(42)), draws(Some(43)));
+        assert!(draws(Some(7)).iter().enumerate().all(|(bound, x)| *x < bound.max(1)));
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+#[cfg(feature = "network")]
+use std::{fs, io::Read};
+
+#[cfg(feature = "network")]
+use serde::{Deserialize, Serialize};
+
+
+pub fn is_remote(entry: &str) -> bool {
+    entry.starts_with("http://") || entry.starts_with("https://")
+}
+
+
+/// `file://` entries are plain paths once the scheme is removed.
+pub fn strip_file_scheme(entry: &str) -> &str {
+    entry.strip_prefix("file://").unwrap_or(entry)
+}
+
+
+/// FNV-1a, stable across runs and Rust versions unlike `DefaultHasher`.
+pub fn url_key(url: &str) -> String {
+    bytes_key(url.as_bytes())
+}
+
+
+/// FNV-1a of arbitrary bytes, e.g. a file's content.
+pub fn bytes_key(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{hash:016x}")
+}
+
+
+fn cached_image_path(url: &str, cache_dir: &Path) -> PathBuf {
+    let extension = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|x| Path::new(x).extension())
+        .and_then(|x| x.to_str())
+        .unwrap_or("img");
+
+    cache_dir.join(format!("{}.{extension}", url_key(url)))
+}
+
+
+#[cfg(feature = "network")]
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct CacheMetadata {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+
+#[cfg(feature = "network")]
+fn download(url: &str, image_path: &Path) -> Result<(), String> {
+    let metadata_path = image_path.with_extension("toml");
+
+    let metadata: CacheMetadata = match image_path.exists() {
+        true => fs::read_to_string(&metadata_path)
+            .ok()
+            .and_then(|x| toml::from_str(&x).ok())
+            .unwrap_or_default(),
+        false => CacheMetadata::default(),
+    };
+
+    let mut request = ureq::get(url);
+    if let Some(etag) = &metadata.etag {
+        request = request.set("If-None-Match", etag);
+    }
+    if let Some(last_modified) = &metadata.last_modified {
+        request = request.set("If-Modified-Since", last_modified);
+    }
+
+    let response = request
+        .call()
+        .map_err(|e| format!("Unable to download {url}: {e}"))?;
+
+    if response.status() == 304 {
+        return Ok(());
+    }
+
+    let metadata = CacheMetadata {
+        etag: response.header("ETag").map(|x| x.to_string()),
+        last_modified: response.header("Last-Modified").map(|x| x.to_string()),
+    };
+
+    let mut bytes = vec![];
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Unable to download {url}: {e}"))?;
+
+    fs::write(image_path, bytes)
+        .map_err(|e| format!("Unable to write {}: {e}", image_path.display()))?;
+    fs::write(&metadata_path, toml::to_string(&metadata).unwrap_or_default())
+        .map_err(|e| format!("Unable to write {}: {e}", metadata_path.display()))?;
+
+    Ok(())
+}
+
+
+#[cfg(not(feature = "network"))]
+fn download(url: &str, _image_path: &Path) -> Result<(), String> {
+    Err(format!("Unable to download {url}: remote images require the `network` feature."))
+}
+
+
+/// Downloads `url` into `cache_dir`, keyed by URL, re-downloading only when
+/// the server reports a change. A previously cached copy is used when the
+/// download fails.
+pub fn fetch(url: &str, cache_dir: &Path) -> Result<PathBuf, String> {
+    let image_path = cached_image_path(url, cache_dir);
+
+    std::fs::create_dir_all(cache_dir)
+        .ok()
+        .ok_or_else(|| format!("Unable to create remote image cache: {}", cache_dir.display()))?;
+
+    match download(url, &image_path) {
+        Ok(_) => Ok(image_path),
+        Err(e) if image_path.exists() => {
+            eprintln!("{e}\nUsing the cached copy.");
+            Ok(image_path)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::WallpaperChangerConfig;
+
+
+/// The settings naming `pack`, left pointing at nothing once it's removed.
+fn references(
+    config: &WallpaperChangerConfig,
+    pack: &str
+) -> Vec<&'static str> {
+    let names = |x: &Option<String>| x.as_deref() == Some(pack);
+
+    [
+        ("wallpaper_pack", config.wallpaper_pack == pack),
+        ("pack_rotation", config.pack_rotation.iter().any(|x| x == pack)),
+        ("random_pack_candidates", config.random_pack_candidates.iter().any(|x| x == pack)),
+        ("battery_pack", names(&config.battery_pack)),
+        ("ac_pack", names(&config.ac_pack)),
+        ("composite_regions", config.composite_regions.iter().any(|x| x.pack == pack)),
+        ("workspace_packs", config.workspace_packs.values().any(|x| x == pack)),
+        ("weekly_blend", config.weekly_blend.as_ref().is_some_and(|x| x.pack == pack)),
+    ]
+    .into_iter()
+    .filter(|(_, referenced)| *referenced)
+    .map(|(setting, _)| setting)
+    .collect()
+}
+
+
+/// Bytes taken by the files under `path`, links counted as themselves.
+fn directory_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|x| x.ok())
+        .filter_map(|x| fs::symlink_metadata(x.path()).ok().map(|metadata| (x.path(), metadata)))
+        .map(|(path, metadata)| match metadata.is_dir() {
+            true => directory_size(&path),
+            false => metadata.len(),
+        })
+        .sum()
+}
+
+
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", units[unit]),
+    }
+}
+
+
+/// Refuses pack names that would point anywhere but right inside the packs
+/// directory, like ones with separators or `..`.
+pub(crate) fn check_pack_name(pack: &str) -> Result<(), String> {
+    if pack.is_empty() {
+        return Err("The pack name is empty.".to_string());
+    }
+
+    let mut components = Path::new(pack).components();
+
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => Err(format!("'{pack}' is not a pack name.")),
+    }
+}
+
+
+/// The directory of `pack`, which has to be a plain directory right inside
+/// the packs directory. Names with separators or `..`, links and anything
+/// resolving elsewhere are refused.
+fn pack_dir(
+    wallpaper_packs_dir: &String,
+    pack: &str
+) -> Result<PathBuf, String> {
+    check_pack_name(pack)?;
+
+    let dir = Path::new(wallpaper_packs_dir).join(pack);
+
+    let metadata = fs::symlink_metadata(&dir)
+        .map_err(|_| format!("Pack '{pack}' doesn't exist in: {wallpaper_packs_dir}"))?;
+
+    if metadata.file_type().is_symlink() {
+        return Err(format!("{} is a link, remove it by hand.", dir.display()));
+    }
+
+    if !metadata.is_dir() {
+        return Err(format!("{} is not a pack directory.", dir.display()));
+    }
+
+    let packs_dir = fs::canonicalize(wallpaper_packs_dir)
+        .map_err(|e| format!("Unable to resolve {wallpaper_packs_dir}: {e}"))?;
+
+    match fs::canonicalize(&dir).is_ok_and(|x| x.parent() == Some(packs_dir.as_path())) {
+        true => Ok(dir),
+        false => Err(format!("{} is outside of {wallpaper_packs_dir}.", dir.display())),
+    }
+}
+
+
+fn confirm(question: &str) -> Result<bool, String> {
+    print!("{question} [y/N] ");
+    io::stdout().flush().ok();
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| format!("Unable to read the answer: {e}"))?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+
+/// Deletes the pack `pack` from the packs directory after asking, unless
+/// `yes`. A pack the config still uses is kept unless `force`.
+pub fn run(
+    config: &WallpaperChangerConfig,
+    wallpaper_packs_dir: &String,
+    pack: &str,
+    yes: bool,
+    force: bool
+) -> Result<(), String> {
+    let dir = pack_dir(wallpaper_packs_dir, pack)?;
+    let references = references(config, pack);
+
+    if !references.is_empty() && !force {
+        return Err(format!(
+            "Pack '{pack}' is still used by {}, pass --force to remove it anyway.",
+            references.join(", ")
+        ));
+    }
+
+    let size = directory_size(&dir);
+
+    if !yes && !confirm(&format!("Remove pack '{pack}' ({}) at {}?", format_size(size), dir.display()))? {
+        println!("Kept pack '{pack}'.");
+        return Ok(());
+    }
+
+    fs::remove_dir_all(&dir).map_err(|e| format!("Unable to remove {}: {e}", dir.display()))?;
+
+    println!("Removed pack '{pack}', freed {}.", format_size(size));
+
+    if !references.is_empty() {
+        println!("  Update {} in the config, they still name it.", references.join(", "));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn packs_are_removed_only_from_inside_the_packs_directory() {
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_remove_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let packs = dir.join("packs");
+        for pack in ["forest", "desert"] {
+            fs::create_dir_all(packs.join(pack)).unwrap();
+            fs::write(packs.join(pack).join("day.png"), "day").unwrap();
+        }
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("outside"), packs.join("linked")).unwrap();
+
+        let config = WallpaperChangerConfig {
+            wallpaper_pack: "forest".to_string(),
+            ..WallpaperChangerConfig::default()
+        };
+        let wallpaper_packs_dir = packs.to_string_lossy().to_string();
+
+        run(&config, &wallpaper_packs_dir, "desert", true, false).unwrap();
+        assert!(!packs.join("desert").exists());
+
+        // The configured pack stays unless forced.
+        assert!(run(&config, &wallpaper_packs_dir, "forest", true, false).is_err());
+        assert!(packs.join("forest").is_dir());
+
+        for pack in ["", "..", "../outside", "forest/..", "missing", "linked"] {
+            assert!(run(&config, &wallpaper_packs_dir, pack, true, true).is_err(), "{pack}");
+        }
+        assert!(dir.join("outside").is_dir());
+
+        run(&config, &wallpaper_packs_dir, "forest", true, true).unwrap();
+        assert!(!packs.join("forest").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
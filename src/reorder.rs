@@ -0,0 +1,252 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use toml_edit::{Array, Document, RawString, Value};
+
+use crate::load_wallpaper_pack;
+
+
+pub(crate) const PHASES: [&str; 6] = ["midnight", "moonset", "sunrise", "noon", "sunset", "moonrise"];
+
+
+fn describe_entry(entry: &Value) -> String {
+    match entry {
+        Value::String(image) => image.value().clone(),
+        Value::InlineTable(timed) => format!(
+            "{} ({}s)",
+            timed.get("image").and_then(|x| x.as_str()).unwrap_or_default(),
+            timed.get("duration_seconds").and_then(|x| x.as_integer()).unwrap_or_default()
+        ),
+        other => other.to_string().trim().to_string(),
+    }
+}
+
+
+/// Splits the whitespace and comments before an array entry into the comment
+/// closing the previous line, if any, and the rest.
+fn split_line_end(raw: &str) -> (&str, &str) {
+    match raw.find('\n') {
+        Some(x) => raw.split_at(x),
+        None => ("", raw),
+    }
+}
+
+
+/// Parses a comma separated list of indices that must use every index below
+/// `count` exactly once.
+fn parse_order(input: &str, count: usize) -> Result<Vec<usize>, String> {
+    let order = input
+        .split(',')
+        .map(|x| x.trim().parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|e| format!("Invalid index in '{}': {e}", input.trim()))?;
+
+    let mut sorted = order.clone();
+    sorted.sort_unstable();
+
+    if !sorted.iter().copied().eq(0..count) {
+        return Err(format!("The new order must list every index from 0 to {} exactly once.", count.saturating_sub(1)));
+    }
+
+    Ok(order)
+}
+
+
+/// Rewrites the images of `phase` in the pack config at
+/// `wallpaper_pack_config_path` in the order `read_order` returns for them.
+/// Only that list changes: the layout stays, comments at the end of an entry's
+/// line move along with it and the rest of the file is left as it is.
+fn reorder(
+    wallpaper_pack_config_path: &Path,
+    wallpaper_pack: &str,
+    phase: &str,
+    read_order: impl FnOnce(&Array) -> Result<Vec<usize>, String>
+) -> Result<(), String> {
+    let mut document = fs::read_to_string(wallpaper_pack_config_path)
+        .ok()
+        .ok_or("Unable to read wallpaper_pack_config.toml file.")?
+        .parse::<Document>()
+        .map_err(|e| format!("Unable to parse wallpaper_pack_config.toml file: {e}"))?;
+
+    let images = match PHASES.contains(&phase) {
+        true => document.get_mut(phase),
+        false => document
+            .get_mut("anchors")
+            .and_then(|x| x.get_mut(phase))
+            .and_then(|x| x.get_mut("images")),
+    }
+        .and_then(|x| x.as_array_mut())
+        .ok_or_else(|| format!("Pack '{wallpaper_pack}' has no images for '{phase}'."))?;
+
+    let order = read_order(images)?;
+    let entries = images.iter().cloned().collect::<Vec<Value>>();
+
+    let raw = |x: Option<&RawString>| x.and_then(|x| x.as_str()).unwrap_or_default().to_string();
+    let prefixes = entries.iter().map(|x| raw(x.decor().prefix())).collect::<Vec<String>>();
+    let trailing = raw(Some(images.trailing()));
+
+    // The comment ending an entry's line is stored in front of the next entry.
+    let line_ends = (0..entries.len())
+        .map(|x| split_line_end(prefixes.get(x + 1).unwrap_or(&trailing)).0.to_string())
+        .collect::<Vec<String>>();
+
+    for (slot, entry) in order.iter().enumerate() {
+        let prefix = match slot {
+            0 => prefixes[0].clone(),
+            _ => line_ends[order[slot - 1]].clone() + split_line_end(&prefixes[slot]).1,
+        };
+
+        let mut value = entries[*entry].clone();
+        value.decor_mut().set_prefix(prefix);
+        value.decor_mut().set_suffix(raw(entries[slot].decor().suffix()));
+
+        images.replace_formatted(slot, value);
+    }
+
+    if let Some(last) = order.last() {
+        images.set_trailing(line_ends[*last].clone() + split_line_end(&trailing).1);
+    }
+
+    fs::write(wallpaper_pack_config_path, document.to_string())
+        .ok()
+        .ok_or("Unable to write wallpaper_pack_config.toml file.")?;
+
+    Ok(())
+}
+
+
+/// Prints the images of `phase` with their indices, reads the new order from
+/// stdin and writes it back to the pack config. `phase` is one of the six
+/// phases or the name of an anchor.
+pub fn run(
+    wallpaper_packs_dir: &String,
+    wallpaper_pack: &String,
+    phase: &str,
+    wallpaper_pack_config_name: &String
+) -> Result<(), String> {
+    let (wallpaper_pack_dir, _) = load_wallpaper_pack(
+        wallpaper_packs_dir,
+        wallpaper_pack,
+        wallpaper_pack_config_name
+    )?;
+
+    let wallpaper_pack_config_path = Path::new(&wallpaper_pack_dir).join(wallpaper_pack_config_name);
+
+    reorder(&wallpaper_pack_config_path, wallpaper_pack, phase, |images| {
+        println!("Images of '{phase}' in pack '{wallpaper_pack}':");
+        for (index, entry) in images.iter().enumerate() {
+            println!("  {index}: {}", describe_entry(entry));
+        }
+
+        print!("New order (comma separated indices): ");
+        io::stdout().flush().ok();
+
+        let mut input = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut input)
+            .ok()
+            .ok_or("Unable to read the new order.")?;
+
+        parse_order(&input, images.len())
+    })?;
+
+    println!("Saved the new order to {}", wallpaper_pack_config_path.display());
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    const PACK_CONFIG: &str = r#"# Mountains at dawn and dusk.
+version = 2
+
+midnight = [
+    "night/stars.png", # the clearest one
+    { image = "night/moon.png", duration_seconds = 600 },
+    "night/dark.png",
+]
+noon = ["day.png"]
+
+[anchors.golden_hour]
+at = "sunset - 60m"
+images = ["gold_1.png", "gold_2.png"] # warm
+"#;
+
+
+    fn pack_config_path(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("wallpaper_changer_reorder_{name}_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join("wallpaper_pack_config.toml");
+        fs::write(&path, PACK_CONFIG).unwrap();
+
+        path
+    }
+
+
+    #[test]
+    fn parse_order_needs_every_index_once() {
+        assert_eq!(parse_order(" 2, 0,1\n", 3), Ok(vec![2, 0, 1]));
+        assert!(parse_order("0,0,1", 3).is_err());
+        assert!(parse_order("0,1", 3).is_err());
+        assert!(parse_order("0,a,1", 3).is_err());
+    }
+
+
+    #[test]
+    fn reorder_only_touches_the_phase_list() {
+        let path = pack_config_path("phase");
+
+        reorder(&path, "mountains", "midnight", |images| {
+            assert_eq!(
+                images.iter().map(describe_entry).collect::<Vec<String>>(),
+                ["night/stars.png", "night/moon.png (600s)", "night/dark.png"]
+            );
+
+            Ok(vec![1, 2, 0])
+        }).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), PACK_CONFIG.replace(
+            r#"    "night/stars.png", # the clearest one
+    { image = "night/moon.png", duration_seconds = 600 },
+    "night/dark.png","#,
+            r#"    { image = "night/moon.png", duration_seconds = 600 },
+    "night/dark.png",
+    "night/stars.png", # the clearest one"#
+        ));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+
+    #[test]
+    fn reorder_finds_anchor_images() {
+        let path = pack_config_path("anchor");
+
+        reorder(&path, "mountains", "golden_hour", |_| Ok(vec![1, 0])).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            PACK_CONFIG.replace(r#"["gold_1.png", "gold_2.png"]"#, r#"["gold_2.png", "gold_1.png"]"#)
+        );
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+
+    #[test]
+    fn reorder_reports_missing_phases() {
+        let path = pack_config_path("missing");
+
+        assert!(reorder(&path, "mountains", "sunset", |_| Ok(vec![])).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), PACK_CONFIG);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::astronomy::Geodate;
+use crate::{
+    day_phases,
+    get_day_sun_and_moon_position_times,
+    map_timestamps,
+    schedule_slot,
+    slot_durations,
+    BoundaryOptions,
+    WallpaperChangerConfig,
+    WallpaperPackConfig,
+};
+
+
+/// An instant as POSIX seconds and in both time zones people compare.
+#[derive(Serialize, Debug)]
+struct Time {
+    posix: i64,
+    local: String,
+    utc: String,
+}
+
+impl Time {
+    fn new(timestamp: i64) -> Self {
+        Self {
+            posix: timestamp,
+            local: Local
+                .timestamp_opt(timestamp, 0)
+                .single()
+                .map(|x| x.to_rfc3339())
+                .unwrap_or_default(),
+            utc: Utc
+                .timestamp_opt(timestamp, 0)
+                .single()
+                .map(|x| x.to_rfc3339())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+
+#[derive(Serialize, Debug)]
+struct Boundary {
+    event: String,
+    #[serde(flatten)]
+    time: Time,
+}
+
+
+#[derive(Serialize, Debug)]
+struct Slot {
+    phase: String,
+    image: String,
+    start: Time,
+    end: Time,
+    duration_seconds: i64,
+}
+
+
+#[derive(Serialize, Debug)]
+struct Report<'a> {
+    generated: String,
+    version: &'static str,
+    day: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+    wallpaper_pack: &'a str,
+    wallpaper_pack_dir: &'a str,
+    boundaries: Vec<Boundary>,
+    schedule: Vec<Slot>,
+    config: &'a WallpaperChangerConfig,
+    pack: &'a WallpaperPackConfig,
+}
+
+
+/// Prints the effective config, the active pack with its entries resolved,
+/// today's phase boundaries and the schedule built from them as one JSON
+/// document, for bug reports. Nothing is left out, the config holds no
+/// secrets.
+pub fn run(
+    today: NaiveDateTime,
+    config: &WallpaperChangerConfig,
+    wallpaper_pack: &str,
+    wallpaper_pack_dir: &str,
+    wallpaper_pack_config: &WallpaperPackConfig
+) -> Result<(), String> {
+    let sun_and_moon = get_day_sun_and_moon_position_times(
+        &Geodate,
+        today.timestamp(),
+        config.longitude,
+        config.latitude,
+        BoundaryOptions::from_config(config),
+    )?;
+
+    let mut boundaries = sun_and_moon
+        .iter()
+        .map(|(key, timestamp)| Boundary {
+            event: format!("{key:?}"),
+            time: Time::new(*timestamp),
+        })
+        .collect::<Vec<Boundary>>();
+    boundaries.sort_by_key(|x| x.time.posix);
+
+    let timestamp_seq = map_timestamps(&sun_and_moon, wallpaper_pack_config);
+    let phases = day_phases(&sun_and_moon, wallpaper_pack_config);
+
+    let schedule = slot_durations(&sun_and_moon, &timestamp_seq)
+        .into_iter()
+        .zip(&timestamp_seq)
+        .enumerate()
+        .map(|(index, (duration, slot_end))| {
+            let (phase, image) = schedule_slot(&phases, wallpaper_pack_config, index)
+                .map(|(phase, image)| (phase, image.path().to_string()))
+                .unwrap_or_default();
+
+            Slot {
+                phase,
+                image,
+                start: Time::new(slot_end - duration),
+                end: Time::new(*slot_end),
+                duration_seconds: duration,
+            }
+        })
+        .collect::<Vec<Slot>>();
+
+    let report = Report {
+        generated: Local::now().to_rfc3339(),
+        version: env!("CARGO_PKG_VERSION"),
+        day: today.date(),
+        latitude: config.latitude,
+        longitude: config.longitude,
+        wallpaper_pack,
+        wallpaper_pack_dir,
+        boundaries,
+        schedule,
+        config,
+        pack: wallpaper_pack_config,
+    };
+
+    let report = serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("Unable to serialize the report: {e}"))?;
+
+    println!("{report}");
+
+    Ok(())
+}
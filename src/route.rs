@@ -0,0 +1,116 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+
+/// How far, in degrees, the position has to move along the route before the
+/// schedule is computed again.
+pub const RECOMPUTE_DEGREES: f64 = 0.05;
+
+
+/// A point of a travel route: where you'll be at a given time.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Waypoint {
+    /// RFC 3339, e.g. `2024-05-01T08:00:00+02:00`, or local
+    /// `YYYY-MM-DD HH:MM`.
+    pub at: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+
+fn parse_time(time: &str) -> Result<i64, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time.trim()) {
+        return Ok(time.timestamp());
+    }
+
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(time.trim(), format).ok())
+        .and_then(|x| Local.from_local_datetime(&x).earliest())
+        .map(|x| x.timestamp())
+        .ok_or_else(|| format!("Unable to parse route time '{time}', expected RFC 3339 or YYYY-MM-DD HH:MM."))
+}
+
+
+/// The route's waypoints as timestamps, in order.
+pub fn parse(route: &[Waypoint]) -> Result<Vec<(i64, f64, f64)>, String> {
+    let mut points = route
+        .iter()
+        .map(|x| {
+            if !(-90.0..=90.0).contains(&x.latitude) || !(-180.0..=180.0).contains(&x.longitude) {
+                return Err(format!("Route waypoint at {} is out of range.", x.at));
+            }
+
+            Ok((parse_time(&x.at)?, x.latitude, x.longitude))
+        })
+        .collect::<Result<Vec<(i64, f64, f64)>, String>>()?;
+
+    points.sort_by_key(|x| x.0);
+
+    Ok(points)
+}
+
+
+/// Latitude and longitude at `timestamp`, moving in a straight line between
+/// the waypoints around it. Before the first and after the last waypoint the
+/// position stays put. Longitudes take the short way across ±180°.
+pub fn position(points: &[(i64, f64, f64)], timestamp: i64) -> Option<(f64, f64)> {
+    let first = points.first()?;
+    let last = points.last()?;
+
+    if timestamp <= first.0 {
+        return Some((first.1, first.2));
+    }
+    if timestamp >= last.0 {
+        return Some((last.1, last.2));
+    }
+
+    let segment = points.windows(2).find(|x| x[0].0 <= timestamp && timestamp < x[1].0)?;
+    let ((start, start_latitude, start_longitude), (end, end_latitude, end_longitude)) = (segment[0], segment[1]);
+
+    let progress = (timestamp - start) as f64 / (end - start) as f64;
+
+    let longitude_delta = (end_longitude - start_longitude + 540.0).rem_euclid(360.0) - 180.0;
+    let longitude = (start_longitude + longitude_delta * progress + 540.0).rem_euclid(360.0) - 180.0;
+
+    Some((start_latitude + (end_latitude - start_latitude) * progress, longitude))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn waypoint(at: &str, latitude: f64, longitude: f64) -> Waypoint {
+        Waypoint { at: at.to_string(), latitude, longitude }
+    }
+
+
+    #[test]
+    fn positions_are_interpolated_between_waypoints() {
+        let points = parse(&[
+            waypoint("2024-05-01T12:00:00Z", 50.0, 170.0),
+            waypoint("2024-05-01T08:00:00Z", 40.0, 10.0),
+            waypoint("2024-05-01T10:00:00Z", 44.0, 20.0),
+            waypoint("2024-05-01T14:00:00Z", 52.0, -170.0),
+        ]).unwrap();
+        let at = |time: &str| position(&points, parse_time(time).unwrap()).unwrap();
+        let close = |(latitude, longitude): (f64, f64), expected: (f64, f64)| {
+            (latitude - expected.0).abs() < 1e-9 && (longitude - expected.1).abs() < 1e-9
+        };
+
+        assert!(close(at("2024-05-01T06:00:00Z"), (40.0, 10.0)));
+        assert!(close(at("2024-05-01T08:00:00Z"), (40.0, 10.0)));
+        assert!(close(at("2024-05-01T09:00:00Z"), (42.0, 15.0)));
+        assert!(close(at("2024-05-01T10:30:00Z"), (45.5, 57.5)));
+        // Across ±180° rather than back around the globe.
+        assert!(close(at("2024-05-01T13:00:00Z"), (51.0, 180.0)) || close(at("2024-05-01T13:00:00Z"), (51.0, -180.0)));
+        assert!(close(at("2024-05-01T13:30:00Z"), (51.5, -175.0)));
+        assert!(close(at("2024-05-01T20:00:00Z"), (52.0, -170.0)));
+
+        assert_eq!(position(&[], 0), None);
+        assert!(parse(&[waypoint("2024-05-01T08:00:00Z", 91.0, 0.0)]).is_err());
+        assert!(parse(&[waypoint("tomorrow", 0.0, 0.0)]).is_err());
+    }
+}
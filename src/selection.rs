@@ -0,0 +1,77 @@
+/// Remembers the selected schedule slot for the rest of the minute, so polls
+/// within it don't select again. An entry also ends at the next boundary of
+/// the schedule, phase selections match a fresh one exactly. Solar time
+/// slots have no boundaries in the schedule and may switch up to a minute
+/// late.
+#[derive(Debug, Default)]
+pub struct SelectionCache {
+    /// The selection and the range of timestamps it holds for.
+    entry: Option<(i64, i64, Option<usize>)>,
+}
+
+
+impl SelectionCache {
+    /// The cached selection for `timestamp`, or the one `select` makes.
+    pub fn get_or_select(
+        &mut self,
+        timestamp: i64,
+        timestamp_seq: &[i64],
+        select: impl FnOnce() -> Option<usize>
+    ) -> Option<usize> {
+        if let Some((start, end, index)) = self.entry {
+            if start <= timestamp && timestamp < end {
+                return index;
+            }
+        }
+
+        let minute_end = (timestamp.div_euclid(60) + 1) * 60;
+        let next_boundary = timestamp_seq
+            .iter()
+            .copied()
+            .find(|x| *x > timestamp)
+            .unwrap_or(i64::MAX);
+
+        let index = select();
+        self.entry = Some((timestamp, minute_end.min(next_boundary), index));
+
+        index
+    }
+
+    /// Drops the cached selection, for when the schedule changes.
+    pub fn invalidate(&mut self) {
+        self.entry = None;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::select_image_index;
+
+
+    #[test]
+    fn cached_selections_match_fresh_ones() {
+        let timestamp_seq = [90, 150, 155, 400, 401];
+        let mut selection_cache = SelectionCache::default();
+        let mut selections = 0;
+
+        for timestamp in 0..500 {
+            let cached = selection_cache.get_or_select(timestamp, &timestamp_seq, || {
+                selections += 1;
+                select_image_index(&timestamp_seq, timestamp)
+            });
+
+            assert_eq!(cached, select_image_index(&timestamp_seq, timestamp), "{timestamp}");
+        }
+
+        // Once a minute and at every boundary within one.
+        assert_eq!(selections, 9 + 5);
+
+        // A new schedule is selected from right away.
+        let timestamp_seq = [10, 20];
+        selection_cache.invalidate();
+
+        assert_eq!(selection_cache.get_or_select(499, &timestamp_seq, || select_image_index(&timestamp_seq, 499)), Some(1));
+    }
+}
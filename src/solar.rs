@@ -0,0 +1,54 @@
+use std::f64::consts::PI;
+
+use chrono::{Datelike, TimeZone, Timelike, Utc};
+use geodate::sun_transit;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunPosition {
+    /// Degrees above the horizon, negative when the sun is below it.
+    pub altitude: f64,
+    /// Apparent solar time in hours, 12.0 when the sun crosses the meridian.
+    pub solar_time: f64,
+}
+
+
+/// The sun's position at `timestamp`. Solar time is measured from the solar
+/// noon `geodate` computes, the same one the schedule's noon uses, and the
+/// declination follows the NOAA fractional-year equation, accurate to within a
+/// fraction of a degree which is plenty for picking images.
+pub fn sun_position(
+    timestamp: i64,
+    longitude: f64,
+    latitude: f64,
+) -> SunPosition {
+    let utc = Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_default();
+
+    let minutes = (utc.hour() * 60 + utc.minute()) as f64 + utc.second() as f64 / 60.0;
+    let gamma = 2.0 * PI / 365.0 * (utc.ordinal0() as f64 + (minutes / 60.0 - 12.0) / 24.0);
+
+    let declination = 0.006918
+        - 0.399912 * gamma.cos()
+        + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    // `get_noon` returns the noon of the solar day containing `timestamp`.
+    let noon = sun_transit::get_noon(timestamp, longitude);
+    let solar_time = (12.0 + (timestamp - noon) as f64 / 3600.0).rem_euclid(24.0);
+    let hour_angle = ((solar_time - 12.0) * 15.0).to_radians();
+
+    let latitude = latitude.to_radians();
+    let cos_zenith = latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos();
+
+    SunPosition {
+        altitude: 90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees(),
+        solar_time,
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "image")]
+use crate::display;
+
+
+/// Parses `#RRGGBB` or `#RGB`, the leading `#` being optional.
+pub(crate) fn parse_hex_color(hex: &str) -> Result<[u8; 3], String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    let invalid = || format!("Invalid color '{hex}', expected #RRGGBB or #RGB.");
+
+    if !digits.chars().all(|x| x.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |x: &str| u8::from_str_radix(x, 16).ok().ok_or_else(invalid);
+
+    match digits.len() {
+        6 => Ok([channel(&digits[0..2])?, channel(&digits[2..4])?, channel(&digits[4..6])?]),
+        3 => Ok([
+            channel(&digits[0..1])? * 17,
+            channel(&digits[1..2])? * 17,
+            channel(&digits[2..3])? * 17,
+        ]),
+        _ => Err(invalid()),
+    }
+}
+
+
+/// Renders a solid color image into `output_dir`, sized to the primary
+/// monitor when it is known and a single pixel otherwise.
+#[cfg(feature = "image")]
+pub fn render(hex: &str, output_dir: &Path) -> Result<PathBuf, String> {
+    let rgb = parse_hex_color(hex)?;
+    let (width, height) = display::primary_monitor_size().unwrap_or((1, 1));
+
+    std::fs::create_dir_all(output_dir)
+        .ok()
+        .ok_or("Unable to create solid color output directory.")?;
+
+    let output = output_dir.join(format!("solid_{:02x}{:02x}{:02x}.png", rgb[0], rgb[1], rgb[2]));
+
+    image::RgbImage::from_pixel(width, height, image::Rgb(rgb))
+        .save(&output)
+        .map_err(|e| format!("Unable to save solid color image: {e}"))?;
+
+    Ok(output)
+}
+
+
+#[cfg(not(feature = "image"))]
+pub fn render(hex: &str, _output_dir: &Path) -> Result<PathBuf, String> {
+    parse_hex_color(hex)?;
+
+    Err("Setting a solid color requires the `image` feature.".to_string())
+}
+
+
+/// Sets a solid color wallpaper, see `render`.
+pub fn set(hex: &str, output_dir: &Path) -> Result<(), String> {
+    let output = render(hex, output_dir)?;
+
+    wallpaper::set_from_path(&output.to_string_lossy())
+        .map_err(|e| format!("Unable to set wallpaper {}: {e}", output.display()))
+}
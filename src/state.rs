@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DaemonStatus {
+    Running,
+    #[default]
+    Stopped,
+}
+
+
+/// Runtime state persisted between runs, separate from the user's config.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct WallpaperChangerState {
+    pub rotation_index: usize,
+    pub rotation_date: Option<NaiveDate>,
+    pub status: DaemonStatus,
+}
+
+/// Whether the changer was turned off with `disable`.
+pub fn is_disabled(disabled_path: &Path) -> bool {
+    disabled_path.exists()
+}
+
+
+/// Turns the changer off or back on across restarts. It's a file of its own
+/// rather than part of the state, which a running daemon stores over.
+pub fn set_disabled(disabled_path: &Path, disabled: bool) -> Result<(), String> {
+    match (disabled, is_disabled(disabled_path)) {
+        (true, false) => std::fs::write(disabled_path, "")
+            .map_err(|e| format!("Unable to create {}: {e}", disabled_path.display())),
+        (false, true) => std::fs::remove_file(disabled_path)
+            .map_err(|e| format!("Unable to remove {}: {e}", disabled_path.display())),
+        _ => Ok(()),
+    }
+}
+
+
+impl WallpaperChangerState {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        confy::load_path(path)
+            .ok()
+            .ok_or_else(|| format!("Unable to load the state file: {}", path.display()))
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), String> {
+        crate::store_file(path, self)
+            .map_err(|e| format!("Unable to store the state file. {e}"))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn disabling_lasts_until_enabled_again() {
+        let disabled_path = std::env::temp_dir()
+            .join(format!("wallpaper_changer_disabled_{}", std::process::id()));
+        let _ = std::fs::remove_file(&disabled_path);
+
+        assert!(!is_disabled(&disabled_path));
+
+        // Every startup checks the file again, disabling twice is fine.
+        set_disabled(&disabled_path, true).unwrap();
+        set_disabled(&disabled_path, true).unwrap();
+        assert!(is_disabled(&disabled_path));
+
+        set_disabled(&disabled_path, false).unwrap();
+        set_disabled(&disabled_path, false).unwrap();
+        assert!(!is_disabled(&disabled_path));
+    }
+}
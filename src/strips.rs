@@ -0,0 +1,260 @@
+#[cfg(feature = "image")]
+use std::fs::File;
+#[cfg(feature = "image")]
+use std::io::{BufReader, BufWriter, Write};
+#[cfg(feature = "image")]
+use std::path::Path;
+
+#[cfg(feature = "image")]
+use image::{DynamicImage, RgbaImage};
+
+
+#[cfg(feature = "image")]
+enum Source {
+    /// PNGs, which every processing step writes, are decoded a row at a time.
+    Png(Box<png::Reader<BufReader<File>>>),
+    /// Other formats and interlaced PNGs are decoded whole up front, only the
+    /// RGBA conversion happens strip by strip.
+    Decoded(DynamicImage),
+}
+
+
+/// Reads an image from the top as RGBA rows.
+#[cfg(feature = "image")]
+struct StripReader {
+    source: Source,
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+    /// The first row not read yet.
+    row: u32,
+}
+
+#[cfg(feature = "image")]
+impl StripReader {
+    fn open(image: &Path) -> Result<Self, String> {
+        let open_error = |e: &dyn std::fmt::Display| format!("Unable to open image {}: {e}", image.display());
+
+        let is_png = image::ImageFormat::from_path(image).is_ok_and(|x| x == image::ImageFormat::Png);
+
+        if is_png {
+            let mut decoder = png::Decoder::new(BufReader::new(File::open(image).map_err(|e| open_error(&e))?));
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+            let reader = decoder.read_info().map_err(|e| open_error(&e))?;
+
+            if !reader.info().interlaced {
+                let (width, height) = (reader.info().width, reader.info().height);
+                let has_alpha = matches!(
+                    reader.output_color_type().0,
+                    png::ColorType::GrayscaleAlpha | png::ColorType::Rgba
+                );
+
+                return Ok(Self {
+                    source: Source::Png(Box::new(reader)),
+                    width,
+                    height,
+                    has_alpha,
+                    row: 0,
+                });
+            }
+        }
+
+        let decoded = image::open(image).map_err(|e| open_error(&e))?;
+
+        Ok(Self {
+            width: decoded.width(),
+            height: decoded.height(),
+            has_alpha: decoded.color().has_alpha(),
+            source: Source::Decoded(decoded),
+            row: 0,
+        })
+    }
+
+    /// Appends the next `rows` rows, fewer at the bottom, to `pixels` as RGBA.
+    fn read(
+        &mut self,
+        rows: u32,
+        pixels: &mut Vec<u8>
+    ) -> Result<(), String> {
+        let rows = rows.min(self.height - self.row);
+
+        match &mut self.source {
+            Source::Png(reader) => {
+                let color_type = reader.output_color_type().0;
+
+                for _ in 0..rows {
+                    let row = reader
+                        .next_row()
+                        .map_err(|e| format!("Unable to decode image: {e}"))?
+                        .ok_or_else(|| "The image ended early.".to_string())?;
+
+                    match color_type {
+                        png::ColorType::Grayscale => pixels.extend(row.data().iter().flat_map(|x| [*x, *x, *x, 255])),
+                        png::ColorType::GrayscaleAlpha => {
+                            pixels.extend(row.data().chunks_exact(2).flat_map(|x| [x[0], x[0], x[0], x[1]]))
+                        }
+                        png::ColorType::Rgb => {
+                            pixels.extend(row.data().chunks_exact(3).flat_map(|x| [x[0], x[1], x[2], 255]))
+                        }
+                        // Palettes are expanded to RGB or RGBA by the transformations.
+                        png::ColorType::Rgba | png::ColorType::Indexed => pixels.extend_from_slice(row.data()),
+                    }
+                }
+            }
+            Source::Decoded(decoded) => {
+                pixels.extend(decoded.crop_imm(0, self.row, self.width, rows).to_rgba8().into_raw());
+            }
+        }
+
+        self.row += rows;
+
+        Ok(())
+    }
+}
+
+
+/// Writes a PNG from the top, a strip at a time.
+#[cfg(feature = "image")]
+struct StripWriter {
+    writer: png::StreamWriter<'static, BufWriter<File>>,
+    has_alpha: bool,
+}
+
+#[cfg(feature = "image")]
+impl StripWriter {
+    fn create(
+        output: &Path,
+        (width, height): (u32, u32),
+        has_alpha: bool
+    ) -> Result<Self, String> {
+        let file = File::create(output).map_err(|e| format!("Unable to create {}: {e}", output.display()))?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(if has_alpha { png::ColorType::Rgba } else { png::ColorType::Rgb });
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let writer = encoder
+            .write_header()
+            .and_then(|x| x.into_stream_writer())
+            .map_err(|e| format!("Unable to write {}: {e}", output.display()))?;
+
+        Ok(Self {
+            writer,
+            has_alpha,
+        })
+    }
+
+    /// Writes whole rows of RGBA `pixels`.
+    fn write(&mut self, pixels: &[u8]) -> Result<(), String> {
+        let result = match self.has_alpha {
+            true => self.writer.write_all(pixels),
+            false => self.writer.write_all(
+                &pixels.chunks_exact(4).flat_map(|x| [x[0], x[1], x[2]]).collect::<Vec<u8>>()
+            ),
+        };
+
+        result.map_err(|e| format!("Unable to write image: {e}"))
+    }
+
+    fn finish(self) -> Result<(), String> {
+        self.writer.finish().map_err(|e| format!("Unable to write image: {e}"))
+    }
+}
+
+
+/// Runs `work` over `image` in horizontal strips of `rows` rows and streams
+/// the result to `output` as a PNG, so only a few strips are held at once
+/// however large the image is. PNGs are also read a row at a time, other
+/// formats are decoded whole.
+///
+/// Each strip is handed to `work` with up to `margin` rows of the image
+/// above and below it, for filters reading neighbouring pixels, and `work`
+/// returns it with the same size. The output keeps the image's alpha channel
+/// unless `opaque`.
+#[cfg(feature = "image")]
+pub fn map(
+    image: &Path,
+    output: &Path,
+    rows: u32,
+    margin: u32,
+    opaque: bool,
+    work: impl Fn(RgbaImage) -> RgbaImage
+) -> Result<(), String> {
+    let mut reader = StripReader::open(image)?;
+    let (width, height) = (reader.width, reader.height);
+    let row_bytes = width as usize * 4;
+    let rows = rows.max(1);
+
+    let mut writer = StripWriter::create(output, (width, height), reader.has_alpha && !opaque)?;
+
+    // Rows from `window_top` on, the strip being processed and its margins.
+    let mut window = Vec::new();
+    let mut window_top = 0;
+
+    for top in (0..height).step_by(rows as usize) {
+        let bottom = top.saturating_add(rows).min(height);
+        let first = top.saturating_sub(margin);
+        let last = bottom.saturating_add(margin).min(height);
+
+        window.drain(..(first - window_top) as usize * row_bytes);
+        window_top = first;
+
+        reader.read(last - reader.row, &mut window)?;
+
+        let window_rows = last - first;
+        let strip = RgbaImage::from_raw(width, window_rows, window.clone())
+            .ok_or_else(|| "Unable to split the image into strips.".to_string())?;
+
+        let processed = work(strip);
+        let offset = (top - window_top) as usize * row_bytes;
+
+        let pixels = processed
+            .as_raw()
+            .get(offset..offset + (bottom - top) as usize * row_bytes)
+            .filter(|_| processed.dimensions() == (width, window_rows))
+            .ok_or_else(|| "A processed strip changed its size.".to_string())?;
+
+        writer.write(pixels)?;
+    }
+
+    writer.finish()
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "image")]
+    #[test]
+    fn strips_come_out_like_the_whole_image() {
+        use image::Rgba;
+
+        use super::*;
+
+        let dir = std::env::temp_dir().join(format!("wallpaper_changer_strips_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let image = dir.join("day.png");
+        let whole = RgbaImage::from_fn(23, 17, |x, y| Rgba([(x * 11) as u8, (y * 15) as u8, ((x ^ y) * 9) as u8, 200]));
+        whole.save(&image).unwrap();
+
+        let blurred = image::imageops::blur(&whole, 1.5);
+
+        for rows in [1, 4, 17, 100] {
+            let output = dir.join(format!("strips_{rows}.png"));
+            map(&image, &output, rows, 5, false, |strip| image::imageops::blur(&strip, 1.5)).unwrap();
+
+            assert_eq!(image::open(output).unwrap().to_rgba8(), blurred, "{rows} rows");
+        }
+
+        // Unchanged strips give back the image, without alpha when opaque.
+        let output = dir.join("opaque.png");
+        map(&image, &output, 4, 0, true, |strip| strip).unwrap();
+        let opaque = image::open(output).unwrap();
+        assert!(!opaque.color().has_alpha());
+        assert_eq!(opaque.to_rgb8(), DynamicImage::ImageRgba8(whole).to_rgb8());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
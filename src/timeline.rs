@@ -0,0 +1,161 @@
+use crate::bench::format_duration;
+
+
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+const MIN_BAR_WIDTH: usize = 8;
+
+/// Upper bounds of the histogram buckets in seconds, with their labels.
+const HISTOGRAM_BUCKETS: [(i64, &str); 7] = [
+    (60, "< 1m"),
+    (5 * 60, "< 5m"),
+    (15 * 60, "< 15m"),
+    (60 * 60, "< 1h"),
+    (3 * 60 * 60, "< 3h"),
+    (6 * 60 * 60, "< 6h"),
+    (i64::MAX, ">= 6h"),
+];
+
+
+/// Width of the terminal from `COLUMNS`, falling back to 80 columns.
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_TERMINAL_WIDTH)
+}
+
+
+/// Writes `text` into `row` at `column`, cut short at `limit`.
+fn put(row: &mut [char], column: usize, text: &str, limit: usize) {
+    let limit = limit.min(row.len());
+
+    for (cell, x) in row[column..limit].iter_mut().zip(text.chars()) {
+        *cell = x;
+    }
+}
+
+
+/// Renders a day's schedule as a bar from `day_start` to `day_end` that fits
+/// in `width` columns. `timestamp_seq` holds the end of every slot and
+/// `phases` the phase name of every slot.
+///
+/// In the bar `#` marks the start of a phase and `|` any other image change.
+/// Below it every phase name starts under its `#`, cut short where the next
+/// phase begins. Changes closer together than a column share one marker.
+pub fn render(
+    day_start: i64,
+    day_end: i64,
+    timestamp_seq: &[i64],
+    phases: &[String],
+    width: usize
+) -> String {
+    let bar_width = width.saturating_sub(2).max(MIN_BAR_WIDTH);
+    let day_length = (day_end - day_start).max(1);
+
+    let column = |timestamp: i64| {
+        ((timestamp - day_start).clamp(0, day_length) as usize * bar_width / day_length as usize).min(bar_width - 1)
+    };
+
+    let mut ruler = vec![' '; bar_width];
+    let mut bar = vec!['-'; bar_width];
+    let mut names = vec![' '; bar_width];
+
+    let mut ruler_end = 0;
+    for hour in (0..=24).step_by(6) {
+        let label = format!("{hour:02}");
+        let start = (hour * bar_width / 24).min(bar_width - label.len());
+
+        // Skip labels that would run into the previous one on narrow terminals.
+        if start >= ruler_end {
+            put(&mut ruler, start, &label, bar_width);
+            ruler_end = start + label.len() + 1;
+        }
+    }
+
+    let mut phase_starts = vec![];
+    for (index, phase) in phases.iter().enumerate() {
+        let slot_start = match index {
+            0 => day_start,
+            _ => timestamp_seq[index - 1],
+        };
+
+        if index == 0 || phases[index - 1] != *phase {
+            bar[column(slot_start)] = '#';
+            phase_starts.push((column(slot_start), phase));
+        } else if bar[column(slot_start)] != '#' {
+            bar[column(slot_start)] = '|';
+        }
+    }
+
+    for (position, (start, phase)) in phase_starts.iter().enumerate() {
+        let limit = phase_starts
+            .get(position + 1)
+            .map(|(next, _)| next.saturating_sub(1))
+            .unwrap_or(bar_width);
+
+        if *start < limit {
+            put(&mut names, *start, phase, limit);
+        }
+    }
+
+    [ruler, bar, names]
+        .iter()
+        .map(|x| format!(" {}", x.iter().collect::<String>().trim_end()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+
+/// Renders how long each image of a day's schedule is shown as a bar chart
+/// of duration buckets, one bar per bucket. `slots` holds a label and the
+/// display time in seconds of every image.
+///
+/// Images shown for less than `poll_interval_seconds` count as `!` instead
+/// of `#` and are listed below the chart, a poll may skip right past them.
+pub fn histogram(
+    slots: &[(String, i64)],
+    poll_interval_seconds: i64,
+    width: usize
+) -> String {
+    let bar_width = width.saturating_sub(16).max(MIN_BAR_WIDTH);
+
+    let counts = HISTOGRAM_BUCKETS
+        .iter()
+        .enumerate()
+        .map(|(bucket, (upper, _))| {
+            let lower = bucket.checked_sub(1).map(|x| HISTOGRAM_BUCKETS[x].0).unwrap_or(i64::MIN);
+            let in_bucket = slots.iter().filter(|(_, x)| lower <= *x && x < upper);
+
+            let short = in_bucket.clone().filter(|(_, x)| *x < poll_interval_seconds).count();
+            (short, in_bucket.count() - short)
+        })
+        .collect::<Vec<(usize, usize)>>();
+
+    let largest = counts.iter().map(|(short, rest)| short + rest).max().unwrap_or_default().max(1);
+    let scale = |count: usize| match count {
+        0 => 0,
+        // Every bucket with images keeps at least one mark.
+        _ => (count * bar_width).div_ceil(largest.max(bar_width)).max(1),
+    };
+
+    let mut lines = HISTOGRAM_BUCKETS
+        .iter()
+        .zip(&counts)
+        .map(|((_, label), (short, rest))| {
+            let bar = "!".repeat(scale(*short)) + &"#".repeat(scale(*rest));
+            format!(" {label:<6} {:>4} {bar}", short + rest).trim_end().to_string()
+        })
+        .collect::<Vec<String>>();
+
+    let short_slots = slots
+        .iter()
+        .filter(|(_, x)| *x < poll_interval_seconds)
+        .collect::<Vec<&(String, i64)>>();
+
+    if !short_slots.is_empty() {
+        lines.push(format!(" Shown for less than the {poll_interval_seconds}s poll interval:"));
+        lines.extend(short_slots.iter().map(|(label, seconds)| format!("   {}  {label}", format_duration(*seconds))));
+    }
+
+    lines.join("\n")
+}
@@ -0,0 +1,206 @@
+use std::path::Path;
+#[cfg(feature = "image")]
+use std::{fs, thread, time};
+
+use serde::{Deserialize, Serialize};
+
+use crate::easing::Easing;
+
+
+fn default_crossfade_frames() -> u32 {
+    10
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "style", rename_all = "lowercase")]
+pub enum Transition {
+    #[default]
+    Instant,
+    /// Blends the previous wallpaper into the next one over `duration_seconds`.
+    /// Requires the `image` feature, otherwise it behaves like `Instant`.
+    Crossfade {
+        duration_seconds: u64,
+        #[serde(default = "default_crossfade_frames")]
+        frames: u32,
+        /// How the blend progresses from frame to frame.
+        #[serde(default)]
+        easing: Easing,
+    },
+}
+
+impl Transition {
+    /// How long the transition runs, 0 for instant ones.
+    pub fn duration_seconds(&self) -> u64 {
+        match self {
+            Transition::Instant => 0,
+            Transition::Crossfade { duration_seconds, .. } => *duration_seconds,
+        }
+    }
+}
+
+
+fn set_from_path(path: &Path) -> Result<(), String> {
+    let path = path
+        .to_str()
+        .ok_or("Unable to convert PathBuf to &str.")?;
+
+    wallpaper::set_from_path(path).map_err(|e| format!("Unable to set wallpaper {path}: {e}"))
+}
+
+
+#[cfg(feature = "image")]
+fn crossfade(
+    from: &Path,
+    to: &Path,
+    (duration_seconds, frames, easing): (u64, u32, Easing),
+    frames_dir: &Path,
+    set: &impl Fn(&Path) -> Result<(), String>
+) -> Result<(), String> {
+    use image::imageops::FilterType;
+
+    let to_image = image::open(to)
+        .map_err(|e| format!("Unable to open image {}: {e}", to.display()))?
+        .to_rgba8();
+    let from_image = image::open(from)
+        .map_err(|e| format!("Unable to open image {}: {e}", from.display()))?
+        .resize_exact(to_image.width(), to_image.height(), FilterType::Triangle)
+        .to_rgba8();
+
+    fs::create_dir_all(frames_dir)
+        .ok()
+        .ok_or("Unable to create transition frames directory.")?;
+
+    let frame_sleep = time::Duration::from_millis(duration_seconds * 1000 / frames as u64);
+
+    for frame in 1..frames {
+        let alpha = easing.apply(frame as f64 / frames as f64) as f32;
+
+        let mut blended = to_image.clone();
+        for (blended_pixel, from_pixel) in blended.pixels_mut().zip(from_image.pixels()) {
+            for channel in 0..4 {
+                blended_pixel[channel] = (from_pixel[channel] as f32 * (1.0 - alpha)
+                    + blended_pixel[channel] as f32 * alpha)
+                    .round() as u8;
+            }
+        }
+
+        // Alternate between two file names, some desktops ignore a set to the same path.
+        let frame_path = frames_dir.join(format!("transition_frame_{}.png", frame % 2));
+        blended
+            .save(&frame_path)
+            .map_err(|e| format!("Unable to save transition frame: {e}"))?;
+
+        set(&frame_path)?;
+        thread::sleep(frame_sleep);
+    }
+
+    set(to)
+}
+
+
+/// Warns when the configured transition can't be played by this build.
+pub fn warn_if_unsupported(transition: &Transition) {
+    if cfg!(not(feature = "image")) && matches!(transition, Transition::Crossfade { .. }) {
+        eprintln!("Crossfade transitions require the `image` feature, falling back to instant.");
+    }
+}
+
+
+/// `transition` shortened to `shortest_slot_seconds`, the shortest time an
+/// image of the schedule is shown, or `None` when it already fits. A longer
+/// one would still be blending when the next change starts.
+pub fn fit_to_slot(
+    transition: &Transition,
+    shortest_slot_seconds: i64
+) -> Option<Transition> {
+    let shortest_slot_seconds = shortest_slot_seconds.max(0) as u64;
+
+    match transition {
+        Transition::Crossfade { duration_seconds, frames, easing } if *duration_seconds > shortest_slot_seconds => {
+            Some(Transition::Crossfade { duration_seconds: shortest_slot_seconds, frames: *frames, easing: *easing })
+        }
+        _ => None,
+    }
+}
+
+
+/// Sets `to` as the wallpaper, transitioning from `from` when one is given.
+pub fn apply(
+    from: Option<&Path>,
+    to: &Path,
+    transition: &Transition,
+    frames_dir: &Path,
+) -> Result<(), String> {
+    apply_with(from, to, transition, frames_dir, set_from_path)
+}
+
+
+/// Like `apply`, but every frame and the final image are set with `set`, for
+/// wallpapers other than the desktop's, e.g. a single workspace's.
+#[cfg_attr(not(feature = "image"), allow(unused_variables))]
+pub fn apply_with(
+    from: Option<&Path>,
+    to: &Path,
+    transition: &Transition,
+    frames_dir: &Path,
+    set: impl Fn(&Path) -> Result<(), String>
+) -> Result<(), String> {
+    match (transition, from) {
+        #[cfg(feature = "image")]
+        (Transition::Crossfade { duration_seconds, frames, easing }, Some(from)) if *frames > 1 => {
+            crossfade(from, to, (*duration_seconds, *frames, *easing), frames_dir, &set).or_else(|e| {
+                eprintln!("Crossfade failed, setting wallpaper directly: {e}");
+                set(to)
+            })
+        }
+        _ => set(to),
+    }
+}
+
+
+/// Sets `from`, then plays `transition` to `to` right away, for tuning the
+/// duration and frame count. The rendered frames are removed afterwards and,
+/// with `restore`, the wallpaper from before the preview is set again once
+/// Enter is pressed.
+pub fn preview(
+    from: &Path,
+    to: &Path,
+    transition: &Transition,
+    frames_dir: &Path,
+    restore: bool
+) -> Result<(), String> {
+    for image in [from, to] {
+        if !image.is_file() {
+            return Err(format!("Image doesn't exist: {}", image.display()));
+        }
+    }
+
+    warn_if_unsupported(transition);
+
+    let previous = match restore {
+        true => Some(wallpaper::get().map_err(|e| format!("Unable to read the current wallpaper: {e}"))?),
+        false => None,
+    };
+
+    set_from_path(from)?;
+
+    let start = std::time::Instant::now();
+    let result = apply(Some(from), to, transition, frames_dir);
+
+    let _ = std::fs::remove_dir_all(frames_dir);
+    result?;
+
+    println!("Transition {transition:?} took {:.2?}.", start.elapsed());
+
+    if let Some(previous) = previous {
+        println!("Press Enter to restore the previous wallpaper.");
+
+        let _ = std::io::stdin().read_line(&mut String::new());
+
+        wallpaper::set_from_path(&previous)
+            .map_err(|e| format!("Unable to restore wallpaper {previous}: {e}"))?;
+    }
+
+    Ok(())
+}
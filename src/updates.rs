@@ -0,0 +1,166 @@
+#[cfg(feature = "network")]
+use std::collections::BTreeMap;
+#[cfg(feature = "network")]
+use std::fs;
+#[cfg(feature = "network")]
+use std::io::Read;
+#[cfg(feature = "network")]
+use std::path::{Component, Path};
+
+#[cfg(feature = "network")]
+use serde::Deserialize;
+#[cfg(feature = "network")]
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "network")]
+use crate::load_wallpaper_pack;
+
+
+/// What a pack's `update_manifest` points to, e.g.
+///
+/// ```toml
+/// base_url = "https://example.com/packs/mountains"
+///
+/// [images]
+/// "sunrise/01.png" = "<sha256 of the file>"
+/// ```
+///
+/// Images are downloaded from `base_url`, or from next to the manifest when
+/// it's not set.
+#[cfg(feature = "network")]
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Manifest {
+    base_url: Option<String>,
+    images: BTreeMap<String, String>,
+}
+
+
+#[cfg(feature = "network")]
+fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| format!("Unable to download {e}. Check the connection."))?;
+
+    let mut bytes = vec![];
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Unable to download {url}: {e}"))?;
+
+    Ok(bytes)
+}
+
+
+#[cfg(feature = "network")]
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|x| format!("{x:02x}"))
+        .collect()
+}
+
+
+/// Manifest paths have to stay inside the pack, a manifest can't overwrite
+/// anything else.
+#[cfg(feature = "network")]
+fn is_pack_relative(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|x| matches!(x, Component::Normal(_)))
+}
+
+
+/// Compares the images of `wallpaper_pack` against its `update_manifest`
+/// and reports the ones that are missing or differ, downloading them when
+/// `download` is set.
+#[cfg(feature = "network")]
+pub fn run(
+    wallpaper_packs_dir: &String,
+    wallpaper_pack: &String,
+    wallpaper_pack_config_name: &String,
+    download: bool
+) -> Result<(), String> {
+    let (wallpaper_pack_dir, wallpaper_pack_config) = load_wallpaper_pack(
+        wallpaper_packs_dir,
+        wallpaper_pack,
+        wallpaper_pack_config_name
+    )?;
+
+    let manifest_url = wallpaper_pack_config.update_manifest
+        .ok_or_else(|| format!("Pack '{wallpaper_pack}' has no update_manifest."))?;
+
+    let manifest: Manifest = toml::from_str(&String::from_utf8_lossy(&fetch(&manifest_url)?))
+        .map_err(|e| format!("Unable to parse the update manifest {manifest_url}: {e}"))?;
+
+    let base_url = manifest.base_url
+        .unwrap_or_else(|| manifest_url.rsplit_once('/').map(|(x, _)| x.to_string()).unwrap_or_default());
+
+    let mut outdated = 0;
+    let mut failed = 0;
+
+    for (image, expected) in &manifest.images {
+        if !is_pack_relative(image) {
+            eprintln!("  Skipping {image}, it's outside the pack.");
+            continue;
+        }
+
+        let image_path = Path::new(&wallpaper_pack_dir).join(image);
+
+        let status = match fs::read(&image_path) {
+            Ok(bytes) if sha256(&bytes).eq_ignore_ascii_case(expected) => continue,
+            Ok(_) => "changed",
+            Err(_) => "missing",
+        };
+
+        outdated += 1;
+        println!("  {status:<8} {image}");
+
+        if !download {
+            continue;
+        }
+
+        let url = format!("{}/{image}", base_url.trim_end_matches('/'));
+
+        // Written next to the image first, a failed download leaves it intact.
+        let result = fetch(&url).and_then(|bytes| {
+            if !sha256(&bytes).eq_ignore_ascii_case(expected) {
+                return Err(format!("{url} doesn't match the manifest's hash."));
+            }
+
+            let partial_path = image_path.with_extension("part");
+
+            fs::create_dir_all(image_path.parent().unwrap_or(Path::new("")))
+                .and_then(|_| fs::write(&partial_path, bytes))
+                .and_then(|_| fs::rename(&partial_path, &image_path))
+                .map_err(|e| format!("Unable to write {}: {e}", image_path.display()))
+        });
+
+        if let Err(e) = result {
+            eprintln!("  {e}");
+            failed += 1;
+        }
+    }
+
+    match (outdated, download) {
+        (0, _) => println!("Pack '{wallpaper_pack}' is up to date."),
+        (_, false) => println!("{outdated} image(s) of pack '{wallpaper_pack}' are out of date, run with --download to update them."),
+        (_, true) => println!("Updated {} of {outdated} image(s) of pack '{wallpaper_pack}'.", outdated - failed),
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{failed} image(s) failed to update.")),
+    }
+}
+
+
+#[cfg(not(feature = "network"))]
+pub fn run(
+    _wallpaper_packs_dir: &String,
+    _wallpaper_pack: &String,
+    _wallpaper_pack_config_name: &String,
+    _download: bool
+) -> Result<(), String> {
+    Err("Checking packs for updates requires the `network` feature.".to_string())
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::Path;
+
+use toml_edit::{value, Array, Document};
+
+use crate::load_wallpaper_pack;
+use crate::migration::CURRENT_PACK_VERSION;
+use crate::reorder::PHASES;
+
+
+/// Optional pack fields that have no default worth writing out.
+const OPTIONAL_FIELDS: [&str; 10] = [
+    "anchors",
+    "aspect_variants",
+    "default_image",
+    "effects",
+    "overcast_dir",
+    "phase_order",
+    "poll_interval_seconds",
+    "transition",
+    "transitions",
+    "update_manifest",
+];
+
+
+/// Brings a pack config up to the current format: missing phases are added
+/// as empty lists and fields with a default are written out, while existing
+/// values, their order and comments are kept. The previous file is saved next
+/// to it with a `.bak` extension.
+pub fn run(
+    wallpaper_packs_dir: &String,
+    wallpaper_pack: &String,
+    wallpaper_pack_config_name: &String
+) -> Result<(), String> {
+    // Refuses packs that wouldn't load anyway, upgrading can't fix those.
+    let (wallpaper_pack_dir, _) = load_wallpaper_pack(
+        wallpaper_packs_dir,
+        wallpaper_pack,
+        wallpaper_pack_config_name
+    )?;
+
+    let wallpaper_pack_config_path = Path::new(&wallpaper_pack_dir).join(wallpaper_pack_config_name);
+
+    let original = fs::read_to_string(&wallpaper_pack_config_path)
+        .ok()
+        .ok_or("Unable to read wallpaper_pack_config.toml file.")?;
+
+    let mut document = original
+        .parse::<Document>()
+        .map_err(|e| format!("Unable to parse wallpaper_pack_config.toml file: {e}"))?;
+
+    let version = document
+        .get("version")
+        .and_then(|x| x.as_integer())
+        .unwrap_or(1);
+
+    if version > CURRENT_PACK_VERSION {
+        return Err(format!(
+            "Pack version {version} is newer than the supported version {CURRENT_PACK_VERSION}."
+        ));
+    }
+
+    let mut added = vec![];
+
+    for phase in PHASES {
+        if !document.contains_key(phase) {
+            document[phase] = value(Array::new());
+            added.push(phase);
+        }
+    }
+
+    if !document.contains_key("night_behavior") {
+        document["night_behavior"] = value("loop");
+        added.push("night_behavior");
+    }
+
+    document["version"] = value(CURRENT_PACK_VERSION);
+
+    let upgraded = document.to_string();
+
+    if upgraded == original {
+        println!("Pack '{wallpaper_pack}' is already up to date.");
+        return Ok(());
+    }
+
+    let backup_path = wallpaper_pack_config_path.with_extension("toml.bak");
+
+    fs::write(&backup_path, &original)
+        .ok()
+        .ok_or("Unable to back up wallpaper_pack_config.toml file.")?;
+    fs::write(&wallpaper_pack_config_path, upgraded)
+        .ok()
+        .ok_or("Unable to write wallpaper_pack_config.toml file.")?;
+
+    println!(
+        "Upgraded pack '{wallpaper_pack}' from version {version} to {CURRENT_PACK_VERSION}, the old config is in {}",
+        backup_path.display()
+    );
+
+    if !added.is_empty() {
+        println!("  Added: {}", added.join(", "));
+    }
+
+    let unset = OPTIONAL_FIELDS
+        .into_iter()
+        .filter(|x| !document.contains_key(x))
+        .collect::<Vec<&str>>();
+
+    if !unset.is_empty() {
+        println!("  Optional, left unset: {}", unset.join(", "));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn older_packs_are_upgraded_keeping_their_values_and_comments() {
+        let wallpaper_packs_dir = std::env::temp_dir().join(format!("wallpaper_changer_upgrade_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&wallpaper_packs_dir);
+        fs::create_dir_all(wallpaper_packs_dir.join("old")).unwrap();
+
+        let config_path = wallpaper_packs_dir.join("old").join("wallpaper_pack_config.toml");
+        let original = "# Evenings by the lake\nsunset = [\"dusk.png\"] # golden\nnoon = [\"day.png\"]\n\n[anchors.golden_hour]\nat = \"sunset - 60m\"\nimages = [\"gold.png\"]\n";
+        fs::write(&config_path, original).unwrap();
+
+        let wallpaper_packs_dir = wallpaper_packs_dir.to_string_lossy().to_string();
+        let config_name = "wallpaper_pack_config.toml".to_string();
+
+        run(&wallpaper_packs_dir, &"old".to_string(), &config_name).unwrap();
+
+        let upgraded = fs::read_to_string(&config_path).unwrap();
+        assert!(upgraded.starts_with("# Evenings by the lake\nsunset = [\"dusk.png\"] # golden\nnoon = [\"day.png\"]\n"), "{upgraded}");
+        assert!(upgraded.contains("midnight = []") && upgraded.contains("night_behavior = \"loop\""), "{upgraded}");
+        assert!(upgraded.contains(&format!("version = {CURRENT_PACK_VERSION}")), "{upgraded}");
+        assert_eq!(fs::read_to_string(config_path.with_extension("toml.bak")).unwrap(), original);
+
+        let (_, wallpaper_pack_config) = load_wallpaper_pack(&wallpaper_packs_dir, &"old".to_string(), &config_name).unwrap();
+        assert_eq!(wallpaper_pack_config.anchors["golden_hour"].images.len(), 1);
+
+        // Upgrading again changes nothing, newer packs are left alone.
+        run(&wallpaper_packs_dir, &"old".to_string(), &config_name).unwrap();
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), upgraded);
+
+        fs::write(&config_path, format!("noon = [\"day.png\"]\nversion = {}", CURRENT_PACK_VERSION + 1)).unwrap();
+        assert!(run(&wallpaper_packs_dir, &"old".to_string(), &config_name).unwrap_err().contains("is newer than"));
+
+        fs::remove_dir_all(&wallpaper_packs_dir).unwrap();
+    }
+}
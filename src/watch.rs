@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "watch")]
+use std::sync::{Arc, Mutex};
+
+
+/// Editors write a file in several steps, a change is only reported once the
+/// file has been left alone for this long.
+#[cfg_attr(not(feature = "watch"), allow(dead_code))]
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+
+/// Paths touched since the last report and when the latest event came in.
+#[derive(Debug, Default)]
+#[cfg_attr(not(feature = "watch"), allow(dead_code))]
+struct PendingChanges {
+    paths: HashSet<PathBuf>,
+    last_event: Option<Instant>,
+}
+
+
+#[cfg_attr(not(feature = "watch"), allow(dead_code))]
+impl PendingChanges {
+    fn record(&mut self, paths: Vec<PathBuf>, now: Instant) {
+        self.paths.extend(paths);
+        self.last_event = Some(now);
+    }
+
+    /// The `files` touched since the last call, once no event came in for
+    /// `DEBOUNCE`.
+    fn settled(&mut self, files: &[PathBuf], now: Instant) -> Vec<PathBuf> {
+        match self.last_event {
+            Some(last_event) if now.duration_since(last_event) >= DEBOUNCE => (),
+            _ => return vec![],
+        }
+
+        self.last_event = None;
+
+        let changed = std::mem::take(&mut self.paths);
+
+        files
+            .iter()
+            .filter(|x| changed.contains(*x))
+            .cloned()
+            .collect()
+    }
+}
+
+
+/// Watches a set of files for changes. The directories holding them are
+/// watched rather than the files, so editors that save by replacing the file
+/// are noticed too.
+#[cfg(feature = "watch")]
+pub struct FileWatcher {
+    watcher: notify::RecommendedWatcher,
+    files: Vec<PathBuf>,
+    pending: Arc<Mutex<PendingChanges>>,
+}
+
+
+#[cfg(feature = "watch")]
+impl FileWatcher {
+    pub fn new() -> Result<Self, String> {
+        let pending = Arc::<Mutex<PendingChanges>>::default();
+        let event_pending = pending.clone();
+
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+
+            if event.kind.is_access() {
+                return;
+            }
+
+            if let Ok(mut pending) = event_pending.lock() {
+                pending.record(event.paths, Instant::now());
+            }
+        }).map_err(|e| format!("Unable to watch the config files: {e}"))?;
+
+        Ok(Self {
+            watcher,
+            files: vec![],
+            pending,
+        })
+    }
+
+    /// Replaces the watched files.
+    pub fn set_files(&mut self, files: Vec<PathBuf>) {
+        use notify::{RecursiveMode, Watcher};
+
+        let directories = |files: &Vec<PathBuf>| {
+            let mut directories = files
+                .iter()
+                .filter_map(|x| x.parent().map(|x| x.to_path_buf()))
+                .collect::<Vec<PathBuf>>();
+            directories.sort();
+            directories.dedup();
+            directories
+        };
+
+        for directory in directories(&self.files) {
+            let _ = self.watcher.unwatch(&directory);
+        }
+
+        for directory in directories(&files) {
+            if let Err(e) = self.watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                eprintln!("Unable to watch {}: {e}", directory.display());
+            }
+        }
+
+        self.files = files;
+    }
+
+    /// The watched files changed since the last call, once they have settled.
+    pub fn changes(&mut self) -> Vec<PathBuf> {
+        match self.pending.lock() {
+            Ok(mut pending) => pending.settled(&self.files, Instant::now()),
+            Err(_) => vec![],
+        }
+    }
+}
+
+
+#[cfg(not(feature = "watch"))]
+pub struct FileWatcher;
+
+
+#[cfg(not(feature = "watch"))]
+impl FileWatcher {
+    pub fn new() -> Result<Self, String> {
+        Err("Watching the config files requires the `watch` feature, reload through the control endpoint instead.".to_string())
+    }
+
+    pub fn set_files(&mut self, _files: Vec<PathBuf>) {}
+
+    pub fn changes(&mut self) -> Vec<PathBuf> {
+        vec![]
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn changes_are_reported_once_settled() {
+        let config = PathBuf::from("/config/wallpaper_changer_config.toml");
+        let pack_config = PathBuf::from("/packs/photos/wallpaper_pack_config.toml");
+        let files = vec![config.clone(), pack_config.clone()];
+        let start = Instant::now();
+        let mut pending = PendingChanges::default();
+
+        assert!(pending.settled(&files, start + DEBOUNCE).is_empty());
+
+        // An editor saving the config in two writes, next to an unwatched file.
+        pending.record(vec![config.clone(), PathBuf::from("/config/other.toml")], start);
+        pending.record(vec![config.clone()], start + DEBOUNCE / 2);
+
+        assert!(pending.settled(&files, start + DEBOUNCE).is_empty());
+        assert_eq!(pending.settled(&files, start + DEBOUNCE / 2 + DEBOUNCE), [config]);
+        assert!(pending.settled(&files, start + DEBOUNCE * 4).is_empty());
+
+        pending.record(vec![pack_config.clone()], start + DEBOUNCE * 4);
+
+        assert_eq!(pending.settled(&files, start + DEBOUNCE * 5), [pack_config]);
+    }
+}
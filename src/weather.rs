@@ -0,0 +1,192 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "image")]
+use crate::orientation;
+use crate::remote;
+
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// Cloud cover is rounded to this many steps, so a new blend is only
+/// rendered when the sky changes noticeably.
+const BLEND_STEPS: f64 = 10.0;
+
+
+#[cfg(feature = "network")]
+fn fetch_cloud_cover(
+    latitude: f64,
+    longitude: f64
+) -> Result<f64, String> {
+    let url = format!(
+        "https://api.open-meteo.com/v1/forecast?latitude={latitude}&longitude={longitude}&current=cloud_cover"
+    );
+
+    let response = ureq::get(&url)
+        .call()
+        .map_err(|e| format!("Unable to fetch the weather: {e}"))?
+        .into_string()
+        .map_err(|e| format!("Unable to read the weather: {e}"))?;
+
+    serde_json::from_str::<serde_json::Value>(&response)
+        .ok()
+        .and_then(|x| x["current"]["cloud_cover"].as_f64())
+        .map(|x| (x / 100.0).clamp(0.0, 1.0))
+        .ok_or_else(|| "Unable to parse the weather response.".to_string())
+}
+
+
+#[cfg(not(feature = "network"))]
+fn fetch_cloud_cover(
+    _latitude: f64,
+    _longitude: f64
+) -> Result<f64, String> {
+    Err("Weather awareness requires the `network` feature.".to_string())
+}
+
+
+/// Cloud cover at the configured location, refreshed every half hour, and the
+/// image last picked for it.
+#[derive(Debug, Default)]
+pub struct Weather {
+    cloud_cover: Option<f64>,
+    fetched: Option<Instant>,
+    last_selection: Option<(PathBuf, f64, PathBuf)>,
+}
+
+impl Weather {
+    /// Cloud cover from 0.0 (clear) to 1.0 (overcast), `None` until the
+    /// first successful fetch. A failed refresh keeps the previous value.
+    fn cloud_cover(&mut self, latitude: f64, longitude: f64) -> Option<f64> {
+        if self.fetched.is_some_and(|x| x.elapsed() < REFRESH_INTERVAL) {
+            return self.cloud_cover;
+        }
+
+        self.fetched = Some(Instant::now());
+
+        match fetch_cloud_cover(latitude, longitude) {
+            Ok(cloud_cover) => self.cloud_cover = Some(cloud_cover),
+            Err(e) => eprintln!("{e}"),
+        }
+
+        self.cloud_cover
+    }
+
+    /// The image to show instead of `clear` under the current sky, see
+    /// `weather_image`. The clear image is kept until the weather is known.
+    pub fn select_image(
+        &mut self,
+        clear: &Path,
+        overcast: &Path,
+        latitude: f64,
+        longitude: f64,
+        blend_dir: &Path
+    ) -> PathBuf {
+        let Some(cloud_cover) = self.cloud_cover(latitude, longitude) else {
+            return clear.to_path_buf();
+        };
+
+        let alpha = (cloud_cover * BLEND_STEPS).round() / BLEND_STEPS;
+
+        if let Some((last_clear, last_alpha, selected)) = &self.last_selection {
+            if last_clear == clear && *last_alpha == alpha {
+                return selected.clone();
+            }
+        }
+
+        let selected = weather_image(clear, overcast, alpha, blend_dir);
+        self.last_selection = Some((clear.to_path_buf(), alpha, selected.clone()));
+
+        selected
+    }
+}
+
+
+/// Mixes `overcast` into `clear` by `alpha`, also used to blend packs.
+#[cfg(feature = "image")]
+pub fn blend(
+    clear: &Path,
+    overcast: &Path,
+    alpha: f64,
+    output: &Path
+) -> Result<(), String> {
+    use image::imageops::FilterType;
+
+    let mut blended = orientation::open(clear)?.to_rgba8();
+    let overcast_image = orientation::open(overcast)?
+        .resize_exact(blended.width(), blended.height(), FilterType::Triangle)
+        .to_rgba8();
+
+    for (blended_pixel, overcast_pixel) in blended.pixels_mut().zip(overcast_image.pixels()) {
+        for channel in 0..4 {
+            blended_pixel[channel] = (blended_pixel[channel] as f64 * (1.0 - alpha)
+                + overcast_pixel[channel] as f64 * alpha)
+                .round() as u8;
+        }
+    }
+
+    std::fs::create_dir_all(output.parent().unwrap_or(Path::new("")))
+        .ok()
+        .ok_or("Unable to create blend directory.")?;
+
+    blended
+        .save(output)
+        .map_err(|e| format!("Unable to save blend: {e}"))
+}
+
+
+#[cfg(not(feature = "image"))]
+pub fn blend(
+    _clear: &Path,
+    _overcast: &Path,
+    _alpha: f64,
+    _output: &Path
+) -> Result<(), String> {
+    Err("Blending images requires the `image` feature.".to_string())
+}
+
+
+/// Picks what to show for `clear` at the rounded cloud cover `alpha`: the
+/// clear image itself when the overcast variant is missing, otherwise both
+/// blended by the cloud cover. When blending isn't possible the closer of the
+/// two is used as is.
+///
+/// Blends are named after the image and the cloud cover, so the same sky maps
+/// to the same file and is only rendered once.
+fn weather_image(
+    clear: &Path,
+    overcast: &Path,
+    alpha: f64,
+    blend_dir: &Path
+) -> PathBuf {
+    if !overcast.is_file() {
+        return clear.to_path_buf();
+    }
+
+    match alpha {
+        x if x <= 0.0 => return clear.to_path_buf(),
+        x if x >= 1.0 => return overcast.to_path_buf(),
+        _ => (),
+    }
+
+    let output = blend_dir.join(format!(
+        "{}_{:02}.png",
+        remote::url_key(&clear.to_string_lossy()),
+        (alpha * BLEND_STEPS) as u32
+    ));
+
+    if output.is_file() {
+        return output;
+    }
+
+    if cfg!(not(feature = "image")) {
+        return if alpha < 0.5 { clear.to_path_buf() } else { overcast.to_path_buf() };
+    }
+
+    match blend(clear, overcast, alpha, &output) {
+        Ok(_) => output,
+        Err(e) => {
+            eprintln!("{e}");
+            if alpha < 0.5 { clear.to_path_buf() } else { overcast.to_path_buf() }
+        }
+    }
+}
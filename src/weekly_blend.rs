@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    load_wallpaper_pack,
+    remote,
+    resolve_pack_entries,
+    weather,
+    PhaseSpan,
+    WallpaperChangerConfig,
+    WallpaperPackConfig,
+};
+
+
+/// The weight is rounded to this many steps, so a new blend is rendered
+/// about twice a day rather than on every poll.
+const BLEND_STEPS: f64 = 12.0;
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendCurve {
+    /// The same shift every day.
+    #[default]
+    Linear,
+    /// Slow around Monday and Sunday, fastest midweek.
+    Smooth,
+}
+
+
+/// Shifts the active pack into `pack` over the week: the active pack's images
+/// on Monday, gradually more of `pack`'s until only those show on Sunday.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WeeklyBlend {
+    pub pack: String,
+    #[serde(default)]
+    pub curve: BlendCurve,
+}
+
+
+/// How much of the blend pack shows at `time`, from 0.0 at the start of
+/// Monday to 1.0 at the start of Sunday, kept through Sunday.
+pub fn weight(
+    curve: BlendCurve,
+    time: NaiveDateTime
+) -> f64 {
+    let day = time.weekday().num_days_from_monday() as f64 + time.num_seconds_from_midnight() as f64 / 86400.0;
+    let progress = (day / 6.0).clamp(0.0, 1.0);
+
+    let weight = match curve {
+        BlendCurve::Linear => progress,
+        BlendCurve::Smooth => progress * progress * (3.0 - 2.0 * progress),
+    };
+
+    (weight * BLEND_STEPS).round() / BLEND_STEPS
+}
+
+
+/// The pack blended in, with its entries resolved like the active pack's.
+pub struct BlendPack {
+    dir: String,
+    config: WallpaperPackConfig,
+}
+
+impl BlendPack {
+    pub fn load(
+        weekly_blend: &WeeklyBlend,
+        wallpaper_packs_dir: &String,
+        wallpaper_pack_config_name: &String,
+        remote_cache_dir: &Path,
+        config: &WallpaperChangerConfig
+    ) -> Result<Self, String> {
+        let (dir, pack_config) = load_wallpaper_pack(wallpaper_packs_dir, &weekly_blend.pack, wallpaper_pack_config_name)
+            .map_err(|e| format!("Unable to load the weekly blend pack: {e}"))?;
+        let config = resolve_pack_entries(&pack_config, &dir, remote_cache_dir, config)?;
+
+        Ok(Self { dir, config })
+    }
+
+    /// The image of this pack in the place of the slot at `index` of the
+    /// active pack's schedule: the same phase or anchor, the same position.
+    fn corresponding_image(
+        &self,
+        phases: &[PhaseSpan],
+        index: usize
+    ) -> Option<PathBuf> {
+        let phase = phases.iter().find(|x| x.slots.contains(&index))?;
+
+        phase
+            .images(&self.config)
+            .get(index - phase.slots.start)
+            .map(|x| Path::new(&self.dir).join(x.path()))
+    }
+
+    /// `image`, the slot at `index` of the active pack, blended with this
+    /// pack's corresponding image by `weight`. Without one, or when it's
+    /// missing on disk, `image` is shown as it is.
+    ///
+    /// Blends are named after both images and the weight, so each is rendered
+    /// once.
+    pub fn blended_image(
+        &self,
+        image: &Path,
+        phases: &[PhaseSpan],
+        index: usize,
+        weight: f64,
+        blend_dir: &Path
+    ) -> PathBuf {
+        let Some(blend_image) = self
+            .corresponding_image(phases, index)
+            .filter(|x| x.is_file())
+        else {
+            return image.to_path_buf();
+        };
+
+        match weight {
+            x if x <= 0.0 => return image.to_path_buf(),
+            x if x >= 1.0 => return blend_image,
+            _ => (),
+        }
+
+        let output = blend_dir.join(format!(
+            "{}_{:02}.png",
+            remote::url_key(&format!("{}\n{}", image.display(), blend_image.display())),
+            (weight * BLEND_STEPS).round() as u32
+        ));
+
+        if output.is_file() {
+            return output;
+        }
+
+        match weather::blend(image, &blend_image, weight, &output) {
+            Ok(_) => output,
+            Err(e) => {
+                eprintln!("{e}");
+                image.to_path_buf()
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use super::*;
+
+
+    #[test]
+    fn the_weight_grows_over_the_week() {
+        // A Monday.
+        let monday = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let at = |hours: i64| monday + Duration::hours(hours);
+
+        assert_eq!(weight(BlendCurve::Linear, at(0)), 0.0);
+        assert_eq!(weight(BlendCurve::Linear, at(36)), 0.25);
+        assert_eq!(weight(BlendCurve::Linear, at(72)), 0.5);
+        assert_eq!(weight(BlendCurve::Linear, at(144)), 1.0);
+        assert_eq!(weight(BlendCurve::Linear, at(167)), 1.0);
+
+        // Slower at the start and the end of the week, the same midweek.
+        assert_eq!(weight(BlendCurve::Smooth, at(0)), 0.0);
+        assert_eq!(weight(BlendCurve::Smooth, at(36)), 2.0 / 12.0);
+        assert_eq!(weight(BlendCurve::Smooth, at(72)), 0.5);
+        assert_eq!(weight(BlendCurve::Smooth, at(108)), 10.0 / 12.0);
+        assert_eq!(weight(BlendCurve::Smooth, at(144)), 1.0);
+
+        for curve in [BlendCurve::Linear, BlendCurve::Smooth] {
+            let weights = (0..168).map(|x| weight(curve, at(x))).collect::<Vec<f64>>();
+
+            assert!(weights.windows(2).all(|x| x[0] <= x[1]), "{curve:?}");
+            // Rendered again about twice a day.
+            assert!(weights.windows(2).filter(|x| x[0] != x[1]).count() <= 12, "{curve:?}");
+        }
+
+        // The next Monday starts over.
+        assert_eq!(weight(BlendCurve::Linear, at(168)), 0.0);
+    }
+}
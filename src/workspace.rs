@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::WallpaperChangerConfig;
+
+
+#[cfg(feature = "xfce-workspaces")]
+fn xfconf_query(args: &[&str]) -> Result<String, String> {
+    let output = std::process::Command::new("xfconf-query")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Unable to run xfconf-query: {e}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "xfconf-query {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+
+/// Whether wallpapers can be set per workspace, only XFCE exposes that.
+#[cfg(feature = "xfce-workspaces")]
+pub fn supported() -> bool {
+    std::env::var("XDG_CURRENT_DESKTOP").is_ok_and(|x| x.to_uppercase().contains("XFCE"))
+        && xfconf_query(&["-c", "xfce4-desktop", "-l"]).is_ok()
+}
+
+
+#[cfg(not(feature = "xfce-workspaces"))]
+pub fn supported() -> bool {
+    false
+}
+
+
+/// Workspace names in order, as shown by the window manager.
+#[cfg(feature = "xfce-workspaces")]
+fn workspace_names() -> Vec<String> {
+    // The array is printed after a header and a blank line, one item per line.
+    xfconf_query(&["-c", "xfwm4", "-p", "/general/workspace_names"])
+        .map(|x| {
+            x.lines()
+                .skip_while(|line| !line.trim().is_empty())
+                .skip(1)
+                .map(|line| line.trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+
+#[cfg(not(feature = "xfce-workspaces"))]
+fn workspace_names() -> Vec<String> {
+    vec![]
+}
+
+
+#[cfg(feature = "xfce-workspaces")]
+pub fn set_workspace_wallpaper(workspace: usize, image: &Path) -> Result<(), String> {
+    let suffix = format!("/workspace{workspace}/last-image");
+
+    let properties = xfconf_query(&["-c", "xfce4-desktop", "-l"])?
+        .lines()
+        .filter(|x| x.starts_with("/backdrop/") && x.ends_with(&suffix))
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>();
+
+    if properties.is_empty() {
+        return Err(format!("XFCE has no backdrop for workspace {}.", workspace + 1));
+    }
+
+    for property in properties {
+        xfconf_query(&["-c", "xfce4-desktop", "-p", &property, "-s", &image.to_string_lossy()])?;
+    }
+
+    Ok(())
+}
+
+
+#[cfg(not(feature = "xfce-workspaces"))]
+pub fn set_workspace_wallpaper(_workspace: usize, _image: &Path) -> Result<(), String> {
+    Err("Per-workspace wallpapers require the `xfce-workspaces` feature.".to_string())
+}
+
+
+/// Zero based index of a workspace given by its number, counting from 1, or
+/// by its name.
+fn workspace_index(workspace: &str, names: &[String]) -> Option<usize> {
+    match workspace.parse::<usize>() {
+        Ok(number) if number > 0 => Some(number - 1),
+        _ => names.iter().position(|x| x == workspace),
+    }
+}
+
+
+/// The configured workspaces by their zero based index, with their pack.
+/// Turns off XFCE's single workspace mode, so each gets its own wallpaper.
+pub fn workspaces(config: &WallpaperChangerConfig) -> Result<BTreeMap<usize, String>, String> {
+    #[cfg(feature = "xfce-workspaces")]
+    xfconf_query(&["-c", "xfce4-desktop", "-p", "/backdrop/single-workspace-mode", "-s", "false"])?;
+
+    let names = workspace_names();
+    let mut workspaces = BTreeMap::new();
+
+    for (workspace, pack) in &config.workspace_packs {
+        match workspace_index(workspace, &names) {
+            Some(index) => {
+                workspaces.insert(index, pack.clone());
+            }
+            None => eprintln!("There is no workspace '{workspace}', its pack is not used."),
+        }
+    }
+
+    Ok(workspaces)
+}